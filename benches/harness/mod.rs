@@ -0,0 +1,85 @@
+// Shared setup of the criterion benchmarks, so that a benchmark of a program
+// or of a single pass is a one-liner in `suite.rs`.
+use std::fs;
+use std::io;
+
+use binter::{optimize_program, Interpreter, OptimizerConfig, Program};
+use criterion::{black_box, BatchSize, Criterion, Throughput};
+
+pub const TAPE_SIZE: usize = 30000;
+
+// Source of a program from `tests/programs`, e.g. `program("sierpinski")`.
+pub fn program(name: &str) -> String {
+    let path = format!("{}/tests/programs/{name}.b", env!("CARGO_MANIFEST_DIR"));
+    fs::read_to_string(&path).unwrap_or_else(|error| panic!("{path}: {error}"))
+}
+
+// A config with only the passes enabled by `enable` turned on, e.g.
+// `only(|config| config.fold = true)`.
+pub fn only(enable: impl FnOnce(&mut OptimizerConfig)) -> OptimizerConfig {
+    let mut config = OptimizerConfig {
+        fold: false,
+        remove_dead_loops: false,
+        fuse_clears: false,
+        ..Default::default()
+    };
+    enable(&mut config);
+    config
+}
+
+pub fn bench_parse(c: &mut Criterion, name: &str, code: &str) {
+    c.bench_function(&format!("parse/{name}"), |b| {
+        b.iter(|| Program::from_reader(black_box(code.as_bytes())).unwrap())
+    });
+}
+
+// Times parsing the code, reporting the throughput in commands (tokens) per
+// second. The lexer isn't exposed on its own, but it does most of the work
+// of the parsing.
+pub fn bench_lex(c: &mut Criterion, name: &str, code: &str) {
+    let commands = code
+        .bytes()
+        .filter(|byte| b"+-<>[].,".contains(byte))
+        .count();
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Elements(commands as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| Program::from_reader(black_box(code.as_bytes())).unwrap())
+    });
+    group.finish();
+}
+
+// Times a full optimization of the code with a given config, without the
+// parsing.
+pub fn bench_optimize(c: &mut Criterion, name: &str, code: &str, config: OptimizerConfig) {
+    let program = Program::from_reader(code.as_bytes()).unwrap();
+    c.bench_function(&format!("optimize/{name}"), |b| {
+        b.iter_batched(
+            || program.clone(),
+            |program| optimize_program(program, config.clone()),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+// Times parsing and running the code, optimized with a given amount of
+// iterations (`Some(0)` meaning a full optimization) or not at all. The
+// output goes to a sink and the input is empty, so no terminal is involved.
+pub fn bench_run(c: &mut Criterion, name: &str, code: &str, optimization: Option<u32>) {
+    let label = match optimization {
+        Some(iterations) => format!("run/{name} (-O {iterations})"),
+        None => format!("run/{name}"),
+    };
+    c.bench_function(&label, |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::from_reader(code.as_bytes(), TAPE_SIZE)
+                .with_input(Box::new(io::empty()))
+                .with_output(Box::new(io::sink()));
+            match optimization {
+                Some(iterations) => interpreter.run_with_optimization(iterations),
+                None => interpreter.run(),
+            }
+            .unwrap()
+        })
+    });
+}
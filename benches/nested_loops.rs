@@ -0,0 +1,63 @@
+// Times the interpreter on nested loop heavy code and counts the allocations
+// made while parsing and running it. Run with `cargo bench`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use binter::{Interpreter, Termination};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Counts down from 255 in four nested loops, touching a few cells on every
+// iteration, similarly to the inner loops of a mandelbrot renderer.
+fn nested_loops() -> String {
+    let mut code = String::from("-[>-[>+++++[>+>++<<-]>>[-]<[-]<<-]<-]");
+    code.insert_str(0, ">++++++++[<++++++++>-]<");
+    code.repeat(4)
+}
+
+// Many small loops, so that parsing and cloning the code dominate.
+fn many_loops() -> String {
+    "+[>[-]<-]>[<+>-]<[->+<]".repeat(2000)
+}
+
+fn bench(name: &str, code: &str, optimize: bool) {
+    let runs = 5;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..runs {
+        let mut interpreter =
+            Interpreter::from_reader(code.as_bytes(), 1000).with_output(Box::new(io::sink()));
+        let termination = match optimize {
+            false => interpreter.run().unwrap(),
+            true => interpreter.run_with_optimization(0).unwrap(),
+        };
+        assert_eq!(termination, Termination::Completed);
+    }
+    let elapsed = start.elapsed() / runs;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / runs as usize;
+    println!("{name:<24} {elapsed:>12.2?} {allocations:>10} allocations");
+}
+
+fn main() {
+    bench("nested loops", &nested_loops(), false);
+    bench("nested loops (-O 0)", &nested_loops(), true);
+    bench("many loops", &many_loops(), false);
+    bench("many loops (-O 0)", &many_loops(), true);
+}
@@ -0,0 +1,65 @@
+// Criterion benchmarks of the hot paths of the interpreter: parsing,
+// optimizing and running. Run with `cargo bench --bench suite`, optionally
+// followed by a filter, e.g. `cargo bench --bench suite -- optimize/`.
+mod harness;
+
+use binter::OptimizerConfig;
+use criterion::{criterion_group, criterion_main, Criterion};
+use harness::{bench_lex, bench_optimize, bench_parse, bench_run, only, program};
+
+// About 2.5 MB of loop heavy code interleaved with comments.
+fn large_program() -> String {
+    "+[>[-]<-]>[<+>-]<[->+<] moves the value back and forth\n\
+     >>[-]>[-]<<< clears two cells\n"
+        .repeat(30_000)
+}
+
+// About 10 MB of commented code, a quarter of it being commands.
+fn commented_program() -> String {
+    "+++[>++<-]> doubles the counter into the next cell\n\
+     [-]<<[->+<] then moves the first one over\n"
+        .repeat(110_000)
+}
+
+// Counts down 16 * 255 * 255 times, doing nothing else.
+const COUNTING_LOOP: &str = "++++++++++++++++[>-[>-[>+<-]<-]<-]";
+
+fn parsing(c: &mut Criterion) {
+    // the lexer isn't exposed on its own, but with nothing but comments it
+    // dominates the parsing time
+    bench_parse(c, "comments only", &"no commands here\n".repeat(150_000));
+    bench_parse(c, "large program", &large_program());
+    bench_lex(c, "10 MB, commented", &commented_program());
+}
+
+fn optimizing(c: &mut Criterion) {
+    let code = large_program();
+    bench_optimize(c, "large program", &code, OptimizerConfig::default());
+    bench_optimize(c, "large program, fold", &code, only(|c| c.fold = true));
+    bench_optimize(
+        c,
+        "large program, remove dead loops",
+        &code,
+        only(|c| c.remove_dead_loops = true),
+    );
+    bench_optimize(
+        c,
+        "large program, fuse clears",
+        &code,
+        only(|c| c.fuse_clears = true),
+    );
+}
+
+fn running(c: &mut Criterion) {
+    for optimization in [None, Some(1), Some(0)] {
+        bench_run(c, "counting loop", COUNTING_LOOP, optimization);
+        bench_run(c, "sierpinski", &program("sierpinski"), optimization);
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = parsing, optimizing, running
+}
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+// Runs a program on a scripted input and captures its output instead of
+// writing it to the standard output.
+use std::cell::RefCell;
+use std::io::{self, Cursor, Write};
+use std::rc::Rc;
+
+use binter::{EofPolicy, Interpreter};
+
+// Turns the input into uppercase, up to the end of the input.
+const UPPERCASE: &str = ",[>++++[<-------->-]<.[-],]";
+
+// A writer whose clones share the buffer, so that the output can be read
+// after the run.
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn main() -> io::Result<()> {
+    let output = Captured::default();
+    let mut interpreter = Interpreter::from_reader(UPPERCASE.as_bytes(), 16)
+        // every `,` takes a byte of the input, the terminal is left alone
+        .with_input(Box::new(Cursor::new("shout")))
+        .with_output(Box::new(output.clone()))
+        // the end of the input sets the cell to zero, ending the loop
+        .with_eof_policy(EofPolicy::Zero);
+    let termination = interpreter.run()?;
+    let captured = output.0.take();
+    println!("{:?}: {}", termination, String::from_utf8_lossy(&captured));
+    Ok(())
+}
@@ -0,0 +1,20 @@
+// Optimizes a program and prints its statements before and after, along
+// with the statistics of the optimization.
+use std::io;
+
+use binter::{optimize_program, OptimizerConfig, Program};
+
+// Clears three cells in a loop and moves the pointer around.
+const CODE: &str = ",[[-]>[-]>>[-]<<<,]>>+<<->+<";
+
+fn main() -> io::Result<()> {
+    let program: Program = CODE.parse()?;
+    println!("before:\n{program}");
+    let (optimized, stats) = optimize_program(program, OptimizerConfig::default());
+    println!("after:\n{optimized}");
+    println!(
+        "{} statements, {} after {} iterations",
+        stats.statements_before, stats.statements_after, stats.iterations
+    );
+    Ok(())
+}
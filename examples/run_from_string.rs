@@ -0,0 +1,16 @@
+// Runs a brainfuck program given as a string, printing its output.
+use std::io;
+
+use binter::{Interpreter, Termination};
+
+// Prints "Hello World!" followed by a newline.
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.\
+                     <-.<.+++.------.--------.>>+.>++.";
+
+fn main() -> io::Result<()> {
+    // any `BufRead` implementor can hold the code, e.g. a byte slice
+    let mut interpreter = Interpreter::from_reader(HELLO.as_bytes(), 30000);
+    // the program doesn't read any input, so it always runs to the end
+    assert_eq!(interpreter.run()?, Termination::Completed);
+    Ok(())
+}
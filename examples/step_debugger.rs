@@ -0,0 +1,17 @@
+// Steps through a program, printing the tape around the pointer before
+// every step, like a debugger would.
+use std::io;
+
+use binter::{Interpreter, Termination};
+
+// Moves the value of the first cell to the second one.
+const CODE: &str = "++[->+<]";
+
+fn main() -> io::Result<()> {
+    let mut interpreter = Interpreter::from_reader(CODE.as_bytes(), 4)
+        // a frame of 4 cells around the pointer for every single step
+        .with_tape_trace(Box::new(io::stdout()), 1, 4);
+    assert_eq!(interpreter.run()?, Termination::Completed);
+    println!("done after {} steps", interpreter.stats().steps);
+    Ok(())
+}
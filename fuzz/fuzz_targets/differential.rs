@@ -0,0 +1,74 @@
+#![no_main]
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use binter::{Interpreter, Termination};
+use libfuzzer_sys::fuzz_target;
+
+// a writer sharing its buffer, so that the output can be read after a run
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Keeps only the commands, dropping the unmatched `]` and closing the loops
+// left open, so that every input is valid code.
+fn repair(data: &[u8]) -> String {
+    let mut code = String::new();
+    let mut open = 0;
+    for &byte in data {
+        match byte {
+            b'[' => open += 1,
+            b']' if open == 0 => continue,
+            b']' => open -= 1,
+            b'+' | b'-' | b'<' | b'>' | b'.' | b',' => {}
+            _ => continue,
+        }
+        code.push(byte as char);
+    }
+    code.push_str(&"]".repeat(open));
+    code
+}
+
+fn run(code: &str, optimize: bool) -> (io::Result<Termination>, Vec<u8>, Vec<u8>) {
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 64)
+        .with_input(Box::new(&b"fuzz"[..]))
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(10_000);
+    let result = match optimize {
+        false => interpreter.run(),
+        true => interpreter.run_with_optimization(0),
+    };
+    let tape = interpreter.get_tape();
+    (result, output.0.take(), tape)
+}
+
+// The optimized code behaves exactly like the parsed one. Runs stopped by
+// the step limit aren't compared, as the optimized code takes fewer steps.
+fuzz_target!(|data: &[u8]| {
+    let code = repair(data);
+    let (result, output, tape) = run(&code, false);
+    let (optimized_result, optimized_output, optimized_tape) = run(&code, true);
+    match (result, optimized_result) {
+        (Ok(Termination::StepLimit), _) | (_, Ok(Termination::StepLimit)) => {}
+        (Ok(termination), Ok(optimized_termination)) => {
+            assert_eq!(termination, optimized_termination, "code: {code}");
+            assert_eq!(output, optimized_output, "code: {code}");
+            assert_eq!(tape, optimized_tape, "code: {code}");
+        }
+        (Err(_), Err(_)) => assert_eq!(output, optimized_output, "code: {code}"),
+        (result, optimized_result) => {
+            panic!("code: {code}, results: {result:?} and {optimized_result:?}")
+        }
+    }
+});
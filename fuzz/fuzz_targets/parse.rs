@@ -0,0 +1,11 @@
+#![no_main]
+
+use binter::Program;
+use libfuzzer_sys::fuzz_target;
+
+// Parsing arbitrary bytes never panics, malformed code is only an error.
+fuzz_target!(|data: &[u8]| {
+    let _ = Program::from_reader(data);
+    let _ = Program::from_reader_partial(data);
+    let _ = Program::from_reader_with_comments(data);
+});
@@ -0,0 +1,83 @@
+use crate::{Program, SideEffectKind, Statement};
+
+// writes the byte pointed to by `%rsi` to the standard output
+const WRITE: &str = "    mov $1, %eax\n    mov $1, %edi\n    mov $1, %edx\n    syscall\n";
+// reads a byte of the standard input to where `%rsi` points
+const READ: &str = "    xor %eax, %eax\n    xor %edi, %edi\n    mov $1, %edx\n    syscall\n";
+// advances the SplitMix64 state kept in `random` and writes the low byte
+// of the result to where `%rsi` points
+const RANDOM: &str = "    movabs $0x9e3779b97f4a7c15, %rax\n    add random(%rip), %rax\n    \
+    mov %rax, random(%rip)\n    mov %rax, %rdx\n    shr $30, %rdx\n    xor %rdx, %rax\n    \
+    movabs $0xbf58476d1ce4e5b9, %rdx\n    imul %rdx, %rax\n    mov %rax, %rdx\n    \
+    shr $27, %rdx\n    xor %rdx, %rax\n    movabs $0x94d049bb133111eb, %rdx\n    \
+    imul %rdx, %rax\n    mov %rax, %rdx\n    shr $31, %rdx\n    xor %rdx, %rax\n    \
+    mov %al, (%rsi)\n";
+
+/// Translates a program to x86-64 assembly for Linux, in the GNU
+/// assembler syntax. The result is a whole program with a `_start`
+/// symbol, ready to be assembled with `as` and linked with `ld`.
+///
+/// The tape of `size` cells is zeroed static memory and the pointer is
+/// kept in `%rsi`, the cells being addressed relative to it. Folded
+/// additions and moves become single instructions, and the IO is done
+/// with a `read` or `write` syscall per byte. Reading past the end of
+/// the input leaves the cell unchanged, as with the default
+/// [`EofPolicy`](crate::EofPolicy). Unlike the interpreter, the code
+/// doesn't check the pointer: moving it off the tape is undefined
+/// behavior. Custom side effects are ignored, and the random bytes are
+/// those of the interpreter's generator seeded with zero.
+pub fn to_asm_x86_64(program: &Program, size: usize) -> String {
+    let mut asm = String::from("    .text\n    .globl _start\n_start:\n");
+    asm.push_str("    lea tape(%rip), %rsi\n");
+    write_statements(&mut asm, &program.statements, size, &mut 0);
+    asm.push_str("    mov $60, %eax\n    xor %edi, %edi\n    syscall\n");
+    asm.push_str("\n    .section .rodata\nbell:\n    .byte 7\n");
+    asm.push_str("\n    .data\nrandom:\n    .quad 0\n");
+    asm.push_str(&format!("\n    .bss\n    .lcomm tape, {}\n", size));
+    asm
+}
+
+// Writes the instructions of the statements, numbering the loops with
+// `loops` so that each one gets unique labels.
+fn write_statements(asm: &mut String, statements: &[Statement], size: usize, loops: &mut usize) {
+    for statement in statements {
+        match statement {
+            Statement::Add(value) => asm.push_str(&format!("    addb ${}, (%rsi)\n", value)),
+            Statement::MoveRight(value) => asm.push_str(&format!("    add ${}, %rsi\n", value)),
+            Statement::MoveLeft(value) => asm.push_str(&format!("    sub ${}, %rsi\n", value)),
+            Statement::ZeroCells(offsets) => {
+                for offset in offsets {
+                    asm.push_str(&format!("    movb $0, {}(%rsi)\n", offset));
+                }
+            }
+            Statement::FillValue { from, to, value } => {
+                for offset in *from..*to {
+                    asm.push_str(&format!("    movb ${}, {}(%rsi)\n", value, offset));
+                }
+            }
+            Statement::LoadTapeSize => asm.push_str(&format!("    movb ${}, (%rsi)\n", size % 256)),
+            Statement::PutChar => asm.push_str(WRITE),
+            Statement::ReadChar => asm.push_str(READ),
+            Statement::Random => asm.push_str(RANDOM),
+            // the bell is written from read-only memory, the pointer is
+            // kept on the stack meanwhile
+            Statement::SideEffect(SideEffectKind::Bell) => {
+                asm.push_str("    push %rsi\n    lea bell(%rip), %rsi\n");
+                asm.push_str(WRITE);
+                asm.push_str("    pop %rsi\n");
+            }
+            Statement::SideEffect(SideEffectKind::Custom(_)) | Statement::Comment(_) => {}
+            Statement::Loop(code) => {
+                let label = *loops;
+                *loops += 1;
+                asm.push_str(&format!(
+                    "    cmpb $0, (%rsi)\n    je .Lend{label}\n.Lloop{label}:\n"
+                ));
+                write_statements(asm, code, size, loops);
+                asm.push_str(&format!(
+                    "    cmpb $0, (%rsi)\n    jne .Lloop{label}\n.Lend{label}:\n"
+                ));
+            }
+        }
+    }
+}
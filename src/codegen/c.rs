@@ -0,0 +1,86 @@
+use crate::{Program, SideEffectKind, Statement};
+
+// the interpreter's SplitMix64 generator, only written out if used
+const C_RANDOM: &str = "static unsigned long long random_state;
+
+static unsigned char next_random(void) {
+    unsigned long long z = random_state += 0x9e3779b97f4a7c15ULL;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9ULL;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebULL;
+    return (unsigned char)(z ^ (z >> 31));
+}
+
+";
+
+/// Translates statements to a C program, to be compiled with any C99
+/// compiler. The tape of `tape_size` cells is a zeroed static array and
+/// the pointer is `p`, the cells being addressed relative to it. Folded
+/// additions and moves become single statements, loops become `while`
+/// loops and the IO is done with `getchar` and `putchar`, the output
+/// being flushed before every read. As in
+/// [`to_asm_x86_64`](super::to_asm_x86_64), reading past the end of the
+/// input leaves the cell unchanged, the pointer isn't checked, custom side
+/// effects are ignored and the random bytes are those of the interpreter's
+/// generator seeded with zero.
+pub fn emit_c(statements: &[Statement], tape_size: usize) -> String {
+    let mut c = String::from("#include <stdio.h>\n#include <string.h>\n\n");
+    c.push_str(&format!("static unsigned char tape[{}];\n\n", tape_size));
+    if uses_random(statements) {
+        c.push_str(C_RANDOM);
+    }
+    c.push_str("int main(void) {\n    unsigned char *p = tape;\n");
+    if Statement::reads_input(statements) {
+        c.push_str("    int c;\n");
+    }
+    write_c_statements(&mut c, statements, tape_size, 1);
+    c.push_str("    return 0;\n}\n");
+    c
+}
+
+/// Translates a program to a C program, see [`emit_c`].
+pub fn to_c(program: &Program, size: usize) -> String {
+    emit_c(&program.statements, size)
+}
+
+fn uses_random(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Random => true,
+        Statement::Loop(code) => uses_random(code),
+        _ => false,
+    })
+}
+
+// Writes the C statements, indented by `depth` levels.
+fn write_c_statements(c: &mut String, statements: &[Statement], size: usize, depth: usize) {
+    let indent = "    ".repeat(depth);
+    for statement in statements {
+        let line = match statement {
+            Statement::Add(value) => format!("*p += {};", value),
+            Statement::MoveRight(value) => format!("p += {};", value),
+            Statement::MoveLeft(value) => format!("p -= {};", value),
+            Statement::ZeroCells(offsets) => offsets
+                .iter()
+                .map(|offset| format!("p[{}] = 0;", offset))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Statement::FillValue { from, to, value } => {
+                format!("memset(p + {}, {}, {});", from, value, to - from)
+            }
+            Statement::LoadTapeSize => format!("*p = {};", size % 256),
+            Statement::PutChar => "putchar(*p);".to_string(),
+            Statement::ReadChar => {
+                "fflush(stdout); if ((c = getchar()) != EOF) *p = c;".to_string()
+            }
+            Statement::Random => "*p = next_random();".to_string(),
+            Statement::SideEffect(SideEffectKind::Bell) => "putchar(7);".to_string(),
+            Statement::SideEffect(SideEffectKind::Custom(_)) | Statement::Comment(_) => continue,
+            Statement::Loop(code) => {
+                c.push_str(&format!("{indent}while (*p) {{\n"));
+                write_c_statements(c, code, size, depth + 1);
+                c.push_str(&format!("{indent}}}\n"));
+                continue;
+            }
+        };
+        c.push_str(&format!("{indent}{line}\n"));
+    }
+}
@@ -0,0 +1,7 @@
+//! Translation of programs to native code.
+
+mod asm;
+mod c;
+
+pub use asm::to_asm_x86_64;
+pub use c::{emit_c, to_c};
@@ -1,16 +1,28 @@
 //! This module exports brainfuck machine and interpreter implementations.
 #![warn(missing_docs)]
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{
+    self, BufRead, BufReader, Error, ErrorKind, IsTerminal, LineWriter, Read, Result, Write,
+};
+use std::ops::{ControlFlow, Deref, DerefMut, Range};
 use std::os::unix::io::AsRawFd;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 enum Token {
     // post-lexing, pre-optimization tokens
     Increment,
@@ -22,492 +34,4193 @@ enum Token {
     // io tokens
     PutChar,
     ReadChar,
+    // dialect tokens
+    SideEffect(SideEffectKind),
+    LoadTapeSize,
+    Random,
+    // comments, only kept when asked for, as raw bytes in whatever encoding
+    // the code was written in
+    Comment(Vec<u8>),
 }
 
-#[derive(Clone, PartialEq, Debug)]
-enum Statement {
+// The command of the token, or a word for the dialect tokens and comments.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Increment => write!(f, "+"),
+            Token::Decrement => write!(f, "-"),
+            Token::ShiftLeft => write!(f, "<"),
+            Token::ShiftRight => write!(f, ">"),
+            Token::StartLoop => write!(f, "["),
+            Token::EndLoop => write!(f, "]"),
+            Token::PutChar => write!(f, "."),
+            Token::ReadChar => write!(f, ","),
+            Token::SideEffect(kind) => write_side_effect(kind, f),
+            Token::LoadTapeSize => write!(f, "size"),
+            Token::Random => write!(f, "rand"),
+            Token::Comment(text) => write!(f, "\"{}\"", text.escape_ascii()),
+        }
+    }
+}
+
+// shared by the tokens and the statements
+fn write_side_effect(kind: &SideEffectKind, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match kind {
+        SideEffectKind::Bell => write!(f, "bell"),
+        SideEffectKind::Custom(value) => write!(f, "custom {value}"),
+    }
+}
+
+/// A statement of parsed brainfuck code, as seen by the custom optimization
+/// passes (see [`OptimizerConfig::add_custom_pass`]).
+#[derive(Clone, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Statement {
+    /// Moves the pointer left by a given amount of cells.
     MoveLeft(usize),
+    /// Moves the pointer right by a given amount of cells.
     MoveRight(usize),
 
+    /// Adds a given value to the current cell, with wrapping.
     Add(u8),
 
-    Loop(Box<Vec<Statement>>),
+    /// Runs the statements while the current cell isn't zero.
+    Loop(Vec<Statement>),
+    /// Outputs the current cell.
     PutChar,
+    /// Reads a byte of input into the current cell.
     ReadChar,
+    /// Triggers a dialect side effect.
+    SideEffect(SideEffectKind),
+    /// Writes the tape size into the current cell.
+    LoadTapeSize,
+    /// Writes the next byte of the random generator into the current cell,
+    /// see [`Interpreter::with_random_command`].
+    Random,
+    /// A comment, kept byte for byte.
+    Comment(Vec<u8>),
+
+    // post-optimization statements
+    /// Sets the cells at given offsets from the pointer to zero, without
+    /// moving the pointer.
+    ZeroCells(Vec<isize>),
+    /// Sets a region of cells to a given value, without moving the pointer.
+    FillValue {
+        /// Offset of the first cell of the region from the pointer.
+        from: isize,
+        /// Offset of the cell right after the region from the pointer.
+        to: isize,
+        /// The value written to every cell of the region.
+        value: u8,
+    },
 }
 
 impl Statement {
     fn is_equal_type(&self, other: &Self) -> bool {
         std::mem::discriminant(self) == std::mem::discriminant(other)
     }
-    fn is_move(&self) -> bool {
-        matches!(self, &(Statement::MoveLeft(_) | Statement::MoveRight(_)))
-    }
     fn new_loop(statements: Vec<Statement>) -> Self {
-        Self::Loop(Box::new(statements))
+        Self::Loop(statements)
     }
-}
-
-/// This struct is used as an implementation of a brainfuck-compatible
-/// Turing-like machine that supports basic operations needed for such
-/// compilations. This machine works under an assumption that chars can be
-/// converted into [`u8`] freely through ASCII decoding and encoding.
-pub struct BrainfuckMachine {
-    /// Size of the tape vector.
-    size: usize,
-    /// Current cell index.
-    index: usize,
-    /// Tape vector.
-    tape: Vec<u8>,
-}
-
-impl BrainfuckMachine {
-    /// Creates a `BrainfuckMachine` instance of given tape size.
-    pub fn new(size: usize) -> Self {
-        let mut result = Self {
-            size,
-            index: 0,
-            tape: Vec::new(),
-        };
-        result.tape.resize(size, 0);
-        result
+    // A loop that always terminates with the current cell set to zero, i.e.
+    // `[-]` or an equivalent loop adding an odd value.
+    fn is_clear_loop(&self) -> bool {
+        self.add_loop_value().is_some_and(|value| value % 2 == 1)
     }
-
-    /// Moves the header left by a given amount. Panics when the index is out
-    /// of bounds.
-    pub fn move_left(&mut self, shift: usize) {
-        match shift.cmp(&(self.index)) {
-            Ordering::Greater => panic!(
-                "Index out of bounds.
-Index before move: {}.
-Left shift value: {}.
-",
-                self.index, shift,
-            ),
-            _ => self.index -= shift,
+    // The value added by a loop that only adds to the current cell, e.g. `2`
+    // for `[++]`.
+    fn add_loop_value(&self) -> Option<u8> {
+        match self {
+            Statement::Loop(code) => match code[..] {
+                [Statement::Add(value)] => Some(value),
+                _ => None,
+            },
+            _ => None,
         }
     }
-    /// Moves the header right by a given amount. Panics when the index is out
-    /// of bounds.
-    pub fn move_right(&mut self, shift: usize) {
-        match shift.cmp(&(self.size - self.index)) {
-            Ordering::Greater => panic!(
-                "Index out of bounds.
-Index before move: {}.
-Right shift value: {}.
-Max possible index: {}.
-",
-                self.index,
-                shift,
-                self.size - 1
+    // Whether a position saved in a `Checkpoint` points into the code: every
+    // index but the last one has to be a loop.
+    fn is_valid_pc(statements: &[Statement], pc: &[usize]) -> bool {
+        match pc {
+            [] => false,
+            [position] => *position <= statements.len(),
+            [position, inner @ ..] => matches!(
+                statements.get(*position),
+                Some(Statement::Loop(code)) if Self::is_valid_pc(code, inner)
             ),
-            _ => self.index += shift,
         }
     }
-
-    /// Adds a given value to the current cell, with wrapping.
-    pub fn add(&mut self, value: u8) {
-        let current = self.tape[self.index];
-        self.tape[self.index] = current.wrapping_add(value);
+    // Whether a loop only moves the pointer and brings it back to where it
+    // started, e.g. `[>><<]`, so its guard never changes.
+    fn is_balanced_scan(&self) -> bool {
+        match self {
+            Statement::Loop(code) => {
+                let mut offset: isize = 0;
+                for statement in code {
+                    match statement {
+                        Statement::MoveLeft(value) => offset -= *value as isize,
+                        Statement::MoveRight(value) => offset += *value as isize,
+                        _ => return false,
+                    }
+                }
+                offset == 0
+            }
+            _ => false,
+        }
     }
-
-    /// Substracts a given value to the current cell, with wrapping.
-    pub fn substract(&mut self, value: u8) {
-        let current = self.tape[self.index];
-        self.tape[self.index] = current.wrapping_sub(value);
+    // The amounts added to the guard of a loop by an iteration, in order,
+    // if the loop's body is straight-line code bringing the pointer back to
+    // where it started and only changing the guard through additions, e.g.
+    // `[1, 255]` for `[+>+<-]`. The list is empty when the guard never
+    // changes, e.g. for `[>><<]`.
+    fn guard_deltas(&self) -> Option<Vec<u8>> {
+        let Statement::Loop(code) = self else {
+            return None;
+        };
+        let mut offset: isize = 0;
+        let mut deltas = Vec::new();
+        for statement in code {
+            match statement {
+                Statement::MoveLeft(value) => offset -= *value as isize,
+                Statement::MoveRight(value) => offset += *value as isize,
+                Statement::Add(value) if offset == 0 => deltas.push(*value),
+                Statement::Add(_) | Statement::PutChar | Statement::Comment(_) => {}
+                // the guard is at `-offset` relative to the pointer
+                Statement::ZeroCells(offsets) if !offsets.contains(&-offset) => {}
+                Statement::FillValue { from, to, .. } if !(*from..*to).contains(&-offset) => {}
+                _ => return None,
+            }
+        }
+        (offset == 0).then_some(deltas)
     }
-
-    /// Inserts a given char's ASCII value into the current cell.
-    pub fn read_char(&mut self, input: char) {
-        self.tape[self.index] = input as u8
+    // Amount of iterations of a loop adding a given value to the current
+    // cell, entered with the cell set to `start`. With wrapping, the loop
+    // ends iff `start` is a multiple of gcd(value, 256), otherwise it never
+    // does and `None` is returned.
+    fn add_loop_trips(value: u8, start: u8) -> Option<u32> {
+        if start == 0 {
+            return Some(0);
+        }
+        if value == 0 {
+            return None;
+        }
+        // gcd(value, 256) is the largest power of two dividing the value
+        let gcd = 1u32 << value.trailing_zeros();
+        if !(start as u32).is_multiple_of(gcd) {
+            return None;
+        }
+        // solve (value / gcd) * trips = (256 - start) / gcd (mod 256 / gcd),
+        // where value / gcd is odd, so it has an inverse
+        let modulus = 256 / gcd;
+        let odd = value as u32 / gcd;
+        let mut inverse = odd;
+        // every step doubles the amount of correct low bits
+        for _ in 0..3 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(odd.wrapping_mul(inverse)));
+        }
+        let target = (256 - start as u32) / gcd;
+        Some(target * (inverse % modulus) % modulus)
     }
-
-    /// Returns the current cell's value ASCII encoded into a char.
-    pub fn put_char(&self) -> char {
-        self.tape[self.index] as char
+    // Net pointer movement of a block of statements, `None` if it depends on
+    // the data, i.e. the block contains a loop that moves the pointer.
+    fn net_offset(statements: &[Statement]) -> Option<isize> {
+        let mut offset: isize = 0;
+        for statement in statements {
+            match statement {
+                Statement::MoveLeft(value) => offset -= *value as isize,
+                Statement::MoveRight(value) => offset += *value as isize,
+                Statement::Loop(code) if Self::net_offset(code)? != 0 => return None,
+                _ => {}
+            }
+        }
+        Some(offset)
+    }
+    /// Returns the offsets of the cells read and written by a straight-line
+    /// block of statements, relative to the cell that the pointer starts at.
+    /// Adding to a cell both reads and writes it. Returns `None` if the
+    /// block contains a loop or performs input or output.
+    pub fn touched_offsets(block: &[Statement]) -> Option<(BTreeSet<isize>, BTreeSet<isize>)> {
+        let mut offset: isize = 0;
+        let (mut read, mut written) = (BTreeSet::new(), BTreeSet::new());
+        for statement in block {
+            match statement {
+                Statement::MoveLeft(value) => offset -= *value as isize,
+                Statement::MoveRight(value) => offset += *value as isize,
+                Statement::Add(0) | Statement::Comment(_) => {}
+                Statement::Add(_) => {
+                    read.insert(offset);
+                    written.insert(offset);
+                }
+                Statement::ZeroCells(offsets) => {
+                    written.extend(offsets.iter().map(|cleared| cleared + offset))
+                }
+                Statement::FillValue { from, to, .. } => written.extend(from + offset..to + offset),
+                Statement::LoadTapeSize | Statement::Random => {
+                    written.insert(offset);
+                }
+                Statement::Loop(_)
+                | Statement::PutChar
+                | Statement::ReadChar
+                | Statement::SideEffect(_) => return None,
+            }
+        }
+        Some((read, written))
+    }
+    fn max_nesting(statements: &[Statement]) -> usize {
+        statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Loop(code) => Self::max_nesting(code) + 1,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+    // Amount of source commands a statement was parsed from, only meaningful
+    // for unoptimized code.
+    fn token_count(&self) -> usize {
+        match self {
+            Statement::Loop(code) => code.iter().map(Self::token_count).sum::<usize>() + 2,
+            Statement::Comment(_) => 0,
+            _ => 1,
+        }
+    }
+    // Index of the command the statement at a given path of nested indices
+    // was parsed from.
+    fn token_index(statements: &[Statement], path: &[usize]) -> usize {
+        let Some((&position, rest)) = path.split_first() else {
+            return 0;
+        };
+        let before: usize = statements[..position].iter().map(Self::token_count).sum();
+        match (&statements[position], rest.is_empty()) {
+            (Statement::Loop(code), false) => before + 1 + Self::token_index(code, rest),
+            _ => before,
+        }
+    }
+    // Writes a listing of the statements for `Display`, indented by a given
+    // loop depth.
+    fn write_ir(statements: &[Statement], depth: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = "    ".repeat(depth);
+        for statement in statements {
+            write!(f, "{indent}")?;
+            match statement {
+                Statement::MoveLeft(value) => writeln!(f, "move -{value}")?,
+                Statement::MoveRight(value) => writeln!(f, "move +{value}")?,
+                Statement::Add(value) => writeln!(f, "add {:+}", *value as i8)?,
+                Statement::PutChar => writeln!(f, "output")?,
+                Statement::ReadChar => writeln!(f, "input")?,
+                Statement::SideEffect(kind) => writeln!(f, "side effect {kind:?}")?,
+                Statement::LoadTapeSize => writeln!(f, "load tape size")?,
+                Statement::Random => writeln!(f, "random")?,
+                Statement::Comment(text) => writeln!(f, "comment \"{}\"", text.escape_ascii())?,
+                Statement::ZeroCells(offsets) => writeln!(f, "zero {offsets:?}")?,
+                Statement::FillValue { from, to, value } => {
+                    writeln!(f, "fill {from}..{to} with {value}")?
+                }
+                Statement::Loop(code) => {
+                    writeln!(f, "loop")?;
+                    Self::write_ir(code, depth + 1, f)?;
+                    writeln!(f, "{indent}end")?;
+                }
+            }
+        }
+        Ok(())
     }
 
-    /// Returns `true` if the current cell's value is non-zero.
-    pub fn check_loop(&self) -> bool {
-        self.tape[self.index] != 0
+    fn write_source(statements: &[Statement], source: &mut Vec<u8>) {
+        for statement in statements {
+            match statement {
+                Statement::MoveLeft(value) => source.extend(b"<".repeat(*value)),
+                Statement::MoveRight(value) => source.extend(b">".repeat(*value)),
+                Statement::Add(value) => Self::write_add(*value, source),
+                Statement::Loop(code) => {
+                    source.push(b'[');
+                    Self::write_source(code, source);
+                    source.push(b']');
+                }
+                Statement::PutChar => source.push(b'.'),
+                Statement::ReadChar => source.push(b','),
+                Statement::SideEffect(SideEffectKind::Custom(command)) => source.push(*command),
+                // these aren't tied to any particular command
+                Statement::SideEffect(SideEffectKind::Bell)
+                | Statement::LoadTapeSize
+                | Statement::Random => {}
+                Statement::Comment(text) => source.extend_from_slice(text),
+                Statement::ZeroCells(offsets) => {
+                    let mut current: isize = 0;
+                    for offset in offsets {
+                        Self::write_move(offset - current, source);
+                        source.extend_from_slice(b"[-]");
+                        current = *offset;
+                    }
+                    Self::write_move(-current, source);
+                }
+                Statement::FillValue { from, to, value } => {
+                    Self::write_move(*from, source);
+                    for offset in *from..*to {
+                        source.extend_from_slice(b"[-]");
+                        Self::write_add(*value, source);
+                        if offset + 1 < *to {
+                            source.push(b'>');
+                        }
+                    }
+                    Self::write_move(1 - to, source);
+                }
+            }
+        }
+    }
+    fn write_add(value: u8, source: &mut Vec<u8>) {
+        match value <= 128 {
+            true => source.extend(b"+".repeat(value as usize)),
+            false => source.extend(b"-".repeat(256 - value as usize)),
+        }
+    }
+    fn write_move(shift: isize, source: &mut Vec<u8>) {
+        match shift < 0 {
+            true => source.extend(b"<".repeat(shift.unsigned_abs())),
+            false => source.extend(b">".repeat(shift as usize)),
+        }
+    }
+    // Amount of statements, including the ones nested in loops.
+    fn count(statements: &[Statement]) -> usize {
+        statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Loop(code) => Self::count(code) + 1,
+                _ => 1,
+            })
+            .sum()
+    }
+    fn performs_io(statements: &[Statement]) -> bool {
+        statements.iter().any(|statement| match statement {
+            Statement::PutChar | Statement::ReadChar | Statement::SideEffect(_) => true,
+            Statement::Loop(code) => Self::performs_io(code),
+            _ => false,
+        })
+    }
+    fn reads_input(statements: &[Statement]) -> bool {
+        statements.iter().any(|statement| match statement {
+            Statement::ReadChar => true,
+            Statement::Loop(code) => Self::reads_input(code),
+            _ => false,
+        })
+    }
+    // Whether the output of the statements only depends on their input,
+    // i.e. they don't use the environment otherwise (see `uses_environment`)
+    // or call a side effect, which a memoized run would skip.
+    fn is_pure(statements: &[Statement]) -> bool {
+        statements.iter().all(|statement| match statement {
+            Statement::Random | Statement::LoadTapeSize | Statement::SideEffect(_) => false,
+            Statement::Loop(code) => Self::is_pure(code),
+            _ => true,
+        })
     }
+    // Whether the statements use anything besides the code itself: the
+    // input, the random generator, the tape size or a side effect handler.
+    fn uses_environment(statements: &[Statement]) -> bool {
+        statements.iter().any(|statement| match statement {
+            Statement::ReadChar
+            | Statement::Random
+            | Statement::LoadTapeSize
+            | Statement::SideEffect(SideEffectKind::Custom(_)) => true,
+            Statement::Loop(code) => Self::uses_environment(code),
+            _ => false,
+        })
+    }
+}
 
-    /// Returns a copy of the vector representing the tape.
-    fn get_tape(&self) -> Vec<u8> {
-        self.tape.clone()
+/// Writes the statement in a compact notation, e.g. `+5` or `-1` for an
+/// addition, `>3` for a move and `[-1 >1 +1 <1]` for a loop. The optimized
+/// statements are written as `0[-1, 2]` for the cleared offsets and
+/// `3[1..4]` for a region filled with a value.
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::MoveLeft(value) => write!(f, "<{value}"),
+            Statement::MoveRight(value) => write!(f, ">{value}"),
+            Statement::Add(value) => write!(f, "{:+}", *value as i8),
+            Statement::Loop(code) => {
+                write!(f, "[")?;
+                for (index, statement) in code.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{statement}")?;
+                }
+                write!(f, "]")
+            }
+            Statement::PutChar => write!(f, "."),
+            Statement::ReadChar => write!(f, ","),
+            Statement::SideEffect(kind) => write_side_effect(kind, f),
+            Statement::LoadTapeSize => write!(f, "size"),
+            Statement::Random => write!(f, "rand"),
+            Statement::Comment(text) => write!(f, "\"{}\"", text.escape_ascii()),
+            Statement::ZeroCells(offsets) => write!(f, "0{offsets:?}"),
+            Statement::FillValue { from, to, value } => write!(f, "{value}[{from}..{to}]"),
+        }
     }
 }
 
-// Brainfuck grammar:
-// code := (stmt_block)*
-//
-// stmt_block := stmt | loop
-//
-// loop := '[' stmt_block+ ']'
-//
-// stmt := '+' | '-' | '<' | '>' | ',' | '.'
-struct Lexer<T: BufRead> {
-    reader: T,
+/// What the additions past the range of a [`Cell`] do.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum CellOverflow {
+    /// The cell wraps around, e.g. `255 + 1` is `0` in a byte.
+    #[default]
+    Wrap,
+    /// The cell stops at its bounds, e.g. `255 + 1` stays `255` and `0 - 1`
+    /// stays `0` in a byte. A loop like `[+]` then never terminates.
+    Saturate,
 }
 
-impl<T: BufRead> Lexer<T> {
-    fn next_token(&mut self) -> Option<Token> {
-        let mut buf: [u8; 1] = [0];
-        match self.reader.read(&mut buf) {
-            Err(msg) => {
-                panic!("Error when reading a token: {}", msg);
+/// The value of a cell of a [`Tape`]. Implemented for [`u8`], the usual
+/// brainfuck cell, and for [`u16`] and [`u32`], for the programs expecting
+/// wider cells (e.g. bignum arithmetic). The cells wrap around unless the
+/// [`CellOverflow`] says otherwise.
+///
+/// The input and the output stay bytes: a byte read is stored as is, and
+/// only the lowest byte of a cell is written out. The amount of a
+/// [`Statement::Add`] is a delta, i.e. the amounts above 127 subtract.
+pub trait Cell: Copy + Default + Eq + fmt::Debug + fmt::Display {
+    /// Width of the cell, in bits.
+    const BITS: u32;
+    /// The largest value of the cell.
+    const MAX: Self;
+    /// Converts a byte into a cell.
+    fn from_byte(byte: u8) -> Self;
+    /// Returns the lowest byte of the cell.
+    fn to_byte(self) -> u8;
+    /// Converts the amount of a [`Statement::Add`] into the value added to
+    /// a cell, sign-extending it.
+    fn from_delta(delta: u8) -> Self;
+    /// Converts a number into a cell, wrapping it around.
+    fn from_usize(value: usize) -> Self;
+    /// Adds two cells, wrapping around.
+    fn wrapping_add(self, other: Self) -> Self;
+    /// Subtracts two cells, wrapping around.
+    fn wrapping_sub(self, other: Self) -> Self;
+    /// Adds two cells, stopping at [`Cell::MAX`].
+    fn saturating_add(self, other: Self) -> Self;
+    /// Subtracts two cells, stopping at zero.
+    fn saturating_sub(self, other: Self) -> Self;
+    /// Returns `true` if the cell is zero.
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+}
+
+macro_rules! impl_cell {
+    ($($cell:ty),*) => {$(
+        impl Cell for $cell {
+            const BITS: u32 = <$cell>::BITS;
+            const MAX: Self = <$cell>::MAX;
+            fn from_byte(byte: u8) -> Self {
+                byte.into()
             }
-            Ok(0) => None,
-            Ok(_) => {
-                let ascii = buf[0];
-                let to_token = ascii as char;
-                Self::tokenize(&to_token)
+            fn to_byte(self) -> u8 {
+                self as u8
             }
-        }
-    }
-    fn eof(&mut self) -> bool {
-        match self.reader.fill_buf() {
-            Ok(buf) => buf.is_empty(),
-            Err(msg) => {
-                panic!("EOF check failed: {}", msg);
+            fn from_delta(delta: u8) -> Self {
+                delta as i8 as Self
+            }
+            fn from_usize(value: usize) -> Self {
+                value as Self
+            }
+            fn wrapping_add(self, other: Self) -> Self {
+                <$cell>::wrapping_add(self, other)
+            }
+            fn wrapping_sub(self, other: Self) -> Self {
+                <$cell>::wrapping_sub(self, other)
+            }
+            fn saturating_add(self, other: Self) -> Self {
+                <$cell>::saturating_add(self, other)
+            }
+            fn saturating_sub(self, other: Self) -> Self {
+                <$cell>::saturating_sub(self, other)
             }
         }
-    }
-    fn tokenize(input: &char) -> Option<Token> {
-        use crate::Token::*;
+    )*};
+}
 
-        match input {
-            '+' => Some(Increment),
-            '-' => Some(Decrement),
-            '<' => Some(ShiftLeft),
-            '>' => Some(ShiftRight),
-            ',' => Some(ReadChar),
-            '.' => Some(PutChar),
-            '[' => Some(StartLoop),
-            ']' => Some(EndLoop),
-            _ => None,
+impl_cell!(u8, u16, u32);
+
+/// The tape of a [`BrainfuckMachine`], a fixed number of cells accessed by
+/// their indices. Implementing it lets the tape live anywhere, e.g. in a
+/// memory-mapped file, while the machine keeps the pointer and the bounds
+/// checks. The machine never accesses a cell at an index past
+/// [`Tape::len`], so implementors may panic on such indices.
+pub trait Tape {
+    /// The type of the cells, see [`Cell`].
+    type Cell: Cell;
+    /// Returns the value of the cell at a given index.
+    fn get(&self, index: usize) -> Self::Cell;
+    /// Sets the value of the cell at a given index.
+    fn set(&mut self, index: usize, value: Self::Cell);
+    /// Returns the number of cells, which must only change through
+    /// [`Tape::grow`] and [`Tape::grow_front`].
+    fn len(&self) -> usize;
+    /// Returns `true` if the tape has no cells.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns `true` if every cell is zero. Implementors may override it,
+    /// e.g. to skip the cells that aren't stored.
+    fn is_zeroed(&self) -> bool {
+        (0..self.len()).all(|index| self.get(index).is_zero())
+    }
+    /// Sets the cells in a given range to a given value. Implementors may
+    /// override it with a faster bulk write.
+    fn fill(&mut self, range: Range<usize>, value: Self::Cell) {
+        for index in range {
+            self.set(index, value);
         }
     }
-    fn iter(&mut self) -> LexerRefIter<'_, T> {
-        LexerRefIter { lexer: self }
+    /// Extends the tape with zeroed cells up to a given length, for
+    /// [`TapePolicy::Grow`]. Returns `false` if the tape can't grow, which
+    /// is the default.
+    fn grow(&mut self, _len: usize) -> bool {
+        false
+    }
+    /// Inserts a given amount of zeroed cells before the first one, for
+    /// [`TapePolicy::GrowBoth`]. Returns `false` if the tape can't grow,
+    /// which is the default.
+    fn grow_front(&mut self, _count: usize) -> bool {
+        false
     }
 }
 
-struct LexerIter<T: BufRead> {
-    lexer: Lexer<T>,
-}
+/// The default [`Tape`], a vector of cells owned by the machine. Derefs to
+/// the slice of the cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VecTape<C: Cell = u8>(Vec<C>);
 
-impl<T: BufRead> Iterator for LexerIter<T> {
-    type Item = Option<Token>;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.lexer.eof() {
-            true => None,
-            false => Some(self.lexer.next_token()),
-        }
+impl<C: Cell> Deref for VecTape<C> {
+    type Target = [C];
+    fn deref(&self) -> &[C] {
+        &self.0
     }
 }
 
-impl<T: BufRead> IntoIterator for Lexer<T> {
-    type Item = Option<Token>;
-    type IntoIter = LexerIter<T>;
-    fn into_iter(self) -> Self::IntoIter {
-        LexerIter { lexer: self }
+impl<C: Cell> DerefMut for VecTape<C> {
+    fn deref_mut(&mut self) -> &mut [C] {
+        &mut self.0
     }
 }
 
-struct LexerRefIter<'a, T: BufRead> {
-    lexer: &'a mut Lexer<T>,
+impl<C: Cell> VecTape<C> {
+    /// Creates a tape of given size with all the cells set to zero.
+    pub fn new(size: usize) -> Self {
+        Self(vec![C::default(); size])
+    }
 }
 
-impl<'a, T: BufRead> Iterator for LexerRefIter<'a, T> {
-    type Item = Option<Token>;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.lexer.eof() {
-            true => None,
-            false => Some(self.lexer.next_token()),
-        }
+impl<C: Cell> Tape for VecTape<C> {
+    type Cell = C;
+    fn get(&self, index: usize) -> C {
+        self.0[index]
+    }
+    fn set(&mut self, index: usize, value: C) {
+        self.0[index] = value;
+    }
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn fill(&mut self, range: Range<usize>, value: C) {
+        self.0[range].fill(value);
+    }
+    fn grow(&mut self, len: usize) -> bool {
+        self.0.resize(len, C::default());
+        true
+    }
+    fn grow_front(&mut self, count: usize) -> bool {
+        self.0
+            .splice(0..0, std::iter::repeat_n(C::default(), count));
+        true
     }
 }
 
-impl<'a, T: BufRead> IntoIterator for &'a mut Lexer<T> {
-    type Item = Option<Token>;
-    type IntoIter = LexerRefIter<'a, T>;
-    fn into_iter(self) -> Self::IntoIter {
-        LexerRefIter { lexer: self }
-    }
+/// A [`Tape`] of a fixed length, allocating its cells lazily: only the cells
+/// up to the last one set to a non-zero value are stored, the rest read as
+/// zeros. Useful for a large tape that a program may mostly leave
+/// untouched, see [`BrainfuckMachine::lazy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LazyTape<C: Cell = u8> {
+    cells: Vec<C>,
+    len: usize,
 }
-struct Parser<T: BufRead> {
-    lexer: Lexer<T>,
+
+impl LazyTape {
+    /// Creates a tape of given length with all the cells set to zero, with
+    /// room for the first 256 of them.
+    pub fn new(len: usize) -> Self {
+        Self::with_len(len)
+    }
 }
 
-impl<T: BufRead> Parser<T> {
-    fn from_lexer(lexer: Lexer<T>) -> Self {
-        Self { lexer }
+impl<C: Cell> LazyTape<C> {
+    /// Like [`LazyTape::new`], for any [`Cell`], e.g.
+    /// `LazyTape::<u16>::with_len(len)`.
+    pub fn with_len(len: usize) -> Self {
+        Self {
+            cells: Vec::with_capacity(len.min(256)),
+            len,
+        }
     }
-    fn from_reader(reader: T) -> Self {
-        Self::from_lexer(Lexer { reader })
+
+    /// Returns the number of cells stored, i.e. the index of the last cell
+    /// set to a non-zero value plus one, at most.
+    pub fn allocated(&self) -> usize {
+        self.cells.len()
     }
-    fn parse_rec(
-        lexer_iter: &mut LexerRefIter<T>,
-        is_loop: bool,
-    ) -> Result<Option<Vec<Statement>>> {
-        let mut result: Vec<Statement> = Vec::new();
-        while let Some(opt_token) = lexer_iter.next() {
-            match opt_token {
-                Some(token) => match token {
-                    Token::Increment => result.push(Statement::Add(1)),
-                    Token::Decrement => result.push(Statement::Add(u8::MAX)),
-                    Token::ShiftLeft => result.push(Statement::MoveLeft(1)),
-                    Token::ShiftRight => result.push(Statement::MoveRight(1)),
-                    Token::PutChar => result.push(Statement::PutChar),
-                    Token::ReadChar => result.push(Statement::ReadChar),
-                    Token::StartLoop => {
-                        let opt_loop = Self::parse_rec(lexer_iter, true)?;
-                        if let Some(stmt_loop) = opt_loop {
-                            result.push(Statement::new_loop(stmt_loop));
-                        }
-                    }
-                    Token::EndLoop => {
-                        if is_loop {
-                            if result.is_empty() {
-                                return Ok(None);
-                            } else {
-                                return Ok(Some(result));
-                            }
-                        } else {
-                            return Err(Error::new(
-                                ErrorKind::InvalidData,
-                                "Error: ']' found with no matching '['.".to_string(),
-                            ));
-                        }
-                    }
-                },
-                None => {}
+}
+
+impl<C: Cell> Tape for LazyTape<C> {
+    type Cell = C;
+    fn get(&self, index: usize) -> C {
+        self.cells.get(index).copied().unwrap_or_default()
+    }
+    fn set(&mut self, index: usize, value: C) {
+        if index >= self.cells.len() {
+            if value.is_zero() {
+                return;
             }
+            self.cells.resize(index + 1, C::default());
         }
-        if is_loop {
-            Err(Error::new(
-                ErrorKind::InvalidData,
-                "Error: '[' found with no matching ']'.".to_string(),
-            ))
+        self.cells[index] = value;
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    // the cells past the stored ones read as zeros
+    fn is_zeroed(&self) -> bool {
+        self.cells.iter().all(|cell| cell.is_zero())
+    }
+    fn fill(&mut self, range: Range<usize>, value: C) {
+        if !value.is_zero() {
+            if range.end > self.cells.len() {
+                self.cells.resize(range.end, C::default());
+            }
+            self.cells[range].fill(value);
+        } else if range.end >= self.cells.len() {
+            // zeroing the stored cells up to the end needs no storage
+            self.cells.truncate(range.start);
         } else {
-            Ok(Some(result))
+            self.cells[range].fill(value);
         }
     }
+}
 
-    fn parse(&mut self) -> Result<Vec<Statement>> {
-        let lexer_iter: &mut LexerRefIter<T> = &mut self.lexer.iter();
-        let parsed_opt = Self::parse_rec(lexer_iter, false)?;
-        Ok(parsed_opt.unwrap_or_default())
+// used by `BrainfuckMachine::over`
+impl<C: Cell> Tape for &mut [C] {
+    type Cell = C;
+    fn get(&self, index: usize) -> C {
+        self[index]
+    }
+    fn set(&mut self, index: usize, value: C) {
+        self[index] = value;
+    }
+    fn len(&self) -> usize {
+        <[C]>::len(self)
+    }
+    fn fill(&mut self, range: Range<usize>, value: C) {
+        self[range].fill(value);
     }
 }
 
-struct Optimizer {
-    statements: Vec<Statement>,
+/// What the moves of a [`BrainfuckMachine`]'s header past the ends of the
+/// tape do.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum TapePolicy {
+    /// The move fails with a [`MachineError`].
+    #[default]
+    Error,
+    /// The header wraps around to the other end of the tape, i.e. the index
+    /// is taken modulo the size.
+    Wrap,
+    /// The header stops at the end of the tape. The pointer can then end up
+    /// elsewhere than the optimized code assumes, so
+    /// [`Interpreter::run_with_optimization`] skips the passes fusing the
+    /// clears and the fills.
+    Saturate,
+    /// The tape is extended to the right with zeroed cells as needed, if it
+    /// can grow (see [`Tape::grow`]). Moves left of the first cell fail.
+    Grow,
+    /// Like [`TapePolicy::Grow`], but the tape is also extended to the left
+    /// of the first cell (see [`Tape::grow_front`]), making it unbounded in
+    /// both directions. The cells left of the starting one have negative
+    /// positions, see [`BrainfuckMachine::tape_range`].
+    GrowBoth,
 }
 
-impl Optimizer {
-    fn new(statements: Vec<Statement>) -> Self {
-        Self { statements }
-    }
+/// Errors of the moves of a [`BrainfuckMachine`]'s header, see
+/// [`BrainfuckMachine::try_move_left`] and
+/// [`BrainfuckMachine::try_move_right`], and of the accesses to its cells,
+/// see [`BrainfuckMachine::set_cell`].
+///
+/// A move out of the tape is an [`MachineError::Overflow`] or an
+/// [`MachineError::Underflow`], depending on its direction. Both carry the
+/// index, the shift and the size, so a caller matching on any out-of-bounds
+/// move can use `Overflow { index, shift, size } | Underflow { index, shift,
+/// size }`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MachineError {
+    /// The header would be moved past the last cell of the tape.
+    Overflow {
+        /// Index of the header before the move.
+        index: usize,
+        /// Length of the move.
+        shift: usize,
+        /// Size of the tape.
+        size: usize,
+    },
+    /// The header would be moved left of the first cell of the tape.
+    Underflow {
+        /// Index of the header before the move.
+        index: usize,
+        /// Length of the move.
+        shift: usize,
+        /// Size of the tape.
+        size: usize,
+    },
+    /// A cell past the last one of the tape would be accessed.
+    CellOutOfBounds {
+        /// Index of the cell.
+        index: usize,
+        /// Size of the tape.
+        size: usize,
+    },
+}
 
-    fn generate_optimized_stmt(stmt_type: &Statement, value: &mut usize) -> Option<Statement> {
-        let result = match value {
-            0 => None,
-            _ => match stmt_type {
-                Statement::Add(_) => Some(Statement::Add(*value as u8)),
-                Statement::MoveLeft(_) => Some(Statement::MoveLeft(*value)),
-                Statement::MoveRight(_) => Some(Statement::MoveRight(*value)),
-                _ => None,
-            },
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (direction, index, shift, size) = match self {
+            Self::Overflow { index, shift, size } => ("right", index, shift, size),
+            Self::Underflow { index, shift, size } => ("left", index, shift, size),
+            Self::CellOutOfBounds { index, size } => {
+                return write!(
+                    f,
+                    "Error: cell {} is past the end of the tape of {} cells.",
+                    index, size
+                )
+            }
         };
-        *value = 0;
-        result
+        write!(
+            f,
+            "Error: moving the pointer {} by {} from cell {} leaves the tape of {} cells.",
+            direction, shift, index, size
+        )
     }
+}
 
-    fn optimize_rec(statements: &Vec<Statement>) -> Option<Vec<Statement>> {
-        let mut result: Vec<Statement> = Vec::new();
-        let mut stmt_count: usize = 0;
-        let mut last_statement = Statement::ReadChar;
+impl std::error::Error for MachineError {}
 
-        for statement in statements {
-            if !statement.is_equal_type(&last_statement)
-                && (!statement.is_move() || !last_statement.is_move())
-            {
-                match Self::generate_optimized_stmt(&last_statement, &mut stmt_count) {
-                    Some(statement) => result.push(statement),
-                    None => {}
-                }
+impl From<MachineError> for Error {
+    fn from(error: MachineError) -> Self {
+        Error::other(error)
+    }
+}
+
+/// This struct is used as an implementation of a brainfuck-compatible
+/// Turing-like machine that supports basic operations needed for such
+/// compilations. This machine works under an assumption that chars can be
+/// converted into [`u8`] freely through ASCII decoding and encoding. The
+/// cells are bytes unless the [`Tape`] says otherwise, see [`Cell`].
+pub struct BrainfuckMachine<S: Tape = VecTape> {
+    /// Size of the tape vector.
+    size: usize,
+    /// Current cell index.
+    index: usize,
+    /// Tape vector.
+    tape: S,
+    /// What the additions past the range of a cell do.
+    overflow: CellOverflow,
+    /// What the moves past the ends of the tape do.
+    policy: TapePolicy,
+    /// Index of the cell at position zero, i.e. the amount of cells added
+    /// to the left of the tape.
+    origin: usize,
+}
+
+impl BrainfuckMachine {
+    /// Creates a `BrainfuckMachine` instance of given tape size.
+    pub fn new(size: usize) -> Self {
+        Self::with_tape(VecTape::new(size))
+    }
+}
+
+impl BrainfuckMachine<LazyTape> {
+    /// Creates a `BrainfuckMachine` instance of given tape size that only
+    /// allocates the cells written to, see [`LazyTape`]. The size is then
+    /// just a cap on the tape.
+    pub fn lazy(size: usize) -> Self {
+        Self::with_tape(LazyTape::new(size))
+    }
+}
+
+impl<'a> BrainfuckMachine<&'a mut [u8]> {
+    /// Creates a `BrainfuckMachine` instance using a given buffer as its
+    /// tape, without copying it or allocating anything. The buffer is
+    /// borrowed for as long as the machine lives, and keeps the values of
+    /// the cells afterwards.
+    pub fn over(tape: &'a mut [u8]) -> Self {
+        Self::with_tape(tape)
+    }
+}
+
+impl<S: Tape> BrainfuckMachine<S> {
+    /// Creates a `BrainfuckMachine` instance using a given [`Tape`], with the
+    /// pointer at the first cell. The cells keep their values.
+    pub fn with_tape(tape: S) -> Self {
+        Self {
+            size: tape.len(),
+            index: 0,
+            tape,
+            overflow: CellOverflow::default(),
+            policy: TapePolicy::default(),
+            origin: 0,
+        }
+    }
+
+    /// Sets what the moves past the ends of the tape do, see [`TapePolicy`].
+    pub fn with_policy(mut self, policy: TapePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets what the additions past the range of a cell do, see
+    /// [`CellOverflow`].
+    pub fn with_cell_overflow(mut self, overflow: CellOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Sets every cell to zero and moves the header to the first cell (to
+    /// position zero if the tape has grown to the left), reusing the tape.
+    /// The size and the [`TapePolicy`] are kept.
+    pub fn reset(&mut self) {
+        self.tape.fill(0..self.size, S::Cell::default());
+        self.index = self.origin;
+    }
+
+    /// Moves the header left by a given amount. Panics when the index is out
+    /// of bounds, see [`BrainfuckMachine::try_move_left`] for a
+    /// non-panicking version.
+    pub fn move_left(&mut self, shift: usize) {
+        if self.try_move_left(shift).is_err() {
+            panic!(
+                "Index out of bounds.
+Index before move: {}.
+Left shift value: {}.
+",
+                self.index, shift,
+            )
+        }
+    }
+    /// Moves the header right by a given amount. Panics when the index is out
+    /// of bounds, i.e. the header would end up past the last cell, see
+    /// [`BrainfuckMachine::try_move_right`] for a non-panicking version.
+    pub fn move_right(&mut self, shift: usize) {
+        if self.try_move_right(shift).is_err() {
+            panic!(
+                "Index out of bounds.
+Index before move: {}.
+Right shift value: {}.
+Max possible index: {}.
+",
+                self.index,
+                shift,
+                self.size.saturating_sub(1)
+            )
+        }
+    }
+
+    /// Moves the header left by a given amount. Returns a [`MachineError`]
+    /// and leaves the header where it was if the index would be out of
+    /// bounds, unless the [`TapePolicy`] says otherwise.
+    pub fn try_move_left(&mut self, shift: usize) -> std::result::Result<(), MachineError> {
+        let underflow = MachineError::Underflow {
+            index: self.index,
+            shift,
+            size: self.size,
+        };
+        self.index = match self.policy {
+            TapePolicy::Wrap => {
+                let shift = shift.checked_rem(self.size).ok_or(underflow)?;
+                (self.index + self.size - shift) % self.size
             }
-            let mut cloned = statement.clone();
-            match statement {
-                Statement::MoveLeft(value) => match last_statement {
-                    Statement::MoveLeft(_) => {
-                        stmt_count += value;
-                    }
-                    Statement::MoveRight(_) => {
-                        if stmt_count < *value {
-                            stmt_count = value - stmt_count;
-                        } else {
-                            stmt_count -= value;
-                            cloned = last_statement.clone();
-                        }
+            TapePolicy::Saturate => self.index.saturating_sub(shift),
+            TapePolicy::GrowBoth if shift > self.index => {
+                // doubling the tape keeps the cost of walking left linear
+                let added = (shift - self.index).max(self.size);
+                if self.size.checked_add(added).is_none() || !self.tape.grow_front(added) {
+                    return Err(underflow);
+                }
+                self.size += added;
+                self.origin += added;
+                self.index + added - shift
+            }
+            TapePolicy::Error | TapePolicy::Grow | TapePolicy::GrowBoth => {
+                match shift.cmp(&(self.index)) {
+                    Ordering::Greater => return Err(underflow),
+                    _ => self.index - shift,
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// Moves the header right by a given amount. Returns a [`MachineError`]
+    /// and leaves the header where it was if the header would end up past
+    /// the last cell, unless the [`TapePolicy`] says otherwise.
+    pub fn try_move_right(&mut self, shift: usize) -> std::result::Result<(), MachineError> {
+        let target = self.right_target(shift)?;
+        if target >= self.size {
+            // only a growing tape gets here
+            if !self.tape.grow(target + 1) {
+                return Err(MachineError::Overflow {
+                    index: self.index,
+                    shift,
+                    size: self.size,
+                });
+            }
+            self.size = target + 1;
+        }
+        self.index = target;
+        Ok(())
+    }
+
+    // Where a move right by a given amount ends up, past the end of the tape
+    // if it has to grow.
+    fn right_target(&self, shift: usize) -> std::result::Result<usize, MachineError> {
+        let overflow = MachineError::Overflow {
+            index: self.index,
+            shift,
+            size: self.size,
+        };
+        match self.policy {
+            TapePolicy::Wrap => {
+                let shift = shift.checked_rem(self.size).ok_or(overflow)?;
+                Ok((self.index + shift) % self.size)
+            }
+            TapePolicy::Saturate => Ok(self
+                .index
+                .saturating_add(shift)
+                .min(self.size.saturating_sub(1))),
+            TapePolicy::Grow | TapePolicy::GrowBoth => self
+                .index
+                .checked_add(shift)
+                .filter(|&target| target < usize::MAX)
+                .ok_or(overflow),
+            // the index is always smaller than the size, so this can't
+            // underflow
+            TapePolicy::Error => match shift.cmp(&(self.size - self.index)) {
+                Ordering::Greater | Ordering::Equal => Err(overflow),
+                _ => Ok(self.index + shift),
+            },
+        }
+    }
+
+    /// Adds a given value to the current cell, with wrapping unless the
+    /// [`CellOverflow`] says otherwise.
+    pub fn add(&mut self, value: S::Cell) {
+        let cell = self.tape.get(self.index);
+        let value = match self.overflow {
+            CellOverflow::Wrap => cell.wrapping_add(value),
+            CellOverflow::Saturate => cell.saturating_add(value),
+        };
+        self.tape.set(self.index, value);
+    }
+
+    /// Substracts a given value to the current cell, with wrapping unless
+    /// the [`CellOverflow`] says otherwise.
+    pub fn substract(&mut self, value: S::Cell) {
+        let cell = self.tape.get(self.index);
+        let value = match self.overflow {
+            CellOverflow::Wrap => cell.wrapping_sub(value),
+            CellOverflow::Saturate => cell.saturating_sub(value),
+        };
+        self.tape.set(self.index, value);
+    }
+
+    /// Inserts a given char's value into the current cell. Only chars up to
+    /// `'\u{FF}'` (ASCII and Latin-1) fit into a cell; for any other char
+    /// the cell is left unchanged and an [`std::io::Error`] is returned. Use
+    /// [`BrainfuckMachine::read_byte`] to insert raw bytes instead.
+    pub fn read_char(&mut self, input: char) -> Result<()> {
+        let byte = u8::try_from(input).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Char {:?} does not fit into a single cell.", input),
+            )
+        })?;
+        self.read_byte(byte);
+        Ok(())
+    }
+
+    /// Inserts a given raw byte into the current cell.
+    pub fn read_byte(&mut self, input: u8) {
+        self.tape.set(self.index, S::Cell::from_byte(input))
+    }
+
+    /// Sets the current cell to a given value.
+    pub fn set_current_cell(&mut self, value: S::Cell) {
+        self.tape.set(self.index, value)
+    }
+
+    /// Returns the current cell's value ASCII encoded into a char. Values
+    /// above `0x7F` are mapped to the matching Latin-1 chars, so writing the
+    /// result out as UTF-8 text does not preserve the original byte. Use
+    /// [`BrainfuckMachine::put_byte`] to get the raw value instead.
+    pub fn put_char(&self) -> char {
+        self.put_byte() as char
+    }
+
+    /// Returns the current cell's raw value, its lowest byte if the cells
+    /// are wider.
+    pub fn put_byte(&self) -> u8 {
+        self.tape.get(self.index).to_byte()
+    }
+
+    /// Returns the value of the cell at a given index, or `None` if the index
+    /// is past the end of the tape.
+    pub fn peek(&self, index: usize) -> Option<S::Cell> {
+        (index < self.size).then(|| self.tape.get(index))
+    }
+
+    /// Returns the value of the cell at a given index, same as
+    /// [`BrainfuckMachine::peek`].
+    pub fn get_cell(&self, index: usize) -> Option<S::Cell> {
+        self.peek(index)
+    }
+
+    /// Sets the cell at a given index to a given value, e.g. to seed the
+    /// tape before a run. Returns a [`MachineError::CellOutOfBounds`], as an
+    /// [`std::io::Error`] of kind [`ErrorKind::InvalidInput`], and leaves
+    /// the tape unchanged if the index is past the end of the tape.
+    pub fn set_cell(&mut self, index: usize, value: S::Cell) -> Result<()> {
+        if index >= self.size {
+            let error = MachineError::CellOutOfBounds {
+                index,
+                size: self.size,
+            };
+            return Err(Error::new(ErrorKind::InvalidInput, error));
+        }
+        self.tape.set(index, value);
+        Ok(())
+    }
+
+    /// Returns the index of the current cell, same as
+    /// [`BrainfuckMachine::current_index`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the index of the current cell.
+    pub fn current_index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of cells of the tape. It only changes when the
+    /// tape grows, see [`TapePolicy::Grow`].
+    pub fn tape_size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the position of the current cell relative to the cell the
+    /// header started at, negative if it's left of it, see
+    /// [`TapePolicy::GrowBoth`].
+    pub fn position(&self) -> isize {
+        self.index as isize - self.origin as isize
+    }
+
+    /// Returns the positions of the first and the last cell of the tape,
+    /// relative to the cell the header started at, i.e. `(0, size - 1)`
+    /// unless the tape has grown to the left, see [`TapePolicy::GrowBoth`].
+    /// [`BrainfuckMachine::get_tape`] returns the cells of this whole range.
+    /// The cells added to the left may not all have been visited, as the
+    /// tape grows by more than a cell at a time.
+    pub fn tape_range(&self) -> (isize, isize) {
+        let first = -(self.origin as isize);
+        (first, first + self.size as isize - 1)
+    }
+
+    /// Returns the current cell's value, without copying the tape.
+    pub fn current_cell(&self) -> S::Cell {
+        self.tape.get(self.index)
+    }
+
+    /// Returns the cells whose values differ from the ones of another
+    /// machine, as `(index, value, other value)`, ordered by the index. The
+    /// cells past the end of the shorter tape are compared as zeros.
+    pub fn diff<O: Tape<Cell = S::Cell>>(
+        &self,
+        other: &BrainfuckMachine<O>,
+    ) -> Vec<(usize, S::Cell, S::Cell)> {
+        (0..self.size.max(other.size))
+            .filter_map(|index| {
+                let value = self.peek(index).unwrap_or_default();
+                let other_value = other.peek(index).unwrap_or_default();
+                (value != other_value).then_some((index, value, other_value))
+            })
+            .collect()
+    }
+
+    /// Returns the indices of the current cells of both machines, as
+    /// `(index, other index)`, if they differ.
+    pub fn diff_index<O: Tape>(&self, other: &BrainfuckMachine<O>) -> Option<(usize, usize)> {
+        (self.index != other.index).then_some((self.index, other.index))
+    }
+
+    /// Returns `true` if the current cell's value is non-zero.
+    pub fn check_loop(&self) -> bool {
+        !self.tape.get(self.index).is_zero()
+    }
+
+    /// Copies given data onto the tape, starting at a given cell. Returns an
+    /// [`std::io::Error`] and leaves the tape unchanged if the data doesn't
+    /// fit.
+    pub fn load(&mut self, start: usize, data: &[u8]) -> Result<()> {
+        match start.checked_add(data.len()) {
+            Some(end) if end <= self.size => {
+                for (index, &value) in (start..end).zip(data) {
+                    self.tape.set(index, S::Cell::from_byte(value));
+                }
+                Ok(())
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes starting at cell {} don't fit onto a tape of size {}.",
+                    data.len(),
+                    start,
+                    self.size
+                ),
+            )),
+        }
+    }
+
+    /// Returns a copy of the vector representing the tape. This clones every
+    /// cell, so use [`BrainfuckMachine::current_cell`] or
+    /// [`BrainfuckMachine::peek`] to read single cells.
+    pub fn get_tape(&self) -> Vec<S::Cell> {
+        (0..self.size).map(|index| self.tape.get(index)).collect()
+    }
+}
+
+// Brainfuck grammar:
+// code := (stmt_block)*
+//
+// stmt_block := stmt | loop
+//
+// loop := '[' stmt_block* ']'
+//
+// stmt := '+' | '-' | '<' | '>' | ',' | '.'
+//
+// The code ends at the end of the reader or, if set, at the first occurrence
+// of the terminator byte. Any bytes after the terminator are left unread.
+struct Lexer<T: BufRead> {
+    reader: T,
+    terminator: Option<u8>,
+    // commands added by a dialect
+    dialect: Vec<(u8, Token)>,
+    keep_comments: bool,
+    finished: bool,
+    // byte offset of the next byte and the offsets of all the tokens read
+    position: usize,
+    offsets: Vec<usize>,
+    // a failed read ends the code, the error is kept for the parser
+    error: Option<Error>,
+}
+
+impl<T: BufRead> Lexer<T> {
+    fn new(reader: T) -> Self {
+        Self {
+            reader,
+            terminator: None,
+            dialect: Vec::new(),
+            keep_comments: false,
+            finished: false,
+            position: 0,
+            offsets: Vec::new(),
+            error: None,
+        }
+    }
+    // All the reads go through `fill_buf`, so a blocking reader is waited on
+    // only once per chunk.
+    fn peek_byte(&mut self) -> Option<u8> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => match buf.first() {
+                    Some(byte) if Some(*byte) == self.terminator => {
+                        self.reader.consume(1);
+                        self.finished = true;
+                        return None;
                     }
-                    _ => {
-                        stmt_count = *value;
+                    Some(byte) => return Some(*byte),
+                    None => {
+                        self.finished = true;
+                        return None;
                     }
                 },
-                Statement::MoveRight(value) => match last_statement {
-                    Statement::MoveRight(_) => {
-                        stmt_count += value;
+                Err(msg) if msg.kind() == ErrorKind::Interrupted => {}
+                Err(msg) => {
+                    self.finished = true;
+                    self.error = Some(msg);
+                    return None;
+                }
+            }
+        }
+    }
+    // Returns the next command, skipping any other bytes (or gathering them
+    // into a single comment, if they are kept), or `None` at the end of the
+    // code. This is the only way the code is read, so the end of the code
+    // can't be noticed too early or too late.
+    fn next_token(&mut self) -> Option<Token> {
+        if !self.keep_comments {
+            self.skip_comments();
+        }
+        let mut comment: Vec<u8> = Vec::new();
+        while let Some(byte) = self.peek_byte() {
+            let token = Self::tokenize(&(byte as char)).or_else(|| {
+                self.dialect
+                    .iter()
+                    .find(|(command, _)| *command == byte)
+                    .map(|(_, token)| token.clone())
+            });
+            if token.is_some() && !comment.is_empty() {
+                break;
+            }
+            self.reader.consume(1);
+            self.position += 1;
+            if token.is_some() {
+                self.offsets.push(self.position - 1);
+                return token;
+            }
+            if self.keep_comments {
+                comment.push(byte);
+            }
+        }
+        match comment.is_empty() {
+            true => None,
+            false => Some(Token::Comment(comment)),
+        }
+    }
+    // Skips the bytes that aren't commands a whole buffer at a time, rather
+    // than peeking at each of them. The errors are left for `peek_byte` to
+    // handle.
+    fn skip_comments(&mut self) {
+        while !self.finished {
+            let Ok(buf) = self.reader.fill_buf() else {
+                return;
+            };
+            let length = buf.len();
+            if length == 0 {
+                self.finished = true;
+                return;
+            }
+            let skipped = buf
+                .iter()
+                .position(|&byte| {
+                    Some(byte) == self.terminator
+                        || Self::tokenize(&(byte as char)).is_some()
+                        || self.dialect.iter().any(|(command, _)| *command == byte)
+                })
+                .unwrap_or(length);
+            self.reader.consume(skipped);
+            self.position += skipped;
+            if skipped < length {
+                return;
+            }
+        }
+    }
+    fn tokenize(input: &char) -> Option<Token> {
+        use crate::Token::*;
+
+        match input {
+            '+' => Some(Increment),
+            '-' => Some(Decrement),
+            '<' => Some(ShiftLeft),
+            '>' => Some(ShiftRight),
+            ',' => Some(ReadChar),
+            '.' => Some(PutChar),
+            '[' => Some(StartLoop),
+            ']' => Some(EndLoop),
+            _ => None,
+        }
+    }
+    fn iter(&mut self) -> LexerRefIter<'_, T> {
+        LexerRefIter { lexer: self }
+    }
+}
+
+struct LexerIter<T: BufRead> {
+    lexer: Lexer<T>,
+}
+
+impl<T: BufRead> Iterator for LexerIter<T> {
+    type Item = Token;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next_token()
+    }
+}
+
+impl<T: BufRead> IntoIterator for Lexer<T> {
+    type Item = Token;
+    type IntoIter = LexerIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        LexerIter { lexer: self }
+    }
+}
+
+struct LexerRefIter<'a, T: BufRead> {
+    lexer: &'a mut Lexer<T>,
+}
+
+impl<'a, T: BufRead> Iterator for LexerRefIter<'a, T> {
+    type Item = Token;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next_token()
+    }
+}
+
+impl<'a, T: BufRead> IntoIterator for &'a mut Lexer<T> {
+    type Item = Token;
+    type IntoIter = LexerRefIter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        LexerRefIter { lexer: self }
+    }
+}
+struct Parser<T: BufRead> {
+    lexer: Lexer<T>,
+    max_statements: Option<usize>,
+}
+
+impl<T: BufRead> Parser<T> {
+    fn from_lexer(lexer: Lexer<T>) -> Self {
+        Self {
+            lexer,
+            max_statements: None,
+        }
+    }
+    fn from_reader(reader: T) -> Self {
+        Self::from_lexer(Lexer::new(reader))
+    }
+    // Loops left open at the end of the code are closed implicitly and
+    // counted in `open`.
+    // Every statement, nested or not, counts towards `max_statements`.
+    fn parse_rec(
+        lexer_iter: &mut LexerRefIter<T>,
+        depth: usize,
+        open: &mut usize,
+        count: &mut usize,
+        max_statements: Option<usize>,
+    ) -> Result<Vec<Statement>> {
+        let is_loop = depth > 0;
+        let mut result: Vec<Statement> = Vec::new();
+        while let Some(token) = lexer_iter.next() {
+            if token != Token::EndLoop {
+                *count += 1;
+                if let Some(max) = max_statements.filter(|&max| *count > max) {
+                    return Err(ParseError::ProgramTooLarge {
+                        count: max,
+                        offset: lexer_iter.lexer.position,
                     }
-                    Statement::MoveLeft(_) => {
-                        if stmt_count < *value {
-                            stmt_count = value - stmt_count;
-                        } else {
-                            stmt_count -= value;
-                            cloned = last_statement.clone();
+                    .into());
+                }
+            }
+            match token {
+                Token::Increment => result.push(Statement::Add(1)),
+                Token::Decrement => result.push(Statement::Add(u8::MAX)),
+                Token::ShiftLeft => result.push(Statement::MoveLeft(1)),
+                Token::ShiftRight => result.push(Statement::MoveRight(1)),
+                Token::PutChar => result.push(Statement::PutChar),
+                Token::ReadChar => result.push(Statement::ReadChar),
+                Token::SideEffect(kind) => result.push(Statement::SideEffect(kind)),
+                Token::LoadTapeSize => result.push(Statement::LoadTapeSize),
+                Token::Random => result.push(Statement::Random),
+                Token::Comment(text) => result.push(Statement::Comment(text)),
+                Token::StartLoop => {
+                    if depth == MAX_NESTING_DEPTH {
+                        return Err(ParseError::NestingTooDeep {
+                            offset: lexer_iter.lexer.position,
                         }
+                        .into());
                     }
-                    _ => {
-                        stmt_count = *value;
-                    }
-                },
-                Statement::Add(value) => match last_statement {
-                    Statement::Add(_) => {
-                        stmt_count = value.wrapping_add(stmt_count as u8) as usize;
-                    }
-                    _ => {
-                        stmt_count = *value as usize;
-                    }
-                },
-                stmt @ (Statement::PutChar | Statement::ReadChar) => result.push(stmt.clone()),
-                Statement::Loop(code) => {
-                    if let Some(optimized) = Self::optimize_rec(&code) {
-                        result.push(Statement::new_loop(optimized));
+                    let stmt_loop =
+                        Self::parse_rec(lexer_iter, depth + 1, open, count, max_statements)?;
+                    result.push(Statement::new_loop(stmt_loop));
+                }
+                Token::EndLoop => {
+                    if is_loop {
+                        return Ok(result);
+                    } else {
+                        return Err(ParseError::UnmatchedLoopEnd {
+                            offset: lexer_iter.lexer.position,
+                        }
+                        .into());
                     }
                 }
             }
-            last_statement = cloned;
         }
-        match Self::generate_optimized_stmt(&last_statement, &mut stmt_count) {
-            Some(statement) => result.push(statement),
-            None => {}
+        if is_loop {
+            *open += 1;
+        }
+        Ok(result)
+    }
+
+    fn parse(&mut self) -> Result<Vec<Statement>> {
+        match self.parse_partial()? {
+            (statements, 0) => Ok(statements),
+            _ => Err(ParseError::UnmatchedLoopStart {
+                offset: self.lexer.position,
+            }
+            .into()),
+        }
+    }
+
+    // Parses code that may still be incomplete, returning the statements
+    // along with the amount of loops left open.
+    fn parse_partial(&mut self) -> Result<(Vec<Statement>, usize)> {
+        let lexer_iter: &mut LexerRefIter<T> = &mut self.lexer.iter();
+        let mut open = 0;
+        let statements = Self::parse_rec(lexer_iter, 0, &mut open, &mut 0, self.max_statements)?;
+        if let Some(error) = self.lexer.error.take() {
+            return Err(error);
+        }
+        Ok((statements, open))
+    }
+}
+
+/// A parsed brainfuck program. It is displayed as a listing of its
+/// statements, one per line, e.g. to inspect the optimized code.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    statements: Vec<Statement>,
+}
+
+impl Program {
+    /// Parses a program from a [`BufRead`] implementor. Returns an
+    /// [`std::io::Error`] if the code is malformed or cannot be read.
+    pub fn from_reader<T: BufRead>(reader: T) -> Result<Self> {
+        let statements = Parser::from_reader(reader).parse()?;
+        Ok(Self { statements })
+    }
+
+    /// Parses a program that may still be incomplete, e.g. one that is being
+    /// typed in. Loops left open at the end of the code are closed, and
+    /// their amount is returned along with the program. A `]` with no
+    /// matching `[` is still an [`std::io::Error`].
+    pub fn from_reader_partial<T: BufRead>(reader: T) -> Result<(Self, usize)> {
+        let (statements, open) = Parser::from_reader(reader).parse_partial()?;
+        Ok((Self { statements }, open))
+    }
+
+    /// Parses a program like [`Program::from_reader`], but keeps the
+    /// comments, so that [`Program::to_source`] can put them back in place.
+    pub fn from_reader_with_comments<T: BufRead>(reader: T) -> Result<Self> {
+        let mut parser = Parser::from_reader(reader);
+        parser.lexer.keep_comments = true;
+        let statements = parser.parse()?;
+        Ok(Self { statements })
+    }
+
+    /// Returns the source code of the program. Comments are included only
+    /// if the program was parsed with [`Program::from_reader_with_comments`],
+    /// bytes that aren't valid UTF-8 are replaced, see
+    /// [`Program::to_source_bytes`] for the exact source.
+    pub fn to_source(&self) -> String {
+        String::from_utf8_lossy(&self.to_source_bytes()).into_owned()
+    }
+
+    /// Returns the source code of the program as raw bytes, keeping the
+    /// comments byte for byte regardless of their encoding.
+    pub fn to_source_bytes(&self) -> Vec<u8> {
+        let mut source = Vec::new();
+        Statement::write_source(&self.statements, &mut source);
+        source
+    }
+
+    /// Returns the net offset of the pointer after running the program,
+    /// relative to its starting position. Returns `None` if the offset
+    /// depends on the data, i.e. the program contains a loop that moves the
+    /// pointer by a non-zero amount per iteration.
+    pub fn net_pointer_offset(&self) -> Option<isize> {
+        Statement::net_offset(&self.statements)
+    }
+
+    /// Returns a 64-bit hash of the program's statements. Unlike the hashes
+    /// of [`std::collections::hash_map::DefaultHasher`], it doesn't change
+    /// between builds (the FNV-1a hash is used), so equal programs have
+    /// equal fingerprints on the same platform, see
+    /// [`Interpreter::with_memoization`].
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        self.statements.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the deepest loop nesting found in the program.
+    pub fn max_nesting_depth(&self) -> usize {
+        Statement::max_nesting(&self.statements)
+    }
+
+    /// Returns the exact output of the program, computed without any input.
+    /// Returns `None` if the output can't be known from the code alone, i.e.
+    /// the program reads input, random bytes or the tape size, or calls a
+    /// custom side effect, as well as if it doesn't finish within
+    /// [`STATIC_OUTPUT_STEP_LIMIT`] steps or moves left of the first cell.
+    /// The tape is taken to be unbounded to the right, up to
+    /// [`STATIC_OUTPUT_MAX_POINTER`].
+    pub fn static_output(&self) -> Option<Vec<u8>> {
+        if Statement::uses_environment(&self.statements) {
+            return None;
+        }
+        let output = CapturedOutput::default();
+        // the tape grows as needed, starting from a single cell
+        let mut interpreter = Interpreter::from_program(self, 1)
+            .with_tape_policy(TapePolicy::Grow)
+            .with_max_pointer(STATIC_OUTPUT_MAX_POINTER)
+            .with_step_limit(STATIC_OUTPUT_STEP_LIMIT)
+            .with_output(Box::new(output.clone()))
+            .with_message_output(Box::new(io::sink()));
+        match interpreter.run() {
+            Ok(Termination::Completed) => Some(output.take()),
+            _ => None,
+        }
+    }
+}
+
+/// Amount of steps after which [`Program::static_output`] gives up.
+pub const STATIC_OUTPUT_STEP_LIMIT: u64 = 10_000_000;
+/// Highest cell index [`Program::static_output`] lets the program reach.
+pub const STATIC_OUTPUT_MAX_POINTER: usize = 1 << 20;
+
+// Lists the statements one per line, with the bodies of the loops indented.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Statement::write_ir(&self.statements, 0, f)
+    }
+}
+
+impl FromStr for Program {
+    type Err = Error;
+    fn from_str(code: &str) -> Result<Self> {
+        Self::from_reader(code.as_bytes())
+    }
+}
+
+/// The function of a custom optimization pass, taking the code and returning
+/// the optimized code.
+pub type CustomPassFn = dyn Fn(&[Statement]) -> Vec<Statement>;
+
+/// A custom optimization pass, see [`OptimizerConfig::add_custom_pass`].
+/// Two passes are equal only if they are the same instance.
+#[derive(Clone)]
+pub struct CustomPass(Rc<CustomPassFn>);
+
+impl PartialEq for CustomPass {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomPass {}
+
+impl fmt::Debug for CustomPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomPass")
+    }
+}
+
+/// Selects which passes are run by [`Interpreter::run_with_optimization`].
+/// By default every built-in pass is enabled and there are no custom ones.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OptimizerConfig {
+    /// Fold runs of additions and pointer moves into single statements.
+    pub fold: bool,
+    /// Remove the loops that are never entered.
+    pub remove_dead_loops: bool,
+    /// Replace runs of clearing loops (`[-]`) and moves that bring the
+    /// pointer back to where it started with a single write to each of the
+    /// cleared cells, e.g. `[-]>[-]>>[-]<<<`.
+    pub fuse_clears: bool,
+    /// Replace runs of clearing loops, additions and moves that bring the
+    /// pointer back to where it started, setting a contiguous region of
+    /// cells to the same value, with a single fill, e.g. `[-]++>[-]++<`.
+    /// The loops running such a run once per decrement of their guard are
+    /// reduced to a single iteration.
+    pub fill_regions: bool,
+    /// Allow the passes to remove or rewrite IO statements. When disabled,
+    /// every `.` and `,` of the program is kept exactly as parsed, so
+    /// arithmetic and movement are the only things being optimized.
+    pub optimize_io: bool,
+    /// Passes run after the built-in ones, in order.
+    pub custom_passes: Vec<CustomPass>,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        Self {
+            fold: true,
+            remove_dead_loops: true,
+            fuse_clears: true,
+            fill_regions: true,
+            optimize_io: true,
+            custom_passes: Vec::new(),
+        }
+    }
+}
+
+impl OptimizerConfig {
+    /// Registers a custom pass. It's given the code and returns the
+    /// optimized code, which has to behave exactly like the given one. The
+    /// pass is run in every optimization iteration alongside the built-in
+    /// passes, so the code is optimized until none of the passes changes it.
+    pub fn add_custom_pass(&mut self, pass: Box<CustomPassFn>) {
+        self.custom_passes.push(CustomPass(Rc::from(pass)));
+    }
+}
+
+/// Summary of the last optimization done by an [`Interpreter`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptimizationReport {
+    /// Amount of optimization iterations that were run.
+    pub iterations: u32,
+    /// Whether the passes stopped changing the code, i.e. the code is fully
+    /// optimized.
+    pub converged: bool,
+    /// Problems found while optimizing, e.g. passes that never converge. The
+    /// optimized code is correct regardless, it may just be less optimized.
+    pub warnings: Vec<String>,
+    /// What the optimizer could tell about the termination of the code.
+    pub termination: TerminationVerdict,
+}
+
+/// What the optimizer could tell about the termination of the code, see
+/// [`OptimizationReport::termination`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TerminationVerdict {
+    /// Nothing was proven, the code may or may not terminate.
+    #[default]
+    Unknown,
+    /// The code reaches a loop that never terminates, e.g. `+[+]` with
+    /// saturating cells (see [`CellOverflow`]), so it can only be stopped by
+    /// a limit or by an error.
+    GuaranteedInfinite,
+}
+
+/// The optimization passes, see [`OptimizerConfig`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OptimizationPass {
+    /// Folding runs of additions and pointer moves.
+    Fold,
+    /// Fusing runs of clearing loops.
+    FuseClears,
+    /// Fusing runs filling a region of cells.
+    FillRegions,
+    /// Removing the loops that are never entered.
+    RemoveDeadLoops,
+    /// A custom pass, given by its index in
+    /// [`OptimizerConfig::custom_passes`].
+    Custom(usize),
+}
+
+impl OptimizationPass {
+    // The built-in passes, in the order they are run in.
+    const BUILT_IN: [Self; 4] = [
+        Self::Fold,
+        Self::FuseClears,
+        Self::FillRegions,
+        Self::RemoveDeadLoops,
+    ];
+}
+
+/// Statistics of an optimization done with [`optimize_program`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OptStats {
+    /// Amount of optimization iterations that were run.
+    pub iterations: u32,
+    /// Amount of passes that were run, over all the iterations.
+    pub passes_run: u32,
+    /// Whether the passes stopped changing the code, i.e. the code is fully
+    /// optimized.
+    pub converged: bool,
+    /// Amount of statements before the optimization, counting the statements
+    /// nested in loops.
+    pub statements_before: usize,
+    /// Amount of statements after the optimization, counting the statements
+    /// nested in loops.
+    pub statements_after: usize,
+    /// The passes that changed the code, in the order they were run.
+    pub applied: Vec<OptimizationPass>,
+}
+
+// Amount of steps between the checks of the timeout of a run.
+const TIMEOUT_CHECK_INTERVAL: u64 = 4096;
+
+// Lookup table of the CRC-32 (IEEE) of every byte, used by
+// `Interpreter::with_output_hash`.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+// The 64-bit FNV-1a hash, used by `Program::fingerprint`.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Safety ceiling for the amount of iterations of a full optimization.
+const MAX_OPTIMIZATION_ITERATIONS: u32 = 1000;
+
+// A pass returns whether it changed the code.
+type Pass = fn(&mut Optimizer) -> bool;
+
+struct Optimizer {
+    statements: Vec<Statement>,
+    loop_semantics: LoopSemantics,
+    zeroed_tape: bool,
+    // whether the cells are wider than a byte, see `Cell`
+    wide_cells: bool,
+    // whether the cells saturate instead of wrapping, see `CellOverflow`
+    saturating: bool,
+    config: OptimizerConfig,
+    extra_passes: Vec<Pass>,
+    passes_run: u32,
+    applied: Vec<OptimizationPass>,
+    // the code after every pass that has changed it, if enabled
+    trace: Option<Vec<(OptimizationPass, Vec<Statement>)>>,
+    // set once a loop that never terminates is found
+    infinite_loop: bool,
+}
+
+impl Optimizer {
+    fn new(statements: Vec<Statement>) -> Self {
+        Self {
+            statements,
+            loop_semantics: LoopSemantics::While,
+            zeroed_tape: true,
+            wide_cells: false,
+            saturating: false,
+            config: OptimizerConfig::default(),
+            extra_passes: Vec::new(),
+            passes_run: 0,
+            applied: Vec::new(),
+            trace: None,
+            infinite_loop: false,
+        }
+    }
+
+    fn with_loop_semantics(mut self, loop_semantics: LoopSemantics) -> Self {
+        self.loop_semantics = loop_semantics;
+        self
+    }
+
+    fn with_zeroed_tape(mut self, zeroed_tape: bool) -> Self {
+        self.zeroed_tape = zeroed_tape;
+        self
+    }
+
+    fn with_wide_cells(mut self, wide_cells: bool) -> Self {
+        self.wide_cells = wide_cells;
+        self
+    }
+
+    fn with_saturating(mut self, saturating: bool) -> Self {
+        self.saturating = saturating;
+        self
+    }
+
+    fn with_config(mut self, config: OptimizerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Cell deltas wrap modulo 256, like the cells themselves, while move
+    // counts are kept whole.
+    fn generate_optimized_stmt(stmt_type: &Statement, value: &mut usize) -> Option<Statement> {
+        let result = match value {
+            0 => None,
+            _ => match stmt_type {
+                Statement::Add(_) => Some(Statement::Add((*value % 256) as u8)),
+                Statement::MoveLeft(_) => Some(Statement::MoveLeft(*value)),
+                Statement::MoveRight(_) => Some(Statement::MoveRight(*value)),
+                _ => None,
+            },
+        };
+        *value = 0;
+        result
+    }
+
+    // Runs of moves are only folded when they go in the same direction. A
+    // move is a potential trap (the pointer can leave the tape), so folding
+    // `>>><<<<` into `<` could let a run survive, and print more output,
+    // where the original code would have been stopped. With `wide_cells` set,
+    // additions are only folded while their sum fits a single delta, i.e.
+    // stays within -128..=127, as the cells don't wrap modulo 256. With
+    // `saturating` set, only additions of the same sign are folded as well,
+    // since `+-` doesn't cancel out on a saturated cell.
+    fn optimize_rec(
+        statements: &Vec<Statement>,
+        wide_cells: bool,
+        saturating: bool,
+        changed: &mut bool,
+    ) -> Option<Vec<Statement>> {
+        let mut result: Vec<Statement> = Vec::new();
+        let mut stmt_count: usize = 0;
+        // a reference, so that loops aren't cloned just for the bookkeeping
+        let mut last_statement: Option<&Statement> = None;
+
+        for statement in statements {
+            let same_type = last_statement.is_some_and(|last| statement.is_equal_type(last))
+                && match statement {
+                    Statement::Add(value) if saturating => {
+                        let (sum, value) = (stmt_count as u8 as i8, *value as i8);
+                        (sum < 0) == (value < 0) && sum.checked_add(value).is_some()
+                    }
+                    Statement::Add(value) if wide_cells => {
+                        (stmt_count as u8 as i8).checked_add(*value as i8).is_some()
+                    }
+                    _ => true,
+                };
+            // the statement is either merged into the previous one or dropped
+            *changed |= matches!(
+                statement,
+                Statement::Add(_) | Statement::MoveLeft(_) | Statement::MoveRight(_)
+            ) && (same_type || matches!(statement, Statement::Add(0)));
+            // the pending run is flushed before anything else, loops included,
+            // so the counts never move across a loop boundary
+            if let (false, Some(last)) = (same_type, last_statement) {
+                if let Some(statement) = Self::generate_optimized_stmt(last, &mut stmt_count) {
+                    result.push(statement);
+                }
+            }
+            match statement {
+                Statement::MoveLeft(value) | Statement::MoveRight(value) => match same_type {
+                    true => stmt_count += value,
+                    false => stmt_count = *value,
+                },
+                Statement::Add(value) => match same_type {
+                    true => stmt_count = value.wrapping_add(stmt_count as u8) as usize,
+                    false => stmt_count = *value as usize,
+                },
+                stmt @ (Statement::PutChar
+                | Statement::ReadChar
+                | Statement::SideEffect(_)
+                | Statement::LoadTapeSize
+                | Statement::Random
+                | Statement::Comment(_)
+                | Statement::ZeroCells(_)
+                | Statement::FillValue { .. }) => result.push(stmt.clone()),
+                Statement::Loop(code) => {
+                    if let Some(optimized) =
+                        Self::optimize_rec(code, wide_cells, saturating, changed)
+                    {
+                        result.push(Statement::new_loop(optimized));
+                    }
+                }
+            }
+            last_statement = Some(statement);
+        }
+        if let Some(last) = last_statement {
+            if let Some(statement) = Self::generate_optimized_stmt(last, &mut stmt_count) {
+                result.push(statement);
+            }
+        }
+        Some(result)
+    }
+
+    // Like all the other passes, returns whether the code was changed.
+    fn optimize_once(&mut self) -> bool {
+        let mut changed = false;
+        let opt_result = Self::optimize_rec(
+            &self.statements,
+            self.wide_cells,
+            self.saturating,
+            &mut changed,
+        );
+        self.statements = opt_result.unwrap_or_default();
+        changed
+    }
+
+    // Zero-cell analysis: `known` is the value of the current cell, if it is
+    // known, and `is_pristine` tells whether the whole tape is still in its
+    // initial, zeroed state. A loop entered with a zero guard never runs, so
+    // it can be removed; any other loop is kept, even an empty one, since
+    // `[]` with a non-zero guard is an intentional infinite loop. Loops only
+    // adding to a known value that never wraps to zero are reported through
+    // `infinite`, along with the loops entered with a known non-zero value
+    // that only move the pointer back and forth, as their guard never
+    // changes (they can only be stopped by leaving the tape). With
+    // `saturating` set, a loop entered with a known non-zero value is also
+    // reported when none of the amounts added to its guard by an iteration
+    // subtracts (see `Statement::guard_deltas`), as the guard then only
+    // grows or stays put. With `optimize_io` unset, dead loops performing IO
+    // are kept as well. With `wide_cells` set, the values are only known
+    // modulo 256, so they are forgotten after an addition.
+    fn remove_dead_loops_rec(
+        &self,
+        statements: &Vec<Statement>,
+        mut known: Option<u8>,
+        mut is_pristine: bool,
+        changed: &mut bool,
+        infinite: &mut bool,
+    ) -> Vec<Statement> {
+        let mut result: Vec<Statement> = Vec::new();
+        for statement in statements {
+            match statement {
+                Statement::MoveLeft(_) | Statement::MoveRight(_) => {
+                    known = is_pristine.then_some(0)
+                }
+                Statement::Add(0)
+                | Statement::PutChar
+                | Statement::SideEffect(_)
+                | Statement::Comment(_) => {}
+                Statement::ZeroCells(offsets) => {
+                    if offsets.contains(&0) {
+                        known = Some(0);
+                    }
+                }
+                Statement::FillValue { from, to, value } => {
+                    if (*from..*to).contains(&0) {
+                        known = Some(*value);
+                    }
+                    is_pristine = false;
+                }
+                Statement::Add(value) => {
+                    known = known.filter(|_| !self.wide_cells).map(|known| {
+                        match (self.saturating, (*value as i8) < 0) {
+                            (false, _) => known.wrapping_add(*value),
+                            (true, false) => known.saturating_add(*value),
+                            (true, true) => known.saturating_sub(value.wrapping_neg()),
+                        }
+                    });
+                    is_pristine = false;
+                }
+                Statement::ReadChar | Statement::LoadTapeSize | Statement::Random => {
+                    known = None;
+                    is_pristine = false;
+                }
+                Statement::Loop(code) => {
+                    if known != Some(0) {
+                        let value = statement.add_loop_value();
+                        if let (Some(value), Some(start), false) = (value, known, self.saturating) {
+                            *infinite |= Statement::add_loop_trips(value, start).is_none();
+                        }
+                        *infinite |= known.is_some() && statement.is_balanced_scan();
+                        if let (Some(deltas), Some(_), true) =
+                            (statement.guard_deltas(), known, self.saturating)
+                        {
+                            *infinite |= deltas.iter().all(|delta| (*delta as i8) >= 0);
+                        }
+                        let body = self.remove_dead_loops_rec(code, None, false, changed, infinite);
+                        result.push(Statement::new_loop(body));
+                        // a loop can only be left with a zero guard
+                        known = Some(0);
+                        is_pristine = false;
+                    } else if !self.config.optimize_io && Statement::performs_io(code) {
+                        result.push(statement.clone());
+                    } else {
+                        *changed = true;
+                    }
+                    continue;
+                }
+            }
+            result.push(statement.clone());
+        }
+        result
+    }
+
+    /// Removes the loops that are never entered, assuming that the code is
+    /// run from the start (on a zeroed tape, unless stated otherwise). Under
+    /// [`LoopSemantics::DoWhile`] every loop is entered at least once, so
+    /// nothing is removed.
+    fn remove_dead_loops(&mut self) -> bool {
+        let mut changed = false;
+        if self.loop_semantics == LoopSemantics::While {
+            let mut infinite = self.infinite_loop;
+            self.statements = self.remove_dead_loops_rec(
+                &self.statements,
+                self.zeroed_tape.then_some(0),
+                self.zeroed_tape,
+                &mut changed,
+                &mut infinite,
+            );
+            self.infinite_loop = infinite;
+        }
+        changed
+    }
+
+    // Length and cleared offsets of the longest run of clears and moves at
+    // the start of `statements` that leaves the pointer where it started.
+    // Only runs starting with a clear of the current cell are matched, and
+    // only if they don't move past the outermost cleared cells, so that the
+    // fused clears hit the ends of the tape exactly when the run would.
+    fn match_clears(statements: &[Statement]) -> Option<(usize, Vec<isize>)> {
+        let mut offset: isize = 0;
+        let (mut lowest, mut highest): (isize, isize) = (0, 0);
+        let mut offsets: Vec<isize> = Vec::new();
+        let mut matched = None;
+        for (index, statement) in statements.iter().enumerate() {
+            match statement {
+                Statement::MoveLeft(value) => offset -= *value as isize,
+                Statement::MoveRight(value) => offset += *value as isize,
+                Statement::ZeroCells(cleared) => {
+                    offsets.extend(cleared.iter().map(|cleared| cleared + offset))
+                }
+                _ if statement.is_clear_loop() => offsets.push(offset),
+                _ => break,
+            }
+            if index == 0 && offsets.is_empty() {
+                return None;
+            }
+            lowest = lowest.min(offset);
+            highest = highest.max(offset);
+            let within = |bound: isize| offsets.contains(&bound);
+            if offset == 0 && within(lowest) && within(highest) {
+                matched = Some((index + 1, offsets.clone()));
+            }
+        }
+        let (length, mut offsets) = matched?;
+        offsets.sort_unstable();
+        offsets.dedup();
+        match offsets.len() {
+            0 | 1 => None,
+            _ => Some((length, offsets)),
+        }
+    }
+
+    fn fuse_clears_rec(statements: &[Statement], changed: &mut bool) -> Vec<Statement> {
+        let mut result: Vec<Statement> = Vec::new();
+        let mut index = 0;
+        while index < statements.len() {
+            if let Some((length, offsets)) = Self::match_clears(&statements[index..]) {
+                // a lone `ZeroCells` is matched as it is
+                *changed |= length > 1;
+                result.push(Statement::ZeroCells(offsets));
+                index += length;
+                continue;
+            }
+            match &statements[index] {
+                Statement::Loop(code) => {
+                    result.push(Statement::new_loop(Self::fuse_clears_rec(code, changed)));
+                }
+                statement => result.push(statement.clone()),
+            }
+            index += 1;
+        }
+        result
+    }
+
+    fn fuse_clears(&mut self) -> bool {
+        let mut changed = false;
+        self.statements = Self::fuse_clears_rec(&self.statements, &mut changed);
+        changed
+    }
+
+    // Length and region of the longest run of clears, additions and moves at
+    // the start of `statements` that leaves the pointer where it started and
+    // sets a contiguous region of at least two cells to the same non-zero
+    // value, as `(length, FillValue)`. Only the additions to cells that were
+    // cleared by the run are matched, the other ones depend on the data. As
+    // with `match_clears`, the run mustn't move past the ends of the region
+    // (or the current cell, which is always on the tape).
+    fn match_fill(statements: &[Statement]) -> Option<(usize, Statement)> {
+        let mut offset: isize = 0;
+        let (mut lowest, mut highest): (isize, isize) = (0, 0);
+        let mut cells: BTreeMap<isize, u8> = BTreeMap::new();
+        let mut matched = None;
+        for (index, statement) in statements.iter().enumerate() {
+            match statement {
+                Statement::MoveLeft(value) => offset -= *value as isize,
+                Statement::MoveRight(value) => offset += *value as isize,
+                Statement::ZeroCells(cleared) => {
+                    cells.extend(cleared.iter().map(|cleared| (cleared + offset, 0)))
+                }
+                Statement::Add(value) => match cells.get_mut(&offset) {
+                    Some(cell) => *cell = cell.wrapping_add(*value),
+                    None => break,
+                },
+                _ if statement.is_clear_loop() => {
+                    cells.insert(offset, 0);
+                }
+                _ => break,
+            }
+            lowest = lowest.min(offset);
+            highest = highest.max(offset);
+            if offset != 0 {
+                continue;
+            }
+            let (Some((&from, &value)), Some((&last, _))) =
+                (cells.first_key_value(), cells.last_key_value())
+            else {
+                continue;
+            };
+            let contiguous = last - from + 1 == cells.len() as isize;
+            if contiguous
+                && cells.len() > 1
+                && value != 0
+                && cells.values().all(|&cell| cell == value)
+                && from.min(0) <= lowest
+                && highest <= last.max(0)
+            {
+                let to = last + 1;
+                matched = Some((index + 1, Statement::FillValue { from, to, value }));
+            }
+        }
+        matched
+    }
+
+    // A loop whose body fills a region next to its guard cell and adds an odd
+    // value to the guard always terminates, and every iteration writes the
+    // same values, so a single iteration clearing the guard is enough.
+    fn fill_loop_once(code: &[Statement]) -> Option<Vec<Statement>> {
+        let (fill, guard) = match code {
+            [fill @ Statement::FillValue { .. }, Statement::Add(guard)]
+            | [Statement::Add(guard), fill @ Statement::FillValue { .. }] => (fill, guard),
+            _ => return None,
+        };
+        match fill {
+            Statement::FillValue { from, to, .. }
+                if guard % 2 == 1 && !(*from..*to).contains(&0) =>
+            {
+                Some(vec![fill.clone(), Statement::ZeroCells(vec![0])])
+            }
+            _ => None,
+        }
+    }
+
+    fn fill_regions_rec(statements: &[Statement], changed: &mut bool) -> Vec<Statement> {
+        let mut result: Vec<Statement> = Vec::new();
+        let mut index = 0;
+        while index < statements.len() {
+            if let Some((length, fill)) = Self::match_fill(&statements[index..]) {
+                *changed = true;
+                result.push(fill);
+                index += length;
+                continue;
+            }
+            match &statements[index] {
+                Statement::Loop(code) => {
+                    let mut body = Self::fill_regions_rec(code, changed);
+                    if let Some(once) = Self::fill_loop_once(&body) {
+                        *changed = true;
+                        body = once;
+                    }
+                    result.push(Statement::new_loop(body));
+                }
+                statement => result.push(statement.clone()),
+            }
+            index += 1;
+        }
+        result
+    }
+
+    fn fill_regions(&mut self) -> bool {
+        let mut changed = false;
+        self.statements = Self::fill_regions_rec(&self.statements, &mut changed);
+        changed
+    }
+
+    // Runs a single iteration of the passes enabled in the config, returns
+    // whether any of them changed the code.
+    fn run_passes(&mut self) -> bool {
+        let mut changed = false;
+        for kind in OptimizationPass::BUILT_IN {
+            let Some((true, pass)) = self.built_in_pass(kind) else {
+                continue;
+            };
+            self.passes_run += 1;
+            if pass(self) {
+                self.record(kind);
+                changed = true;
+            }
+        }
+        for (index, pass) in self.config.custom_passes.clone().iter().enumerate() {
+            self.passes_run += 1;
+            let statements = (pass.0)(&self.statements);
+            if statements != self.statements {
+                self.statements = statements;
+                self.record(OptimizationPass::Custom(index));
+                changed = true;
+            }
+        }
+        for pass in self.extra_passes.clone() {
+            self.passes_run += 1;
+            changed |= pass(self);
+        }
+        changed
+    }
+
+    // Whether a built-in pass is enabled in the config, and its function.
+    fn built_in_pass(&self, kind: OptimizationPass) -> Option<(bool, Pass)> {
+        match kind {
+            OptimizationPass::Fold => Some((self.config.fold, Self::optimize_once)),
+            OptimizationPass::FuseClears => Some((self.config.fuse_clears, Self::fuse_clears)),
+            OptimizationPass::FillRegions => Some((self.config.fill_regions, Self::fill_regions)),
+            OptimizationPass::RemoveDeadLoops => {
+                Some((self.config.remove_dead_loops, Self::remove_dead_loops))
+            }
+            OptimizationPass::Custom(_) => None,
+        }
+    }
+
+    fn record(&mut self, pass: OptimizationPass) {
+        self.applied.push(pass);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push((pass, self.statements.clone()));
+        }
+    }
+
+    fn hash_statements(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.statements.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Runs the passes until the code stops changing, but no more than
+    // `max_iterations` times (or `MAX_OPTIMIZATION_ITERATIONS` times when
+    // it is zero). The hashes of the visited states are kept to detect
+    // passes undoing each other's work; a hash collision can only make the
+    // optimization stop early.
+    fn optimize(&mut self, max_iterations: u32) -> OptimizationReport {
+        let limit = match max_iterations {
+            0 => MAX_OPTIMIZATION_ITERATIONS,
+            _ => max_iterations,
+        };
+        let mut report = OptimizationReport::default();
+        let mut visited = HashSet::from([self.hash_statements()]);
+        while report.iterations < limit {
+            report.iterations += 1;
+            if !self.run_passes() {
+                report.converged = true;
+                break;
+            }
+            if !visited.insert(self.hash_statements()) {
+                report.warnings.push(format!(
+                    "The optimization passes oscillate, stopped after {} iterations.",
+                    report.iterations
+                ));
+                break;
+            }
+        }
+        if max_iterations == 0 && !report.converged && report.warnings.is_empty() {
+            report.warnings.push(format!(
+                "The optimization didn't converge within {} iterations.",
+                MAX_OPTIMIZATION_ITERATIONS
+            ));
+        }
+        if self.infinite_loop {
+            report.termination = TerminationVerdict::GuaranteedInfinite;
+        }
+        report
+    }
+
+    fn yield_back(self) -> Vec<Statement> {
+        self.statements
+    }
+}
+
+/// A table of notional costs assigned to each kind of statement. It is used
+/// to accumulate a deterministic "cycle" count during a run, which allows
+/// comparing the efficiency of programs without the noise of wall-clock
+/// measurements.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CostTable {
+    /// Cost of a single (possibly folded) pointer move.
+    pub moves: u64,
+    /// Cost of a single (possibly folded) cell value change.
+    pub add: u64,
+    /// Cost of a single loop guard check.
+    pub loop_branch: u64,
+    /// Cost of a single input or output operation.
+    pub io: u64,
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self {
+            moves: 1,
+            add: 1,
+            loop_branch: 2,
+            io: 10,
+        }
+    }
+}
+
+impl CostTable {
+    fn cost_of(&self, statement: &Statement) -> u64 {
+        match statement {
+            Statement::MoveLeft(_) | Statement::MoveRight(_) => self.moves,
+            Statement::Add(_) | Statement::LoadTapeSize | Statement::Random => self.add,
+            Statement::Loop(_) => self.loop_branch,
+            Statement::PutChar | Statement::ReadChar | Statement::SideEffect(_) => self.io,
+            Statement::ZeroCells(_) | Statement::FillValue { .. } => self.add,
+            Statement::Comment(_) => 0,
+        }
+    }
+}
+
+/// Semantics of the loops executed by an [`Interpreter`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum LoopSemantics {
+    /// Standard brainfuck loops: the guard cell is checked before every
+    /// iteration, including the first one.
+    #[default]
+    While,
+    /// Non-standard loops whose body always runs at least once: the guard
+    /// cell is only checked after every iteration.
+    DoWhile,
+}
+
+/// Value stored in the current cell when `,` is executed after the input has
+/// been exhausted.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum EofPolicy {
+    /// The cell is left unchanged.
+    #[default]
+    Unchanged,
+    /// The cell is set to `0`.
+    Zero,
+    /// The cell is set to its largest value, i.e. `-1` with wrapping (`255`
+    /// for the byte cells).
+    Max,
+}
+
+/// Translation of the newlines written by `.`, e.g. for consumers expecting
+/// the line endings of a given platform.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum NewlineTranslation {
+    /// The bytes are written as they are.
+    #[default]
+    None,
+    /// Every `\n` is written as `\r\n`.
+    LfToCrlf,
+    /// Every `\r\n` is written as `\n`, the other `\r` are kept.
+    CrlfToLf,
+}
+
+/// Source of the bytes read by `,`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum InputSource {
+    /// Bytes are read from the standard input, or from the reader set with
+    /// [`Interpreter::with_input`].
+    #[default]
+    Stream,
+    /// Bytes are read one by one from a region of the tape, which acts as an
+    /// input queue. The region has its own cursor, independent of the
+    /// header, and is exhausted after `len` reads.
+    TapeRegion {
+        /// Position of the first cell of the region, relative to the cell
+        /// the header started at, so the region stays in place when the
+        /// tape grows to the left (see [`TapePolicy::GrowBoth`]).
+        start: usize,
+        /// Amount of cells in the region.
+        len: usize,
+    },
+}
+
+/// Policy applied when writing the program's output fails.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OnWriteError {
+    /// Stops the run as if the program has ended, without an error. This is
+    /// useful when the downstream end of a pipe has been closed, for example
+    /// by `head`.
+    Stop,
+    /// Stops the run and returns the write error.
+    Error,
+    /// Ignores the error and continues the run, the output is lost.
+    Ignore,
+}
+
+/// Side effects that can be triggered by the dialect commands set with
+/// [`Interpreter::with_side_effect`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SideEffectKind {
+    /// Rings the terminal bell, i.e. writes `0x07` to the output.
+    Bell,
+    /// Calls the handler set with [`Interpreter::with_side_effect_handler`],
+    /// the value can be used to tell different commands apart.
+    Custom(u8),
+}
+
+/// A range of bytes of the source code, `end` being exclusive.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// Offset of the first byte.
+    pub start: usize,
+    /// Offset one past the last byte.
+    pub end: usize,
+}
+
+/// The deepest loop nesting accepted by the parser. The code is parsed,
+/// optimized and run recursively, so deeper loops could overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 1000;
+
+/// Errors of the parsed brainfuck code. They are returned wrapped in an
+/// [`std::io::Error`] of kind [`ErrorKind::InvalidData`] and can be recovered
+/// with [`std::io::Error::get_ref`] and a downcast.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// A `]` with no matching `[`. The code was read up to and including the
+    /// `]`, i.e. up to a given offset.
+    UnmatchedLoopEnd {
+        /// Amount of bytes of code read when the error was found.
+        offset: usize,
+    },
+    /// A `[` with no matching `]`. The whole code was read, i.e. up to a
+    /// given offset, along with the code terminator, if there is one.
+    UnmatchedLoopStart {
+        /// Amount of bytes of code read when the error was found.
+        offset: usize,
+    },
+    /// A `[` nested deeper than [`MAX_NESTING_DEPTH`] loops. The code was
+    /// read up to and including the `[`, i.e. up to a given offset.
+    NestingTooDeep {
+        /// Amount of bytes of code read when the error was found.
+        offset: usize,
+    },
+    /// The code has more than `count` statements, counting the loops and
+    /// the statements nested in them, see
+    /// [`Interpreter::with_max_statements`]. The code was read up to and
+    /// including the first statement over the limit, i.e. up to a given
+    /// offset.
+    ProgramTooLarge {
+        /// The maximum amount of statements.
+        count: usize,
+        /// Amount of bytes of code read when the error was found.
+        offset: usize,
+    },
+}
+
+impl ParseError {
+    /// Returns the amount of bytes of code read when the error was found.
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::UnmatchedLoopEnd { offset }
+            | Self::UnmatchedLoopStart { offset }
+            | Self::NestingTooDeep { offset }
+            | Self::ProgramTooLarge { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnmatchedLoopEnd { .. } => write!(f, "Error: ']' found with no matching '['."),
+            Self::UnmatchedLoopStart { .. } => {
+                write!(f, "Error: '[' found with no matching ']'.")
+            }
+            Self::NestingTooDeep { .. } => write!(
+                f,
+                "Error: the loops are nested deeper than {} levels.",
+                MAX_NESTING_DEPTH
+            ),
+            Self::ProgramTooLarge { count, .. } => {
+                write!(f, "Error: the code has more than {} statements.", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+/// Severity of a [`Diagnostic`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Severity {
+    /// A problem that keeps the code from being run.
+    Error,
+    /// Suspicious code that can still be run.
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found in the source code by [`diagnose`], e.g. to be shown in
+/// an editor.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// Description of the problem.
+    pub message: String,
+    /// Line of the start of the span, counted from 1.
+    pub line: usize,
+    /// Column of the start of the span, in bytes and counted from 1.
+    pub column: usize,
+    /// The code with the problem.
+    pub span: Span,
+}
+
+impl Diagnostic {
+    fn new(code: &[u8], severity: Severity, message: String, span: Span) -> Self {
+        let before = &code[..span.start];
+        let line_start = before.iter().rposition(|&byte| byte == b'\n');
+        Self {
+            severity,
+            message,
+            line: before.iter().filter(|&&byte| byte == b'\n').count() + 1,
+            column: span.start - line_start.map_or(0, |index| index + 1) + 1,
+            span,
+        }
+    }
+
+    /// Formats the diagnostic as a JSON object with the `severity`,
+    /// `message`, `line`, `column` and `span` (with `start` and `end`) keys.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"severity\":\"{}\",\"message\":{},\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}}}}",
+            self.severity.as_str(),
+            json_string(&self.message),
+            self.line,
+            self.column,
+            self.span.start,
+            self.span.end
+        )
+    }
+}
+
+// Quotes a string for JSON, escaping the quotes, backslashes and control
+// characters.
+fn json_string(text: &str) -> String {
+    let mut result = String::from('"');
+    for character in text.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            character if character.is_control() => {
+                result.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => result.push(character),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Finds the problems in the given code, without running it: unmatched
+/// brackets are reported as errors and loops that move the pointer by the
+/// same amount on every iteration as warnings. With `strict` set, any text
+/// other than the commands and whitespace is reported as an error as well,
+/// instead of being treated as a comment. The diagnostics are sorted by
+/// their position in the code.
+pub fn diagnose(code: &[u8], strict: bool) -> Vec<Diagnostic> {
+    let span = |start: usize, end: usize| Span { start, end };
+    let mut diagnostics = Vec::new();
+    // the open loops, each with its start and the pointer movement of its
+    // body so far, `None` once it depends on the data
+    let mut loops: Vec<(usize, Option<isize>)> = Vec::new();
+    let mut unexpected: Option<usize> = None;
+    for (index, &byte) in code.iter().enumerate() {
+        let is_expected = b"+-<>[].,".contains(&byte) || byte.is_ascii_whitespace();
+        match unexpected {
+            Some(start) if is_expected => {
+                let text = String::from_utf8_lossy(&code[start..index]);
+                let message = format!("Unexpected text \"{}\".", text);
+                diagnostics.push(Diagnostic::new(
+                    code,
+                    Severity::Error,
+                    message,
+                    span(start, index),
+                ));
+                unexpected = None;
+            }
+            None if strict && !is_expected => unexpected = Some(index),
+            _ => {}
+        }
+        let shift = match byte {
+            b'<' => -1,
+            b'>' => 1,
+            _ => 0,
+        };
+        if let Some((_, Some(moves))) = loops.last_mut() {
+            *moves += shift;
+        }
+        match byte {
+            b'[' => loops.push((index, Some(0))),
+            b']' => match loops.pop() {
+                Some((start, moves)) => {
+                    if let Some(moves @ (..=-1 | 1..)) = moves {
+                        let message = format!(
+                            "The loop moves the pointer by {} on every iteration.",
+                            moves
+                        );
+                        diagnostics.push(Diagnostic::new(
+                            code,
+                            Severity::Warning,
+                            message,
+                            span(start, index + 1),
+                        ));
+                    }
+                    if moves != Some(0) {
+                        if let Some((_, outer)) = loops.last_mut() {
+                            *outer = None;
+                        }
+                    }
+                }
+                None => diagnostics.push(Diagnostic::new(
+                    code,
+                    Severity::Error,
+                    "']' found with no matching '['.".to_string(),
+                    span(index, index + 1),
+                )),
+            },
+            _ => {}
+        }
+    }
+    if let Some(start) = unexpected {
+        let text = String::from_utf8_lossy(&code[start..]);
+        let message = format!("Unexpected text \"{}\".", text);
+        diagnostics.push(Diagnostic::new(
+            code,
+            Severity::Error,
+            message,
+            span(start, code.len()),
+        ));
+    }
+    for (start, _) in loops {
+        diagnostics.push(Diagnostic::new(
+            code,
+            Severity::Error,
+            "'[' found with no matching ']'.".to_string(),
+            span(start, start + 1),
+        ));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+/// Formats diagnostics as a JSON array of the objects described in
+/// [`Diagnostic::to_json`].
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic]) -> String {
+    let objects: Vec<String> = diagnostics.iter().map(Diagnostic::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// Errors of the executed brainfuck programs. They are returned wrapped in
+/// an [`std::io::Error`] of kind [`ErrorKind::Other`] and can be recovered
+/// with [`std::io::Error::get_ref`] and a downcast.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BrainfuckError {
+    /// The pointer would be moved past the last cell of the tape.
+    TapeOverflow {
+        /// Index of the pointer before the move.
+        index: usize,
+        /// Length of the move.
+        shift: usize,
+        /// The command that made the move, known only for unoptimized runs.
+        span: Option<Span>,
+    },
+    /// The pointer would be moved left of the first cell of the tape.
+    TapeUnderflow {
+        /// Index of the pointer before the move.
+        index: usize,
+        /// Length of the move.
+        shift: usize,
+        /// The command that made the move, known only for unoptimized runs.
+        span: Option<Span>,
+    },
+    /// The pointer would be moved past the limit set with
+    /// [`Interpreter::with_max_pointer`]. Converted into an
+    /// [`std::io::Error`], it is of kind [`ErrorKind::PermissionDenied`].
+    PointerLimitExceeded {
+        /// The furthest position that the pointer may reach.
+        limit: usize,
+        /// Index of the pointer before the move.
+        index: usize,
+        /// Length of the move.
+        shift: usize,
+        /// The command that made the move, known only for unoptimized runs.
+        span: Option<Span>,
+    },
+    /// A cell past the last one of the tape would be accessed, see
+    /// [`MachineError::CellOutOfBounds`]. Converted into an
+    /// [`std::io::Error`], it is of kind [`ErrorKind::InvalidInput`].
+    CellOutOfBounds {
+        /// Index of the cell.
+        index: usize,
+        /// Size of the tape.
+        size: usize,
+    },
+}
+
+impl BrainfuckError {
+    fn set_span(&mut self, new_span: Span) {
+        match self {
+            Self::TapeOverflow { span, .. }
+            | Self::TapeUnderflow { span, .. }
+            | Self::PointerLimitExceeded { span, .. } => *span = Some(new_span),
+            // no command is involved
+            Self::CellOutOfBounds { .. } => {}
+        }
+    }
+}
+
+impl fmt::Display for BrainfuckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = match self {
+            Self::TapeOverflow { index, shift, span } => {
+                write!(
+                    f,
+                    "Error: moving the pointer right by {} from cell {} leaves the tape",
+                    shift, index
+                )?;
+                span
+            }
+            Self::TapeUnderflow { index, shift, span } => {
+                write!(
+                    f,
+                    "Error: moving the pointer left by {} from cell {} leaves the tape",
+                    shift, index
+                )?;
+                span
+            }
+            Self::PointerLimitExceeded {
+                limit,
+                index,
+                shift,
+                span,
+            } => {
+                write!(
+                    f,
+                    "Error: moving the pointer right by {} from cell {} exceeds the pointer \
+                     limit of {}",
+                    shift, index, limit
+                )?;
+                span
+            }
+            Self::CellOutOfBounds { index, size } => {
+                return write!(
+                    f,
+                    "Error: cell {} is past the end of the tape of {} cells.",
+                    index, size
+                )
+            }
+        };
+        match span {
+            Some(span) => write!(f, " (at byte {}).", span.start),
+            None => write!(f, "."),
+        }
+    }
+}
+
+impl std::error::Error for BrainfuckError {}
+
+// The interpreter reports the moves past the tape with the commands that
+// made them, which the machine doesn't know.
+impl From<MachineError> for BrainfuckError {
+    fn from(error: MachineError) -> Self {
+        match error {
+            MachineError::CellOutOfBounds { index, size } => Self::CellOutOfBounds { index, size },
+            MachineError::Overflow { index, shift, .. } => Self::TapeOverflow {
+                index,
+                shift,
+                span: None,
+            },
+            MachineError::Underflow { index, shift, .. } => Self::TapeUnderflow {
+                index,
+                shift,
+                span: None,
+            },
+        }
+    }
+}
+
+impl From<BrainfuckError> for Error {
+    fn from(error: BrainfuckError) -> Self {
+        match error {
+            BrainfuckError::PointerLimitExceeded { .. } => {
+                Error::new(ErrorKind::PermissionDenied, error)
+            }
+            BrainfuckError::CellOutOfBounds { .. } => Error::new(ErrorKind::InvalidInput, error),
+            _ => Error::other(error),
+        }
+    }
+}
+
+/// The reason why a run of an [`Interpreter`] has stopped without an error.
+#[must_use]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Termination {
+    /// The program has ended.
+    Completed,
+    /// The limit set with [`Interpreter::with_step_limit`] was reached.
+    StepLimit,
+    /// The limit set with [`Interpreter::with_output_limit`] was reached.
+    OutputLimit,
+    /// The time set with [`Interpreter::with_timeout`] has run out.
+    Timeout,
+    /// Writing the output failed and the run was stopped, see
+    /// [`OnWriteError::Stop`].
+    Halted,
+    /// The flag set with [`Interpreter::with_interrupt`] was raised and
+    /// there is no handler for it. The tape and the pointer are left as they
+    /// were at the loop guard check where the flag was noticed.
+    Interrupted,
+    /// The run has been paused before its next statement by
+    /// [`Interpreter::step_into`], [`Interpreter::step_over`] or
+    /// [`Interpreter::step_out`], which continue it when called again.
+    Paused,
+}
+
+/// Statistics gathered during the last run of an [`Interpreter`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExecStats {
+    /// Amount of executed statements and loop iterations.
+    pub steps: u64,
+    /// Total notional cost of the run, computed using the [`CostTable`] set
+    /// on the interpreter.
+    pub cycles: u64,
+    /// The deepest loop nesting actually reached during the run, i.e. the
+    /// maximum recursion depth of the interpreter.
+    pub max_depth: usize,
+    /// The furthest cell index the pointer reached during the run.
+    pub max_pointer: usize,
+    /// The time spent on each kind of statement, only measured if
+    /// [`Interpreter::with_statement_timing`] is set.
+    pub timings: Option<StatementTimings>,
+    /// The time spent on each phase of the run.
+    pub phases: PhaseTimings,
+}
+
+/// The time spent on each phase of a run. The phases that were skipped,
+/// e.g. the parsing of code that was already parsed by an earlier run, took
+/// no time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhaseTimings {
+    /// Time spent on parsing the code.
+    pub parse: Duration,
+    /// Time spent on optimizing the code, see
+    /// [`Interpreter::run_with_optimization`].
+    pub optimize: Duration,
+    /// Time spent on executing the code, including the final flush of the
+    /// output.
+    pub execute: Duration,
+}
+
+/// How a run reported by a [`RunReport`] has ended.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RunOutcome {
+    /// The run stopped without an error, for a given reason.
+    Stopped(Termination),
+    /// The code failed to parse or the run failed.
+    Failed {
+        /// The description of the error.
+        message: String,
+        /// The command that raised the error, if known, see
+        /// [`BrainfuckError`].
+        span: Option<Span>,
+    },
+}
+
+impl RunOutcome {
+    fn failed(error: &Error) -> Self {
+        let span = match error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<BrainfuckError>())
+        {
+            Some(
+                BrainfuckError::TapeOverflow { span, .. }
+                | BrainfuckError::TapeUnderflow { span, .. }
+                | BrainfuckError::PointerLimitExceeded { span, .. },
+            ) => *span,
+            Some(BrainfuckError::CellOutOfBounds { .. }) | None => None,
+        };
+        Self::Failed {
+            message: error.to_string(),
+            span,
+        }
+    }
+}
+
+/// Everything known about a run, see [`Interpreter::run_full`]. With the
+/// `serde` feature it can be serialized, e.g. to be sent to a web page.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunReport<C: Cell = u8> {
+    /// How the run has ended.
+    pub outcome: RunOutcome,
+    /// The output of the program.
+    pub output: Vec<u8>,
+    /// The tape after the run.
+    pub tape: Vec<C>,
+    /// Index of the current cell after the run.
+    pub pointer: usize,
+    /// The statistics of the run, including its [`PhaseTimings`].
+    pub stats: ExecStats,
+    /// The report of the optimization, if the code was optimized.
+    pub optimization: Option<OptimizationReport>,
+}
+
+/// The state of a stopped run, to be continued later with
+/// [`Interpreter::resume`], see [`Interpreter::checkpoint`]. With the
+/// `serde` feature it can be serialized, e.g. to be saved to a file.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    /// The code that was run, optimized or not.
+    pub program: Vec<Statement>,
+    /// The tape at the time of the checkpoint.
+    pub tape: Vec<u8>,
+    /// Index of the current cell.
+    pub pointer: usize,
+    /// Position of the next statement to run, as the indices of the
+    /// statements on the way to it, starting with the outermost one. Every
+    /// index but the last one is a loop; a last index just past the end of
+    /// a loop's body stands for the check of the loop's guard.
+    pub pc: Vec<usize>,
+    /// Amount of steps run so far.
+    pub steps: u64,
+    /// State of the generator of [`Interpreter::with_random_command`].
+    pub random: u64,
+}
+
+/// The outputs of the runs memoized by [`Interpreter::with_memoization`],
+/// keyed by the [`Program::fingerprint`], a fingerprint of the settings of
+/// the interpreter affecting the output (e.g. the tape size and the limits)
+/// and the input of the run.
+pub type MemoCache = HashMap<(u64, u64, Vec<u8>), Vec<u8>>;
+
+// Where a run can be continued from, see `Checkpoint`.
+struct ResumePoint {
+    pc: Vec<usize>,
+    steps: u64,
+    random: u64,
+}
+
+const INVALID_CHECKPOINT: &str = "Error: the checkpoint doesn't match its program.";
+
+/// The time spent on each kind of statement during a run. Every statement
+/// only counts its own time, e.g. the statements inside of a loop count
+/// towards their own kinds, so the durations add up to the whole run.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatementTimings {
+    /// Time spent on changing the cells, i.e. `+`, `-` and the cells
+    /// cleared by the optimizer.
+    pub arithmetic: Duration,
+    /// Time spent on moving the pointer.
+    pub movement: Duration,
+    /// Time spent on reading the input.
+    pub input: Duration,
+    /// Time spent on writing and flushing the output, including the side
+    /// effects.
+    pub output: Duration,
+    /// Time spent on checking the loop guards.
+    pub loops: Duration,
+}
+
+impl StatementTimings {
+    /// Returns the total time of the run.
+    pub fn total(&self) -> Duration {
+        self.arithmetic + self.movement + self.input + self.output + self.loops
+    }
+
+    fn of_mut(&mut self, kind: TimedKind) -> &mut Duration {
+        match kind {
+            TimedKind::Arithmetic => &mut self.arithmetic,
+            TimedKind::Movement => &mut self.movement,
+            TimedKind::Input => &mut self.input,
+            TimedKind::Output => &mut self.output,
+            TimedKind::Loop => &mut self.loops,
+        }
+    }
+}
+
+// The kinds of statements that the time of a run is split into.
+#[derive(Copy, Clone)]
+enum TimedKind {
+    Arithmetic,
+    Movement,
+    Input,
+    Output,
+    Loop,
+}
+
+impl TimedKind {
+    fn of(statement: &Statement) -> Self {
+        match statement {
+            Statement::Add(_)
+            | Statement::ZeroCells(_)
+            | Statement::FillValue { .. }
+            | Statement::LoadTapeSize
+            | Statement::Random => Self::Arithmetic,
+            Statement::MoveLeft(_) | Statement::MoveRight(_) => Self::Movement,
+            Statement::ReadChar => Self::Input,
+            Statement::PutChar | Statement::SideEffect(_) => Self::Output,
+            // comments aren't steps, so they are never timed
+            Statement::Loop(_) | Statement::Comment(_) => Self::Loop,
+        }
+    }
+}
+
+// Counts the reads and the writes of every cell, by index, see
+// `Interpreter::with_access_counts`. The counts follow the tape as it grows,
+// `origin` being the index of the machine's starting cell they were kept
+// for.
+struct AccessCounts {
+    counts: Vec<u64>,
+    origin: usize,
+}
+
+impl AccessCounts {
+    fn record<S: Tape>(&mut self, machine: &BrainfuckMachine<S>, cells: Range<usize>, amount: u64) {
+        self.follow(machine);
+        for count in &mut self.counts[cells] {
+            *count += amount;
+        }
+    }
+
+    // Adds the cells the tape has grown by since the last call.
+    fn follow<S: Tape>(&mut self, machine: &BrainfuckMachine<S>) {
+        if self.counts.len() != machine.size {
+            let added = machine.origin - self.origin;
+            self.counts.splice(0..0, std::iter::repeat_n(0, added));
+            self.counts.resize(machine.size, 0);
+            self.origin = machine.origin;
+        }
+    }
+}
+
+// Measures the time between the steps, charging it to the kind of the
+// statement that was running. The clock only has to count up from any
+// starting point.
+struct StatementTimer {
+    clock: Box<dyn FnMut() -> Duration>,
+    lap_start: Duration,
+    running: Option<TimedKind>,
+}
+
+impl StatementTimer {
+    fn lap(&mut self, timings: &mut StatementTimings, next: Option<TimedKind>) {
+        let now = (self.clock)();
+        if let Some(kind) = self.running {
+            *timings.of_mut(kind) += now.saturating_sub(self.lap_start);
+        }
+        self.lap_start = now;
+        self.running = next;
+    }
+}
+
+// Switches the terminal between the canonical mode and the raw mode used to
+// read single keypresses.
+trait TerminalControl {
+    fn enable_raw_mode(&mut self);
+    fn disable_raw_mode(&mut self);
+}
+
+// Terminal control of the standard input, restores the settings that were in
+// place when it was created.
+struct StdinTerminal {
+    original: termios::Termios,
+}
+
+impl StdinTerminal {
+    fn new() -> Option<Self> {
+        let original = termios::Termios::from_fd(io::stdin().as_raw_fd()).ok()?;
+        Some(Self { original })
+    }
+}
+
+impl TerminalControl for StdinTerminal {
+    fn enable_raw_mode(&mut self) {
+        let mut raw = self.original;
+        raw.c_lflag &= !(termios::ICANON);
+        let _ = termios::tcsetattr(io::stdin().as_raw_fd(), termios::TCSANOW, &raw);
+    }
+    fn disable_raw_mode(&mut self) {
+        let _ = termios::tcsetattr(io::stdin().as_raw_fd(), termios::TCSANOW, &self.original);
+    }
+}
+
+// Keeps the terminal in raw mode for as long as it lives, so that the
+// terminal settings are restored even if the run is stopped by a panic.
+struct RawModeGuard {
+    terminal: Option<Box<dyn TerminalControl>>,
+}
+
+impl RawModeGuard {
+    fn new(mut terminal: Option<Box<dyn TerminalControl>>) -> Self {
+        if let Some(terminal) = terminal.as_mut() {
+            terminal.enable_raw_mode();
+        }
+        Self { terminal }
+    }
+
+    fn release(mut self) -> Option<Box<dyn TerminalControl>> {
+        let mut terminal = self.terminal.take();
+        if let Some(terminal) = terminal.as_mut() {
+            terminal.disable_raw_mode();
+        }
+        terminal
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Some(terminal) = self.terminal.as_mut() {
+            terminal.disable_raw_mode();
+        }
+    }
+}
+
+// The SplitMix64 generator, simple and fast enough for the `Random`
+// statements, and easy to reproduce, e.g. in the generated code.
+#[derive(Clone, Copy)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (value ^ (value >> 31)) as u8
+    }
+}
+
+// Writes a frame with a window of the tape around the pointer every
+// `interval` steps.
+struct TapeTrace {
+    output: Box<dyn Write>,
+    interval: u64,
+    window: usize,
+    frames: u64,
+}
+
+impl TapeTrace {
+    // A frame is a header line followed by the comma separated values of the
+    // cells in the window, e.g. `frame 1: step 4, pointer 2, cells 0..5`.
+    fn write_frame<S: Tape>(&mut self, machine: &BrainfuckMachine<S>, step: u64) -> Result<()> {
+        let start = machine
+            .index
+            .saturating_sub(self.window / 2)
+            .min(machine.size.saturating_sub(self.window));
+        let end = (start + self.window).min(machine.size);
+        let values: Vec<String> = (start..end)
+            .map(|index| machine.tape.get(index).to_string())
+            .collect();
+        self.frames += 1;
+        writeln!(
+            self.output,
+            "frame {}: step {}, pointer {}, cells {}..{}\n{}",
+            self.frames,
+            step,
+            machine.index,
+            start,
+            end,
+            values.join(",")
+        )
+    }
+}
+
+/// A brainfuck interpreter class that reads code from a file / [`BufRead`]
+/// instance, parses, optimizes and runs it.
+pub struct Interpreter<T: BufRead, S: Tape = VecTape> {
+    parser: Parser<T>,
+    statements: Option<Vec<Statement>>,
+    // the time it took to parse the code, until it's reported by a run
+    parse_time: Duration,
+    // the code run by the last call to `run_with_optimization`
+    optimized_statements: Option<Vec<Statement>>,
+    machine: BrainfuckMachine<S>,
+    terminal: Option<Box<dyn TerminalControl>>,
+    stdin_input: bool,
+    // the only reader of the program's input, the bytes that it buffers
+    // ahead (e.g. typed ahead by the user) are kept between the reads
+    input: BufReader<Box<dyn Read>>,
+    input_source: InputSource,
+    input_cursor: usize,
+    // asked for more input once the source is exhausted, the bytes that it
+    // supplies are read before asking again
+    input_provider: Option<Box<dyn FnMut() -> Option<Vec<u8>>>>,
+    provided_input: VecDeque<u8>,
+    eof_policy: EofPolicy,
+    output: Box<dyn Write>,
+    newline_translation: NewlineTranslation,
+    // a `\r` held back until it's known whether a `\n` follows
+    pending_cr: bool,
+    // the interpreter's own messages, kept apart from the program's output
+    messages: Box<dyn Write>,
+    step_limit: Option<u64>,
+    output_limit: Option<u64>,
+    output_written: u64,
+    // the running CRC-32 register of the output, if enabled
+    output_crc: Option<u32>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    max_pointer: Option<usize>,
+    costs: CostTable,
+    loop_semantics: LoopSemantics,
+    optimizer_config: OptimizerConfig,
+    optimization_report: OptimizationReport,
+    on_write_error: Option<OnWriteError>,
+    side_effect_handler: Option<Box<dyn FnMut(SideEffectKind)>>,
+    output_callback: Option<Box<dyn FnMut(u8)>>,
+    fault_path: Vec<usize>,
+    // the path to the statement that a run was stopped at, gathered like the
+    // fault path, and the steps run before it, if the run can be continued
+    stop_path: Vec<usize>,
+    stop_steps: Option<u64>,
+    stopped_at: Option<ResumePoint>,
+    resume_from: Option<ResumePoint>,
+    // the deepest loop nesting at which a stepped run pauses before a
+    // statement, along with the amount of steps at the start of the run
+    pause: Option<(usize, u64)>,
+    tape_trace: Option<TapeTrace>,
+    interrupt: Option<Arc<AtomicBool>>,
+    interrupt_handler: Option<Rc<[Statement]>>,
+    persistent_tape: bool,
+    // the seed is kept so that every run gets the same random bytes
+    random_seed: u64,
+    random: SplitMix64,
+    statement_timer: Option<StatementTimer>,
+    access_counts: Option<AccessCounts>,
+    memoization: Option<MemoCache>,
+    stats: ExecStats,
+}
+
+impl Interpreter<BufReader<File>> {
+    /// Creates a new [`Interpreter<BufReader<File>>`] instance wrapped in a
+    /// [`Result`] object. If there were any problems when reading a file
+    /// the function will return an [`std::io::Error`] instance.
+    pub fn from_file(file_name: &str, machine_size: usize) -> Result<Self> {
+        Self::from_file_with_machine(file_name, BrainfuckMachine::new(machine_size))
+    }
+
+    /// Runs the program from a given file with a tape of a given size,
+    /// feeding it the contents of another file as its input. Returns the
+    /// output of the program, or an [`std::io::Error`] if either of the files
+    /// can't be read or the run fails (see [`Interpreter::run`]).
+    pub fn run_with_input_file(
+        program_path: &str,
+        input_path: &str,
+        machine_size: usize,
+    ) -> Result<Vec<u8>> {
+        let input = File::open(input_path)?;
+        let output = CapturedOutput::default();
+        // writing to memory can't fail and there are no limits, so the run
+        // can only complete
+        let _ = Self::from_file(program_path, machine_size)?
+            .with_input(Box::new(input))
+            .with_output(Box::new(output.clone()))
+            .run()?;
+        Ok(output.take())
+    }
+}
+
+impl Interpreter<io::Empty> {
+    /// Creates an interpreter continuing the run saved in a given
+    /// [`Checkpoint`], with a tape of the saved size. The next call to
+    /// [`Interpreter::run`] runs the rest of the saved code, counting the
+    /// steps on from the saved amount (so a step limit applies to the whole
+    /// run); the input, the output and the other settings have to be set
+    /// again. Returns an [`std::io::Error`] if the checkpoint is
+    /// inconsistent, e.g. its position isn't in its program.
+    pub fn resume(checkpoint: Checkpoint) -> Result<Self> {
+        if checkpoint.pointer >= checkpoint.tape.len()
+            || !Statement::is_valid_pc(&checkpoint.program, &checkpoint.pc)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, INVALID_CHECKPOINT));
+        }
+        let machine = BrainfuckMachine::with_tape(VecTape(checkpoint.tape));
+        let mut interpreter = Self::from_machine(io::empty(), machine);
+        interpreter.machine.index = checkpoint.pointer;
+        interpreter.statements = Some(checkpoint.program);
+        interpreter.resume_from = Some(ResumePoint {
+            pc: checkpoint.pc,
+            steps: checkpoint.steps,
+            random: checkpoint.random,
+        });
+        Ok(interpreter)
+    }
+
+    // An interpreter of an already parsed program, with no code to read.
+    fn from_program(program: &Program, machine_size: usize) -> Self {
+        let mut interpreter = Self::from_reader(io::empty(), machine_size);
+        interpreter.statements = Some(program.statements.clone());
+        interpreter
+    }
+}
+
+// Kept in a module of its own so that the `testing` module can re-export it
+// under its old path.
+mod captured_output {
+    use std::cell::RefCell;
+    use std::io::{Result, Write};
+    use std::rc::Rc;
+
+    /// A writer keeping the output in memory. Its clones share the buffer,
+    /// so the output of a run can be read through a clone of the writer
+    /// given to [`Interpreter::with_output`](crate::Interpreter::with_output).
+    #[derive(Clone, Default, Debug)]
+    pub struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl CapturedOutput {
+        /// Returns a copy of everything written so far.
+        pub fn contents(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+
+        /// Returns everything written so far, emptying the buffer.
+        pub fn take(&self) -> Vec<u8> {
+            self.0.take()
+        }
+    }
+
+    impl Write for CapturedOutput {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+pub use captured_output::CapturedOutput;
+
+impl<S: Tape> Interpreter<BufReader<File>, S> {
+    /// Creates a new [`Interpreter`] instance reading the code from a file
+    /// and running on a given machine. If there were any problems when
+    /// reading the file the function will return an [`std::io::Error`]
+    /// instance.
+    pub fn from_file_with_machine(file_name: &str, machine: BrainfuckMachine<S>) -> Result<Self> {
+        let path = Path::new(file_name);
+        if !path.is_file() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Data cannot be read from: {}", file_name),
+            ));
+        }
+        let file = File::open(path)?;
+        let reader: BufReader<File> = BufReader::new(file);
+        Ok(Self::from_machine(reader, machine))
+    }
+}
+
+impl<T: BufRead> Interpreter<T> {
+    /// Creates a new [`Interpreter`] instance from a [`BufRead`] implementor
+    /// with a given tape size.
+    pub fn from_reader(reader: T, machine_size: usize) -> Self {
+        Self::from_machine(reader, BrainfuckMachine::new(machine_size))
+    }
+
+    /// Creates a new [`Interpreter`] instance like
+    /// [`Interpreter::from_reader`], writing the program's output to a given
+    /// writer, see [`Interpreter::with_output`].
+    pub fn from_reader_with_output(reader: T, machine_size: usize, output: Box<dyn Write>) -> Self {
+        Self::from_reader(reader, machine_size).with_output(output)
+    }
+}
+
+// Checkpoints only hold bytes, like the tapes that they are resumed on.
+impl<T: BufRead, S: Tape<Cell = u8>> Interpreter<T, S> {
+    /// Saves the state of the last run, to be continued later with
+    /// [`Interpreter::resume`]. Only the runs that completed or were stopped
+    /// by the step limit, the timeout or an interrupt without a handler can
+    /// be continued; for the rest, and before the first run, an
+    /// [`std::io::Error`] is returned. The settings of the interpreter, as
+    /// well as its input and output, aren't saved.
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        let program = self
+            .optimized_statements
+            .as_ref()
+            .or(self.statements.as_ref());
+        match (&self.stopped_at, program) {
+            (Some(point), Some(program)) => Ok(Checkpoint {
+                program: program.clone(),
+                tape: self.get_tape(),
+                pointer: self.machine.index,
+                pc: point.pc.clone(),
+                steps: point.steps,
+                random: point.random,
+            }),
+            _ => Err(Error::other("Error: the last run can't be continued.")),
+        }
+    }
+}
+
+impl<T: BufRead, S: Tape> Interpreter<T, S> {
+    /// Creates a new [`Interpreter`] instance from a [`BufRead`] implementor
+    /// running on a given machine, e.g. one made with
+    /// [`BrainfuckMachine::over`] to run directly on a buffer of the caller.
+    pub fn from_machine(reader: T, machine: BrainfuckMachine<S>) -> Self {
+        Self {
+            parser: Parser::from_reader(reader),
+            statements: None,
+            parse_time: Duration::ZERO,
+            optimized_statements: None,
+            machine,
+            terminal: None,
+            stdin_input: true,
+            input: BufReader::new(Box::new(io::stdin())),
+            input_source: InputSource::default(),
+            input_cursor: 0,
+            input_provider: None,
+            provided_input: VecDeque::new(),
+            eof_policy: EofPolicy::default(),
+            // flushed at every newline, before every read and at the end of
+            // a run, without locking the standard output for every byte
+            output: Box::new(LineWriter::new(io::stdout())),
+            newline_translation: NewlineTranslation::default(),
+            pending_cr: false,
+            messages: Box::new(io::stderr()),
+            step_limit: None,
+            output_limit: None,
+            output_written: 0,
+            output_crc: None,
+            timeout: None,
+            deadline: None,
+            max_pointer: None,
+            costs: CostTable::default(),
+            loop_semantics: LoopSemantics::default(),
+            optimizer_config: OptimizerConfig::default(),
+            optimization_report: OptimizationReport::default(),
+            on_write_error: None,
+            side_effect_handler: None,
+            output_callback: None,
+            fault_path: Vec::new(),
+            stop_path: Vec::new(),
+            stop_steps: None,
+            stopped_at: None,
+            resume_from: None,
+            pause: None,
+            tape_trace: None,
+            interrupt: None,
+            interrupt_handler: None,
+            persistent_tape: false,
+            random_seed: 0,
+            random: SplitMix64(0),
+            statement_timer: None,
+            access_counts: None,
+            memoization: None,
+            stats: ExecStats::default(),
+        }
+    }
+
+    /// Sets the reader that the program's input is read from instead of the
+    /// standard input. Every `,` consumes exactly one byte of the input and
+    /// the terminal mode is left untouched. The reader is buffered, so more
+    /// bytes than the program consumes may be read from it.
+    pub fn with_input(mut self, input: Box<dyn Read>) -> Self {
+        self.input = BufReader::new(input);
+        self.stdin_input = false;
+        self
+    }
+
+    /// Makes the code end at the first occurrence of a given byte (e.g. `!`)
+    /// instead of at the end of the reader. The bytes after the terminator
+    /// are left unread, so the same reader can carry both the code and the
+    /// program's input: once the code is parsed, the reader is positioned
+    /// right after the terminator. If the code is malformed, the amount of
+    /// bytes read is reported by the returned [`ParseError`].
+    pub fn with_code_terminator(mut self, terminator: u8) -> Self {
+        self.parser.lexer.terminator = Some(terminator);
+        self
+    }
+
+    /// Sets the maximum amount of statements of the code, counting the
+    /// loops and the statements nested in them. Larger code is rejected
+    /// while being parsed, before it's run, with
+    /// [`ParseError::ProgramTooLarge`].
+    pub fn with_max_statements(mut self, max_statements: usize) -> Self {
+        self.parser.max_statements = Some(max_statements);
+        self
+    }
+
+    /// Adds a dialect command: every occurrence of a given byte in the code
+    /// triggers a given [`SideEffectKind`]. The standard commands and the
+    /// code terminator can't be redefined and keep their meaning.
+    pub fn with_side_effect(mut self, command: u8, kind: SideEffectKind) -> Self {
+        self.parser
+            .lexer
+            .dialect
+            .push((command, Token::SideEffect(kind)));
+        self
+    }
+
+    /// Adds a dialect command that loads the size of the tape, modulo 256,
+    /// into the current cell, e.g. for programs meant to run on tapes of
+    /// different sizes. Like with [`Interpreter::with_side_effect`], the
+    /// standard commands can't be redefined.
+    pub fn with_tape_size_command(mut self, command: u8) -> Self {
+        self.parser
+            .lexer
+            .dialect
+            .push((command, Token::LoadTapeSize));
+        self
+    }
+
+    /// Adds a dialect command that writes a pseudorandom byte into the
+    /// current cell, e.g. `?`. The bytes come from a generator seeded with a
+    /// given seed at the start of every run, so the runs are reproducible.
+    /// Like with [`Interpreter::with_side_effect`], the standard commands
+    /// can't be redefined.
+    pub fn with_random_command(mut self, command: u8, seed: u64) -> Self {
+        self.parser.lexer.dialect.push((command, Token::Random));
+        self.random_seed = seed;
+        self
+    }
+
+    /// Sets the handler called for every [`SideEffectKind::Custom`] command
+    /// run. Without a handler such commands do nothing.
+    pub fn with_side_effect_handler(mut self, handler: Box<dyn FnMut(SideEffectKind)>) -> Self {
+        self.side_effect_handler = Some(handler);
+        self
+    }
+
+    /// Sets the [`InputSource`] that `,` reads from.
+    pub fn with_input_source(mut self, input_source: InputSource) -> Self {
+        self.input_source = input_source;
+        self
+    }
+
+    /// Sets a function supplying more input once the [`InputSource`] is
+    /// exhausted, e.g. by prompting the user. It's called whenever the
+    /// program reads and there are no bytes left, and its bytes are read
+    /// before it's called again. Returning `None` applies the [`EofPolicy`]
+    /// to the read, while the function is still called on the next one.
+    pub fn with_input_provider(mut self, provider: Box<dyn FnMut() -> Option<Vec<u8>>>) -> Self {
+        self.input_provider = Some(provider);
+        self
+    }
+
+    /// Sets what the moves past the ends of the tape do, see [`TapePolicy`].
+    pub fn with_tape_policy(mut self, policy: TapePolicy) -> Self {
+        self.machine.policy = policy;
+        self
+    }
+
+    /// Sets the [`EofPolicy`] applied once the input is exhausted.
+    pub fn with_eof_policy(mut self, eof_policy: EofPolicy) -> Self {
+        self.eof_policy = eof_policy;
+        self
+    }
+
+    /// Sets the [`NewlineTranslation`] applied to the bytes written by `.`.
+    /// The limits, the hash and the callback of the output see the
+    /// translated bytes.
+    pub fn with_newline_translation(mut self, translation: NewlineTranslation) -> Self {
+        self.newline_translation = translation;
+        self
+    }
+
+    /// Sets the writer that the program's output is written to instead of
+    /// the standard output. Every `.` writes exactly one raw byte, so a
+    /// writer that isn't buffered (e.g. a [`File`]) is best wrapped in a
+    /// [`io::BufWriter`]; the output is flushed before every read of the
+    /// input and at the end of a run.
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Sets a function called with every byte of the program's output, e.g.
+    /// to show the output as it's produced. The bytes are written to the
+    /// output as well, use [`std::io::sink`] as the output to only pass them
+    /// to the function.
+    pub fn with_output_callback(mut self, callback: Box<dyn FnMut(u8)>) -> Self {
+        self.output_callback = Some(callback);
+        self
+    }
+
+    /// Sets the writer that the interpreter's own messages, e.g. the
+    /// warnings of the optimization, are written to instead of the standard
+    /// error. They never mix with the program's output. Failing to write a
+    /// message doesn't affect the run.
+    pub fn with_message_output(mut self, messages: Box<dyn Write>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Sets the maximum amount of steps that a run can take. Every executed
+    /// statement and every loop iteration counts as a single step. When the
+    /// limit is exceeded, the run is stopped with
+    /// [`Termination::StepLimit`].
+    pub fn with_step_limit(mut self, step_limit: u64) -> Self {
+        self.step_limit = Some(step_limit);
+        self
+    }
+
+    /// Makes the interpreter compute a CRC-32 checksum of the output of
+    /// every run, see [`Interpreter::output_hash`]. It allows checking large
+    /// outputs without keeping them.
+    pub fn with_output_hash(mut self) -> Self {
+        self.output_crc = Some(u32::MAX);
+        self
+    }
+
+    /// Sets the maximum amount of bytes that a run can output. When the
+    /// program tries to output more, the run is stopped with
+    /// [`Termination::OutputLimit`].
+    pub fn with_output_limit(mut self, output_limit: u64) -> Self {
+        self.output_limit = Some(output_limit);
+        self
+    }
+
+    /// Sets the maximum time that a run can take. When it runs out, the run
+    /// is stopped with [`Termination::Timeout`]. The time is checked only
+    /// every few thousand steps, so the run may take slightly longer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a flag that the host can raise at any time, e.g. from another
+    /// thread, to interrupt the run. The flag is checked before every loop
+    /// guard check and lowered once noticed. With no handler, the run is
+    /// stopped with [`Termination::Interrupted`]. Otherwise the handler is
+    /// run on the same tape, from the current cell, and then the program
+    /// goes on, starting with the guard check.
+    pub fn with_interrupt(mut self, flag: Arc<AtomicBool>, handler: Option<Program>) -> Self {
+        self.interrupt = Some(flag);
+        self.interrupt_handler = handler.map(|handler| handler.statements.into());
+        self
+    }
+
+    /// Makes [`Interpreter::run_captured`] run every program on the tape
+    /// left by the previous one instead of a cleared tape, so that a
+    /// sequence of programs can share their state. The tape can still be
+    /// cleared explicitly with [`Interpreter::reset`].
+    pub fn with_persistent_tape(mut self, persistent_tape: bool) -> Self {
+        self.persistent_tape = persistent_tape;
+        self
+    }
+
+    /// Makes [`Interpreter::run_captured`] remember the output of every
+    /// completed run in a given cache, keyed by the
+    /// [`Program::fingerprint`], the settings and the input, and return it
+    /// without running anything when the same program is run on the same
+    /// input with the same settings again. On such a hit the tape and the
+    /// [`Interpreter::stats`] are left as after no run at all (with zero
+    /// steps). Only the programs that don't read random bytes or the tape
+    /// size, or call side effects, are memoized, and only on a cleared
+    /// tape, i.e. without
+    /// [`Interpreter::with_persistent_tape`]. The cache may be filled
+    /// beforehand, e.g. by an earlier interpreter with the same settings,
+    /// see [`Interpreter::memoization_cache`].
+    pub fn with_memoization(mut self, cache: MemoCache) -> Self {
+        self.memoization = Some(cache);
+        self
+    }
+
+    /// Sets the furthest cell index that the pointer may reach, regardless
+    /// of the size of the tape. When a move would exceed the limit, the run
+    /// is stopped and a [`BrainfuckError::PointerLimitExceeded`] is
+    /// returned, as an [`std::io::Error`] of kind
+    /// [`ErrorKind::PermissionDenied`].
+    pub fn with_max_pointer(mut self, max_pointer: usize) -> Self {
+        self.max_pointer = Some(max_pointer);
+        self
+    }
+
+    /// Makes the runs write a frame to a given writer right before every
+    /// `interval`-th step, e.g. to animate the execution. A frame is a
+    /// `frame N: step S, pointer P, cells A..B` line followed by a line with
+    /// the comma separated values of up to `window` cells around the pointer.
+    pub fn with_tape_trace(mut self, output: Box<dyn Write>, interval: u64, window: usize) -> Self {
+        self.tape_trace = Some(TapeTrace {
+            output,
+            interval: interval.max(1),
+            window,
+            frames: 0,
+        });
+        self
+    }
+
+    /// Measures the time spent on each kind of statement, reported by
+    /// [`ExecStats::timings`]. The clock is read once per step, so the runs
+    /// are slower with this setting.
+    pub fn with_statement_timing(self) -> Self {
+        let origin = Instant::now();
+        self.with_statement_clock(Box::new(move || origin.elapsed()))
+    }
+
+    /// Counts how many times each cell is read or written during the runs,
+    /// see [`Interpreter::access_counts`]. Every statement using the current
+    /// cell is an access, an addition being both a read and a write, as is
+    /// every check of a loop's guard. Moves alone don't access any cells.
+    pub fn with_access_counts(mut self) -> Self {
+        self.access_counts = Some(AccessCounts {
+            counts: Vec::new(),
+            origin: 0,
+        });
+        self
+    }
+
+    fn with_statement_clock(mut self, clock: Box<dyn FnMut() -> Duration>) -> Self {
+        self.statement_timer = Some(StatementTimer {
+            clock,
+            lap_start: Duration::ZERO,
+            running: None,
+        });
+        self
+    }
+
+    /// Sets the [`CostTable`] used to count the cycles of a run.
+    pub fn with_cost_table(mut self, costs: CostTable) -> Self {
+        self.costs = costs;
+        self
+    }
+
+    /// Sets the [`OnWriteError`] policy used for every failed write of the
+    /// program's output. By default a broken pipe stops the run like
+    /// [`OnWriteError::Stop`] and any other failure is handled like
+    /// [`OnWriteError::Error`].
+    pub fn with_on_write_error(mut self, on_write_error: OnWriteError) -> Self {
+        self.on_write_error = Some(on_write_error);
+        self
+    }
+
+    /// Sets what the additions past the range of a cell do, see
+    /// [`CellOverflow`].
+    pub fn with_cell_overflow(mut self, overflow: CellOverflow) -> Self {
+        self.machine.overflow = overflow;
+        self
+    }
+
+    /// Sets the [`LoopSemantics`] of the executed loops. Note that anything
+    /// other than [`LoopSemantics::While`] is not standard brainfuck.
+    pub fn with_loop_semantics(mut self, loop_semantics: LoopSemantics) -> Self {
+        self.loop_semantics = loop_semantics;
+        self
+    }
+
+    /// Sets the [`OptimizerConfig`] used by
+    /// [`Interpreter::run_with_optimization`].
+    pub fn with_optimizer_config(mut self, optimizer_config: OptimizerConfig) -> Self {
+        self.optimizer_config = optimizer_config;
+        self
+    }
+
+    // Reads a single byte of input, returns `None` at the end of the input.
+    fn get_byte(&mut self) -> Result<Option<u8>> {
+        loop {
+            if let Some(byte) = self.provided_input.pop_front() {
+                return Ok(Some(byte));
+            }
+            if let Some(byte) = self.get_source_byte()? {
+                return Ok(Some(byte));
+            }
+            match self.input_provider.as_mut().and_then(|provider| provider()) {
+                Some(bytes) => self.provided_input.extend(bytes),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn get_source_byte(&mut self) -> Result<Option<u8>> {
+        if let InputSource::TapeRegion { start, len } = self.input_source {
+            if self.input_cursor == len {
+                return Ok(None);
+            }
+            self.input_cursor += 1;
+            // the region stays put when the tape grows to the left
+            let index = self.machine.origin + start + self.input_cursor - 1;
+            return Ok(Some(self.machine.tape.get(index).to_byte()));
+        }
+        loop {
+            match self.input.fill_buf() {
+                Ok([]) => return Ok(None),
+                Ok(buffer) => {
+                    let byte = buffer[0];
+                    self.input.consume(1);
+                    return Ok(Some(byte));
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn prepare_run(&mut self, statements: &[Statement]) -> Result<()> {
+        if let InputSource::TapeRegion { start, len } = self.input_source {
+            // the region is checked against the cells right of position zero,
+            // which is all of the tape unless it has grown to the left
+            if start
+                .checked_add(len)
+                .is_none_or(|end| end > self.machine.size - self.machine.origin)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Input region doesn't fit onto the tape.",
+                ));
+            }
+        }
+        self.stats = ExecStats {
+            max_pointer: self.machine.index.saturating_sub(self.machine.origin),
+            timings: self.statement_timer.as_ref().map(|_| Default::default()),
+            ..Default::default()
+        };
+        // the first lap starts the timing
+        if let Some(timer) = self.statement_timer.as_mut() {
+            timer.running = None;
+        }
+        if let Some(access) = self.access_counts.as_mut() {
+            access.counts = vec![0; self.machine.size];
+            access.origin = self.machine.origin;
+        }
+        self.input_cursor = 0;
+        self.random = SplitMix64(self.random_seed);
+        self.output_written = 0;
+        self.pending_cr = false;
+        self.output_crc = self.output_crc.map(|_| u32::MAX);
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.fault_path.clear();
+        self.stop_path.clear();
+        self.stop_steps = None;
+        self.prepare_terminal(statements);
+        Ok(())
+    }
+
+    // Takes a snapshot of the terminal settings, but only if they are going
+    // to be changed, i.e. the program reads from an interactive standard
+    // input. Otherwise the input is read in the plain, buffered way.
+    fn prepare_terminal(&mut self, statements: &[Statement]) {
+        if self.terminal.is_none()
+            && self.stdin_input
+            && self.input_source == InputSource::Stream
+            && Statement::reads_input(statements)
+            && io::stdin().is_terminal()
+        {
+            if let Some(terminal) = StdinTerminal::new() {
+                self.terminal = Some(Box::new(terminal));
+            }
+        }
+    }
+
+    // The reader can only be consumed once, so the parsed code is cached for
+    // the subsequent runs.
+    fn parsed_statements(&mut self) -> Result<Vec<Statement>> {
+        if self.statements.is_none() {
+            let start = Instant::now();
+            self.statements = Some(self.parser.parse()?);
+            self.parse_time = start.elapsed();
+        }
+        Ok(self.statements.clone().unwrap_or_default())
+    }
+
+    /// Returns the code passed to the constructor as a [`Program`], parsing
+    /// it first if needed, with the commands of the interpreter's dialect.
+    /// Useful to parse the code once and run it on many inputs with
+    /// [`Interpreter::run_captured`]. Returns an [`std::io::Error`] if the
+    /// code fails to parse.
+    pub fn program(&mut self) -> Result<Program> {
+        Ok(Program {
+            statements: self.parsed_statements()?,
+        })
+    }
+
+    /// Like [`Interpreter::program`], but optimizes the code first the way
+    /// [`Interpreter::run_with_optimization`] does with a given
+    /// `max_iterations`, for the current state of the machine. The warnings
+    /// are written out and kept in the
+    /// [`Interpreter::optimization_report`]. Returns an
+    /// [`std::io::Error`] if the code fails to parse.
+    pub fn optimized_program(&mut self, max_iterations: u32) -> Result<Program> {
+        let statements = self.parsed_statements()?;
+        Ok(Program {
+            statements: self.optimize_statements(statements, max_iterations),
+        })
+    }
+
+    /// Parses the code that was contained within the [`BufRead`] instance
+    /// passed to the constructor (or within a given file, if the
+    /// [`Interpreter::from_file`] constructor has been
+    /// called) and then runs it. This function returns the reason why the run
+    /// has stopped, see [`Termination`], or a wrapped [`std::io::Error`] if
+    /// there were any issues.
+    /// The code is parsed only once, calling this function again runs the
+    /// same code on the current state of the machine.
+    ///
+    /// [`Interpreter::from_file`]: ./struct.Interpreter.html#method.from_file
+    pub fn run(&mut self) -> Result<Termination> {
+        self.optimized_statements = None;
+        let statements = self.parsed_statements()?;
+        self.prepare_run(&statements)?;
+        let resume = self.resume_from.take();
+        if let Some(point) = &resume {
+            self.stats.steps = point.steps;
+            self.random = SplitMix64(point.random);
         }
-        Some(result)
+        let start = Instant::now();
+        let pc = resume.as_ref().map_or(&[][..], |point| &point.pc);
+        let result = self.run_code(&statements, pc);
+        self.stats.phases = PhaseTimings {
+            parse: std::mem::take(&mut self.parse_time),
+            optimize: Duration::ZERO,
+            execute: start.elapsed(),
+        };
+        result.map_err(|error| self.locate_error(&statements, error))
     }
 
-    fn optimize_once(&mut self) {
-        let opt_result = Self::optimize_rec(&self.statements);
-        self.statements = opt_result.unwrap_or_default();
+    /// Runs a single statement of the code and pauses before the next one,
+    /// stepping into the loops, with [`Termination::Paused`]. The run is
+    /// continued from where the last one has stopped, if it can be (see
+    /// [`Interpreter::checkpoint`]), e.g. from the last pause, and started
+    /// anew otherwise. A loop's guard checks are run along with the
+    /// statements before them, so stepping from the last statement of a
+    /// loop's body leads either to its first statement or past the loop.
+    /// See [`Interpreter::stopped_at`] for where the run has paused.
+    pub fn step_into(&mut self) -> Result<Termination> {
+        self.run_stepping(|_| Some(usize::MAX))
     }
 
-    fn optimize(&mut self, max_iterations: u32) {
-        if max_iterations == 0 {
-            loop {
-                let previous = self.statements.clone();
-                self.optimize_once();
-                if self.statements == previous {
-                    break;
-                }
-            }
-        } else {
-            for _ in 0..max_iterations {
-                let previous = self.statements.clone();
-                self.optimize_once();
-                if self.statements == previous {
-                    break;
-                }
-            }
-        }
+    /// Like [`Interpreter::step_into`], but a loop is run as a single
+    /// statement, pausing past it once it's done.
+    pub fn step_over(&mut self) -> Result<Termination> {
+        self.run_stepping(Some)
     }
 
-    fn yield_back(self) -> Vec<Statement> {
-        self.statements
+    /// Like [`Interpreter::step_into`], but runs the rest of the loop that
+    /// the run has paused in, pausing past it once its guard is zero.
+    /// Outside of any loop, the code is run to the end.
+    pub fn step_out(&mut self) -> Result<Termination> {
+        self.run_stepping(|depth| depth.checked_sub(1))
     }
-}
 
-/// A brainfuck interpreter class that reads code from a file / [`BufRead`]
-/// instance, parses, optimizes and runs it.
-pub struct Interpreter<T: BufRead> {
-    parser: Parser<T>,
-    machine: BrainfuckMachine,
-    console: termios::Termios,
-}
+    /// Returns the position of the next statement of the last run, if it
+    /// can be continued, as described by [`Checkpoint::pc`]. After a
+    /// completed run it's just past the last statement.
+    pub fn stopped_at(&self) -> Option<&[usize]> {
+        self.stopped_at.as_ref().map(|point| &point.pc[..])
+    }
 
-impl Interpreter<BufReader<File>> {
-    /// Creates a new [`Interpreter<BufReader<File>>`] instance wrapped in a
-    /// [`Result`] object. If there were any problems when reading a file
-    /// the function will return an [`std::io::Error`] instance.
-    pub fn from_file(file_name: &str, machine_size: usize) -> Result<Self> {
-        let path = Path::new(file_name);
-        if !path.is_file() {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Data cannot be read from: {}", file_name),
-            ));
+    // Continues the last run of the parsed code (or starts a new one),
+    // pausing at a depth computed from the one of the next statement.
+    fn run_stepping(&mut self, pause_depth: fn(usize) -> Option<usize>) -> Result<Termination> {
+        // the position of an optimized run is meaningless in the parsed code
+        let point = match self.optimized_statements {
+            Some(_) => None,
+            None => self.stopped_at.take(),
+        };
+        let (depth, steps) = point.as_ref().map_or((0, 0), |point| {
+            (point.pc.len().saturating_sub(1), point.steps)
+        });
+        self.resume_from = point;
+        self.pause = pause_depth(depth).map(|depth| (depth, steps));
+        let result = self.run();
+        self.pause = None;
+        result
+    }
+
+    /// Runs the code like [`Interpreter::run`], but with a given step limit
+    /// in place of the one set with [`Interpreter::with_step_limit`], e.g.
+    /// for untrusted code that may never stop. Returns an
+    /// [`std::io::Error`] of kind [`ErrorKind::TimedOut`] once the run takes
+    /// more than `max_steps` steps (every statement and every loop
+    /// iteration is a step) or runs out of the time set with
+    /// [`Interpreter::with_timeout`]. A run stopped for any other reason is
+    /// an error as well, so `Ok` means that the program has completed.
+    pub fn run_with_limit(&mut self, max_steps: u64) -> Result<()> {
+        let step_limit = self.step_limit.replace(max_steps);
+        let result = self.run();
+        self.step_limit = step_limit;
+        match result? {
+            Termination::Completed => Ok(()),
+            Termination::StepLimit => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("Error: the program has taken more than {max_steps} steps."),
+            )),
+            Termination::Timeout => Err(Error::new(
+                ErrorKind::TimedOut,
+                "Error: the program has run out of time.",
+            )),
+            termination => Err(Error::other(format!(
+                "Error: the program has been stopped: {termination:?}."
+            ))),
         }
-        let file = File::open(path)?;
-        let reader: BufReader<File> = BufReader::new(file);
-        Ok(Self {
-            parser: Parser::<BufReader<File>>::from_reader(reader),
-            machine: BrainfuckMachine::new(machine_size),
-            console: termios::Termios::from_fd(0).unwrap(),
-        })
     }
-}
 
-impl<T: BufRead> Interpreter<T> {
-    /// Creates a new [`Interpreter`] instance from a [`BufRead`] implementor
-    /// with a given tape size.
-    pub fn from_reader(reader: T, machine_size: usize) -> Self {
-        Self {
-            parser: Parser::from_reader(reader),
-            machine: BrainfuckMachine::new(machine_size),
-            console: termios::Termios::from_fd(0).unwrap(),
+    /// Runs a given program instead of the code passed to the constructor,
+    /// reading a given input and returning the output instead of writing
+    /// it. Before the run, the tape is cleared and the pointer is moved to
+    /// the first cell, unless [`Interpreter::with_persistent_tape`] is set.
+    /// The input provider set with [`Interpreter::with_input_provider`]
+    /// isn't used. The rest of the settings, e.g. the limits, apply as in
+    /// [`Interpreter::run`].
+    pub fn run_captured(
+        &mut self,
+        program: &Program,
+        input: &[u8],
+    ) -> Result<(Termination, Vec<u8>)> {
+        if !self.persistent_tape {
+            self.reset();
         }
+        let key = (self.memoization.is_some()
+            && !self.persistent_tape
+            && self.input_source == InputSource::Stream
+            && Statement::is_pure(&program.statements))
+        .then(|| {
+            let settings = self.settings_fingerprint();
+            (program.fingerprint(), settings, input.to_vec())
+        });
+        if let Some(output) = key
+            .as_ref()
+            .and_then(|key| self.memoization.as_ref()?.get(key))
+        {
+            self.stats = ExecStats::default();
+            return Ok((Termination::Completed, output.clone()));
+        }
+        let captured = CapturedOutput::default();
+        let input = BufReader::new(Box::new(io::Cursor::new(input.to_vec())) as Box<dyn Read>);
+        let input = std::mem::replace(&mut self.input, input);
+        let output = std::mem::replace(&mut self.output, Box::new(captured.clone()));
+        // the terminal is left alone, as the standard input isn't read
+        let stdin_input = std::mem::replace(&mut self.stdin_input, false);
+        let terminal = self.terminal.take();
+        let provider = self.input_provider.take();
+        let provided_input = std::mem::take(&mut self.provided_input);
+        let result = self.prepare_run(&program.statements).and_then(|()| {
+            let start = Instant::now();
+            let result = self.run_code(&program.statements, &[]);
+            self.stats.phases.execute = start.elapsed();
+            result
+        });
+        // the program isn't kept, so there's nothing to continue
+        self.stopped_at = None;
+        self.input = input;
+        self.output = output;
+        self.stdin_input = stdin_input;
+        self.terminal = terminal;
+        self.input_provider = provider;
+        self.provided_input = provided_input;
+        let termination = result?;
+        let output = captured.take();
+        if let (Some(key), Some(cache), Termination::Completed) =
+            (key, self.memoization.as_mut(), termination)
+        {
+            cache.insert(key, output.clone());
+        }
+        Ok((termination, output))
     }
 
-    fn get_char(&mut self) -> char {
-        let stdout = io::stdout();
-        let mut buffer = [0; 1];
-        let mut reader = io::stdin();
-        stdout.lock().flush().unwrap();
-        reader.read_exact(&mut buffer).unwrap();
-        buffer[0] as char
+    // A hash of the settings that can change the output of a run, keying the
+    // memoized outputs along with the program and the input.
+    fn settings_fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        self.machine.size.hash(&mut hasher);
+        S::Cell::BITS.hash(&mut hasher);
+        self.machine.policy.hash(&mut hasher);
+        self.machine.overflow.hash(&mut hasher);
+        self.eof_policy.hash(&mut hasher);
+        self.loop_semantics.hash(&mut hasher);
+        self.newline_translation.hash(&mut hasher);
+        self.costs.hash(&mut hasher);
+        self.step_limit.hash(&mut hasher);
+        self.output_limit.hash(&mut hasher);
+        self.max_pointer.hash(&mut hasher);
+        self.timeout.hash(&mut hasher);
+        hasher.finish()
     }
 
-    fn enable_get_char_mode(&mut self) {
-        let mut new_termios = self.console.clone();
-        new_termios.c_lflag &= !(termios::ICANON);
-        termios::tcsetattr(
-            std::io::Stdin::as_raw_fd(&std::io::stdin()),
-            termios::TCSANOW,
-            &mut new_termios,
-        )
-        .unwrap();
+    /// Runs the code like [`Interpreter::run`], or like
+    /// [`Interpreter::run_with_optimization`] with a given `max_iterations`,
+    /// and gathers everything about the run into a [`RunReport`]. The output
+    /// is collected into the report instead of being written to the writer
+    /// set with [`Interpreter::with_output`]. Errors, including the parse
+    /// errors, are reported through [`RunReport::outcome`].
+    pub fn run_full(&mut self, max_iterations: Option<u32>) -> RunReport<S::Cell> {
+        // a parse error leaves no statistics behind
+        self.stats = ExecStats::default();
+        let captured = CapturedOutput::default();
+        let output = std::mem::replace(&mut self.output, Box::new(captured.clone()));
+        let result = match max_iterations {
+            Some(max_iterations) => self.run_with_optimization(max_iterations),
+            None => self.run(),
+        };
+        self.output = output;
+        RunReport {
+            outcome: match result {
+                Ok(termination) => RunOutcome::Stopped(termination),
+                Err(error) => RunOutcome::failed(&error),
+            },
+            output: captured.take(),
+            tape: self.get_tape(),
+            pointer: self.machine.index,
+            stats: self.stats,
+            optimization: max_iterations.map(|_| self.optimization_report.clone()),
+        }
     }
 
-    fn disable_get_char_mode(&mut self) {
-        termios::tcsetattr(
-            std::io::Stdin::as_raw_fd(&std::io::stdin()),
-            termios::TCSANOW,
-            &self.console,
-        )
-        .unwrap();
+    /// Clears the tape and moves the pointer to the first cell. The code and
+    /// the settings of the interpreter are kept.
+    pub fn reset(&mut self) {
+        self.machine.reset();
     }
 
-    /// Parses the code that was contained within the [`BufRead`] instance
-    /// passed to the constructor (or within a given file, if the
-    /// [`Interpreter::from_file`] constructor has been
-    /// called) and then runs it. This function returns an [`Ok(())`] instance
-    /// in case of no issues and a wrapped [`std::io::Error`] if there are any.
-    ///
-    /// [`Interpreter::from_file`]: ./struct.Interpreter.html#method.from_file
-    pub fn run(&mut self) -> Result<()> {
-        let statements = self.parser.parse()?;
-        self.run_code(&statements);
-        Ok(())
+    // Fills in the span of a [`BrainfuckError`] raised by unoptimized code,
+    // using the path to the failed statement gathered while unwinding.
+    fn locate_error(&mut self, statements: &[Statement], mut error: Error) -> Error {
+        let fault = error
+            .get_mut()
+            .and_then(|inner| inner.downcast_mut::<BrainfuckError>());
+        if let Some(fault) = fault {
+            self.fault_path.reverse();
+            let token = Statement::token_index(statements, &self.fault_path);
+            if let Some(&start) = self.parser.lexer.offsets.get(token) {
+                fault.set_span(Span {
+                    start,
+                    end: start + 1,
+                });
+            }
+        }
+        error
     }
 
     /// Parses the code that was contained within the [`BufRead`] instance
@@ -516,58 +4229,742 @@ impl<T: BufRead> Interpreter<T> {
     /// called) and then runs it with a given optimization level. The
     /// `max_iterations` parameter specifies the maximum amount of optimization
     /// iterations that will be run on the code. If `max_iterations` is equal
-    /// to `0`, then the code will be optimized fully. This function returns an
-    /// [`Ok(())`] instancein case of no issues and a wrapped
-    /// [`std::io::Error`] if there are any.
+    /// to `0`, then the code will be optimized fully (within a safety limit,
+    /// see [`Interpreter::optimization_report`]). This function returns the
+    /// reason why the run has stopped, see [`Termination`], or a wrapped
+    /// [`std::io::Error`] if there were any issues.
+    ///
+    /// The optimized code stops (with an error or a panic) whenever the
+    /// unoptimized one would, and only after producing exactly the same
+    /// output.
     ///
     /// [`Interpreter::from_file`]: ./struct.Interpreter.html#method.from_file
-    pub fn run_with_optimization(&mut self, max_iterations: u32) -> Result<()> {
-        let statements = self.parser.parse()?;
-        let mut optimizer = Optimizer::new(statements);
-        optimizer.optimize(max_iterations);
+    pub fn run_with_optimization(&mut self, max_iterations: u32) -> Result<Termination> {
+        // the saved position is only valid in the saved code
+        self.resume_from = None;
+        let statements = self.parsed_statements()?;
+        let start = Instant::now();
+        let statements = self.optimize_statements(statements, max_iterations);
+        let optimize = start.elapsed();
+        let result = self.prepare_run(&statements).and_then(|()| {
+            let start = Instant::now();
+            let result = self.run_code(&statements, &[]);
+            self.stats.phases = PhaseTimings {
+                parse: std::mem::take(&mut self.parse_time),
+                optimize,
+                execute: start.elapsed(),
+            };
+            result
+        });
+        self.optimized_statements = Some(statements);
+        result
+    }
+
+    // Optimizes the code for the current state of the machine and the
+    // settings of the interpreter, keeping the report and writing its
+    // warnings to the messages.
+    fn optimize_statements(
+        &mut self,
+        statements: Vec<Statement>,
+        max_iterations: u32,
+    ) -> Vec<Statement> {
+        let zeroed_tape = self.machine.tape.is_zeroed();
+        let mut config = self.optimizer_config.clone();
+        // with saturating cells, `[+]` isn't a clear
+        let saturating = self.machine.overflow == CellOverflow::Saturate;
+        if self.machine.policy == TapePolicy::Saturate || saturating {
+            config.fuse_clears = false;
+            config.fill_regions = false;
+        }
+        // the fills are computed modulo 256
+        let wide_cells = S::Cell::BITS > 8;
+        if wide_cells {
+            config.fill_regions = false;
+        }
+        let mut optimizer = Optimizer::new(statements)
+            .with_loop_semantics(self.loop_semantics)
+            .with_zeroed_tape(zeroed_tape)
+            .with_saturating(saturating)
+            .with_wide_cells(wide_cells)
+            .with_config(config);
+        self.optimization_report = optimizer.optimize(max_iterations);
         let statements = optimizer.yield_back();
-        self.run_code(&statements);
+        for warning in &self.optimization_report.warnings {
+            let _ = writeln!(self.messages, "Warning: {}", warning);
+        }
+        if self.optimization_report.termination == TerminationVerdict::GuaranteedInfinite {
+            let _ = writeln!(
+                self.messages,
+                "Warning: The code contains a loop that never terminates."
+            );
+        }
+        let _ = self.messages.flush();
+        statements
+    }
+
+    // Counts a step, a `Break` means that the run should be stopped because
+    // of a limit.
+    fn step(&mut self, cost: u64, kind: TimedKind) -> Result<ControlFlow<Termination>> {
+        self.stats.steps += 1;
+        self.stats.cycles += cost;
+        self.lap_timer(Some(kind));
+        if let Some(trace) = self.tape_trace.as_mut() {
+            if self.stats.steps.is_multiple_of(trace.interval) {
+                trace.write_frame(&self.machine, self.stats.steps)?;
+            }
+        }
+        let termination = if self
+            .step_limit
+            .is_some_and(|limit| self.stats.steps > limit)
+        {
+            Termination::StepLimit
+        } else if self.stats.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Termination::Timeout
+        } else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        // the step isn't run, so the run can be continued from it
+        self.stop_steps = Some(self.stats.steps - 1);
+        Ok(ControlFlow::Break(termination))
+    }
+
+    // Starts timing the next kind of statement, or stops timing if there's
+    // none.
+    fn lap_timer(&mut self, next: Option<TimedKind>) {
+        if let (Some(timer), Some(timings)) =
+            (self.statement_timer.as_mut(), self.stats.timings.as_mut())
+        {
+            timer.lap(timings, next);
+        }
+    }
+
+    // Moves the pointer, reporting the moves past the tape as errors instead
+    // of panicking like the machine's `move_*` do.
+    fn move_left(&mut self, shift: usize) -> Result<()> {
+        self.machine
+            .try_move_left(shift)
+            .map_err(|error| BrainfuckError::from(error).into())
+    }
+
+    fn move_right(&mut self, shift: usize) -> Result<()> {
+        // the limit is checked first, before a growing tape grows
+        // the limit applies to the positions, which differ from the indices
+        // once the tape has grown to the left
+        let target = self
+            .machine
+            .right_target(shift)
+            .unwrap_or(self.machine.index.saturating_add(shift))
+            .saturating_sub(self.machine.origin);
+        match self.max_pointer {
+            Some(limit) if target > limit => Err(BrainfuckError::PointerLimitExceeded {
+                limit,
+                index: self.machine.index,
+                shift,
+                span: None,
+            }
+            .into()),
+            _ => {
+                self.machine
+                    .try_move_right(shift)
+                    .map_err(BrainfuckError::from)?;
+                let position = self.machine.index.saturating_sub(self.machine.origin);
+                self.stats.max_pointer = self.stats.max_pointer.max(position);
+                Ok(())
+            }
+        }
+    }
+
+    // Clears the cells at given offsets from the pointer, without moving it.
+    // The bounds are checked as if the pointer moved to each of the cells.
+    fn zero_cells(&mut self, offsets: &[isize]) -> Result<()> {
+        let position = self.machine.position();
+        for offset in offsets {
+            if *offset < 0 {
+                self.move_left(offset.unsigned_abs())?;
+            } else {
+                self.move_right(*offset as usize)?;
+            }
+            self.machine.read_byte(0);
+            self.count_access(1);
+            self.restore_position(position);
+        }
         Ok(())
     }
 
-    fn run_code(&mut self, statements: &Vec<Statement>) {
-        self.enable_get_char_mode();
-        for statement in statements {
-            match statement {
-                Statement::MoveLeft(value) => self.machine.move_left(*value),
-                Statement::MoveRight(value) => self.machine.move_right(*value),
-                Statement::Add(value) => self.machine.add(*value),
-                Statement::ReadChar => {
-                    let chr = self.get_char();
-                    self.machine.read_char(chr);
-                }
-                Statement::PutChar => {
-                    let chr = self.machine.put_char();
-                    print!("{}", chr);
-                }
-                Statement::Loop(boxed) => {
-                    while self.machine.check_loop() {
-                        self.run_code(boxed);
+    // Moves the pointer back to a saved position, whose index has changed if
+    // the tape has grown to the left since.
+    fn restore_position(&mut self, position: isize) {
+        self.machine.index = self.machine.origin.wrapping_add_signed(position);
+    }
+
+    // Like `zero_cells`, the ends of the region are checked by moving the
+    // pointer there, so that leaving the tape raises the same errors as the
+    // moves of the unoptimized code.
+    fn fill_cells(&mut self, from: isize, to: isize, value: u8) -> Result<()> {
+        let position = self.machine.position();
+        // the region of a wrapping tape may be split between its ends
+        if self.machine.policy == TapePolicy::Wrap {
+            for offset in from..to {
+                if offset < 0 {
+                    self.move_left(offset.unsigned_abs())?;
+                } else {
+                    self.move_right(offset as usize)?;
+                }
+                self.machine.read_byte(value);
+                self.count_access(1);
+                self.restore_position(position);
+            }
+            return Ok(());
+        }
+        for offset in [from, to - 1] {
+            if offset < 0 {
+                self.move_left(offset.unsigned_abs())?;
+            } else {
+                self.move_right(offset as usize)?;
+            }
+            self.restore_position(position);
+        }
+        let index = self.machine.index;
+        let start = index.wrapping_add_signed(from);
+        let end = index.wrapping_add_signed(to);
+        self.machine
+            .tape
+            .fill(start..end, S::Cell::from_byte(value));
+        if let Some(access) = self.access_counts.as_mut() {
+            access.record(&self.machine, start..end, 1);
+        }
+        Ok(())
+    }
+
+    fn count_access(&mut self, amount: u64) {
+        if let Some(access) = self.access_counts.as_mut() {
+            let index = self.machine.index;
+            access.record(&self.machine, index..index + 1, amount);
+        }
+    }
+
+    // Runs the whole program, or its rest from a given position, see
+    // `Checkpoint::pc`. The terminal is switched to raw mode only once per
+    // run and only if the program reads any input at all.
+    fn run_code(&mut self, statements: &[Statement], pc: &[usize]) -> Result<Termination> {
+        let terminal = match Statement::reads_input(statements) {
+            true => self.terminal.take(),
+            false => None,
+        };
+        let guard = RawModeGuard::new(terminal);
+        // the output is flushed no matter how the run ends, even when it is
+        // stopped by a panic
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.resume_statements(statements, pc, 0)
+        }));
+        self.lap_timer(Some(TimedKind::Output));
+        let flushed = self.write_pending_cr().and_then(|pending| {
+            let flushed = self.flush_output()?;
+            if let Some(trace) = self.tape_trace.as_mut() {
+                trace.output.flush()?;
+            }
+            Ok(if pending.is_break() { pending } else { flushed })
+        });
+        self.lap_timer(None);
+        if let Some(access) = self.access_counts.as_mut() {
+            access.follow(&self.machine);
+        }
+        if let Some(terminal) = guard.release() {
+            self.terminal = Some(terminal);
+        }
+        let result = match result {
+            Ok(Ok(ControlFlow::Continue(()))) => flushed.map(|flushed| match flushed {
+                ControlFlow::Continue(()) => Termination::Completed,
+                ControlFlow::Break(termination) => termination,
+            }),
+            Ok(Ok(ControlFlow::Break(termination))) => Ok(termination),
+            Ok(Err(error)) => Err(error),
+            Err(payload) => panic::resume_unwind(payload),
+        };
+        self.stopped_at = match result {
+            Ok(Termination::Completed) => Some(vec![statements.len()]),
+            Ok(_) if self.stop_steps.is_some() => {
+                Some(self.stop_path.iter().rev().copied().collect())
+            }
+            _ => None,
+        }
+        .map(|pc| ResumePoint {
+            pc,
+            steps: self.stop_steps.unwrap_or(self.stats.steps),
+            random: self.random.0,
+        });
+        result
+    }
+
+    // Decides what to do when writing the output fails, a `Break` means that
+    // the run should be stopped without an error.
+    fn handle_write_error(&self, error: Error) -> Result<ControlFlow<Termination>> {
+        let policy = self.on_write_error.unwrap_or(match error.kind() {
+            ErrorKind::BrokenPipe => OnWriteError::Stop,
+            _ => OnWriteError::Error,
+        });
+        match policy {
+            OnWriteError::Stop => Ok(ControlFlow::Break(Termination::Halted)),
+            OnWriteError::Error => Err(error),
+            OnWriteError::Ignore => Ok(ControlFlow::Continue(())),
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<ControlFlow<Termination>> {
+        if self
+            .output_limit
+            .is_some_and(|limit| self.output_written >= limit)
+        {
+            return Ok(ControlFlow::Break(Termination::OutputLimit));
+        }
+        self.output_written += 1;
+        if let Some(callback) = self.output_callback.as_mut() {
+            callback(byte);
+        }
+        if let Some(crc) = self.output_crc.as_mut() {
+            *crc = (*crc >> 8) ^ CRC32_TABLE[((*crc ^ byte as u32) & 0xff) as usize];
+        }
+        match self.output.write_all(&[byte]) {
+            Ok(()) => Ok(ControlFlow::Continue(())),
+            Err(error) => self.handle_write_error(error),
+        }
+    }
+
+    // Writes a byte output by `.`, translating the newlines.
+    fn put_byte(&mut self, byte: u8) -> Result<ControlFlow<Termination>> {
+        match self.newline_translation {
+            NewlineTranslation::LfToCrlf if byte == b'\n' => {
+                if let flow @ ControlFlow::Break(_) = self.write_byte(b'\r')? {
+                    return Ok(flow);
+                }
+                self.write_byte(byte)
+            }
+            NewlineTranslation::CrlfToLf => {
+                if let flow @ ControlFlow::Break(_) = self.write_pending_cr_before(byte)? {
+                    return Ok(flow);
+                }
+                match byte {
+                    b'\r' => {
+                        self.pending_cr = true;
+                        Ok(ControlFlow::Continue(()))
                     }
+                    _ => self.write_byte(byte),
                 }
             }
+            _ => self.write_byte(byte),
+        }
+    }
+
+    // Writes the held back `\r`, unless it's followed by a `\n`.
+    fn write_pending_cr_before(&mut self, byte: u8) -> Result<ControlFlow<Termination>> {
+        match std::mem::take(&mut self.pending_cr) && byte != b'\n' {
+            true => self.write_byte(b'\r'),
+            false => Ok(ControlFlow::Continue(())),
+        }
+    }
+
+    // Writes the `\r` held back at the end of a run.
+    fn write_pending_cr(&mut self) -> Result<ControlFlow<Termination>> {
+        self.write_pending_cr_before(b'\r')
+    }
+
+    fn flush_output(&mut self) -> Result<ControlFlow<Termination>> {
+        match self.output.flush() {
+            Ok(()) => Ok(ControlFlow::Continue(())),
+            Err(error) => self.handle_write_error(error),
+        }
+    }
+
+    // Handles a raised interrupt flag, see `Interpreter::with_interrupt`.
+    fn check_interrupt(&mut self, depth: usize) -> Result<ControlFlow<Termination>> {
+        let raised = self
+            .interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.swap(false, AtomicOrdering::Relaxed));
+        if !raised {
+            return Ok(ControlFlow::Continue(()));
+        }
+        match self.interrupt_handler.clone() {
+            Some(handler) => {
+                let flow = self.run_statements(&handler, depth)?;
+                // the handler isn't part of the code, so a run stopped in
+                // it can't be continued
+                if flow.is_break() {
+                    self.stop_steps = None;
+                }
+                Ok(flow)
+            }
+            None => {
+                self.stop_steps = Some(self.stats.steps);
+                Ok(ControlFlow::Break(Termination::Interrupted))
+            }
+        }
+    }
+
+    fn run_statements(
+        &mut self,
+        statements: &[Statement],
+        depth: usize,
+    ) -> Result<ControlFlow<Termination>> {
+        self.run_statements_from(statements, 0, depth)
+    }
+
+    // Continues the statements from a given position, the path to the next
+    // statement to run. The loops on the way are continued from where their
+    // bodies were stopped.
+    fn resume_statements(
+        &mut self,
+        statements: &[Statement],
+        pc: &[usize],
+        depth: usize,
+    ) -> Result<ControlFlow<Termination>> {
+        let Some((&position, inner)) = pc.split_first() else {
+            return self.run_statements(statements, depth);
+        };
+        if inner.is_empty() {
+            return self.run_statements_from(statements, position, depth);
+        }
+        let Some(Statement::Loop(code)) = statements.get(position) else {
+            return Err(Error::new(ErrorKind::InvalidData, INVALID_CHECKPOINT));
+        };
+        let flow = self
+            .resume_statements(code, inner, depth + 1)
+            .and_then(|flow| match flow {
+                ControlFlow::Continue(()) => self.run_iterations(code, depth, true),
+                flow => Ok(flow),
+            });
+        match flow {
+            Ok(ControlFlow::Continue(())) => {}
+            Ok(flow) => {
+                self.stop_path.push(position);
+                return Ok(flow);
+            }
+            Err(error) => {
+                self.fault_path.push(position);
+                return Err(error);
+            }
+        }
+        self.run_statements_from(statements, position + 1, depth)
+    }
+
+    fn run_statements_from(
+        &mut self,
+        statements: &[Statement],
+        start: usize,
+        depth: usize,
+    ) -> Result<ControlFlow<Termination>> {
+        for (position, statement) in statements.iter().enumerate().skip(start) {
+            if let Some((pause_depth, start_steps)) = self.pause {
+                // the statement the run was continued from is always run
+                if depth <= pause_depth && self.stats.steps > start_steps {
+                    self.stop_steps = Some(self.stats.steps);
+                    self.stop_path.push(position);
+                    return Ok(ControlFlow::Break(Termination::Paused));
+                }
+            }
+            match self.run_statement(statement, depth) {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(flow) => {
+                    // the path to the stopped statement, innermost first
+                    self.stop_path.push(position);
+                    return Ok(flow);
+                }
+                Err(error) => {
+                    // the path to the failed statement, innermost first
+                    self.fault_path.push(position);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn run_statement(
+        &mut self,
+        statement: &Statement,
+        depth: usize,
+    ) -> Result<ControlFlow<Termination>> {
+        // comments are not executed, so they don't count as steps
+        if !matches!(statement, Statement::Comment(_)) {
+            if let flow @ ControlFlow::Break(_) =
+                self.step(self.costs.cost_of(statement), TimedKind::of(statement))?
+            {
+                return Ok(flow);
+            }
+        }
+        match statement {
+            Statement::Comment(_) => {}
+            Statement::MoveLeft(value) => self.move_left(*value)?,
+            Statement::MoveRight(value) => self.move_right(*value)?,
+            // the amounts above 127 subtract, which matters once the cells
+            // saturate
+            Statement::Add(value) if (*value as i8) < 0 => {
+                self.machine
+                    .substract(S::Cell::from_byte(value.wrapping_neg()));
+                // a read and a write
+                self.count_access(2);
+            }
+            Statement::Add(value) => {
+                self.machine.add(S::Cell::from_delta(*value));
+                // a read and a write
+                self.count_access(2);
+            }
+            Statement::ZeroCells(offsets) => self.zero_cells(offsets)?,
+            Statement::FillValue { from, to, value } => self.fill_cells(*from, *to, *value)?,
+            Statement::ReadChar => {
+                // the output is flushed so that any prompts are visible
+                if self.input_source == InputSource::Stream {
+                    if let flow @ ControlFlow::Break(_) = self.flush_output()? {
+                        return Ok(flow);
+                    }
+                }
+                match (self.get_byte()?, self.eof_policy) {
+                    (Some(byte), _) => self.machine.read_byte(byte),
+                    (None, EofPolicy::Unchanged) => {}
+                    (None, EofPolicy::Zero) => self.machine.read_byte(0),
+                    (None, EofPolicy::Max) => self.machine.set_current_cell(S::Cell::MAX),
+                }
+                self.count_access(1);
+            }
+            Statement::PutChar => {
+                self.count_access(1);
+                let byte = self.machine.put_byte();
+                if let flow @ ControlFlow::Break(_) = self.put_byte(byte)? {
+                    return Ok(flow);
+                }
+            }
+            Statement::LoadTapeSize => {
+                self.machine
+                    .set_current_cell(S::Cell::from_usize(self.machine.size));
+                self.count_access(1);
+            }
+            Statement::Random => {
+                let byte = self.random.next_byte();
+                self.machine.read_byte(byte);
+                self.count_access(1);
+            }
+            Statement::SideEffect(SideEffectKind::Bell) => {
+                if let flow @ ControlFlow::Break(_) = self.write_byte(0x07)? {
+                    return Ok(flow);
+                }
+            }
+            Statement::SideEffect(kind) => {
+                if let Some(handler) = self.side_effect_handler.as_mut() {
+                    handler(*kind);
+                }
+            }
+            Statement::Loop(code) => {
+                let entered = self.loop_semantics == LoopSemantics::DoWhile;
+                if entered {
+                    if let flow @ ControlFlow::Break(_) = self.check_interrupt(depth)? {
+                        return Ok(flow);
+                    }
+                    if let flow @ ControlFlow::Break(_) =
+                        self.step(self.costs.loop_branch, TimedKind::Loop)?
+                    {
+                        return Ok(flow);
+                    }
+                    self.stats.max_depth = self.stats.max_depth.max(depth + 1);
+                    if let flow @ ControlFlow::Break(_) = self.run_statements(code, depth + 1)? {
+                        return Ok(flow);
+                    }
+                }
+                return self.run_iterations(code, depth, entered);
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    // Runs the body of a loop while its guard isn't zero. A run stopped at
+    // the guard is continued from the guard, i.e. from past the end of the
+    // body.
+    fn run_iterations(
+        &mut self,
+        code: &[Statement],
+        depth: usize,
+        mut entered: bool,
+    ) -> Result<ControlFlow<Termination>> {
+        loop {
+            if let flow @ ControlFlow::Break(_) = self.check_interrupt(depth)? {
+                self.stop_path.push(code.len());
+                return Ok(flow);
+            }
+            // the last check isn't a step, but it's timed all the same
+            self.lap_timer(Some(TimedKind::Loop));
+            self.count_access(1);
+            if !self.machine.check_loop() {
+                break;
+            }
+            if let flow @ ControlFlow::Break(_) =
+                self.step(self.costs.loop_branch, TimedKind::Loop)?
+            {
+                self.stop_path.push(code.len());
+                return Ok(flow);
+            }
+            if !entered {
+                entered = true;
+                self.stats.max_depth = self.stats.max_depth.max(depth + 1);
+            }
+            if let flow @ ControlFlow::Break(_) = self.run_statements(code, depth + 1)? {
+                return Ok(flow);
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Returns how many times each cell was read or written during the last
+    /// run, in the order of [`Interpreter::get_tape`]. Empty unless
+    /// [`Interpreter::with_access_counts`] is set.
+    pub fn access_counts(&self) -> &[u64] {
+        match &self.access_counts {
+            Some(access) => &access.counts,
+            None => &[],
         }
-        self.disable_get_char_mode();
     }
 
     /// Returns a [`Vec<u8>`] instance represeting the tape of the underlying
     /// [machine].
     ///
     /// [machine]: BrainfuckMachine
-    pub fn get_tape(&self) -> Vec<u8> {
+    pub fn get_tape(&self) -> Vec<S::Cell> {
         self.machine.get_tape()
     }
+
+    /// Returns a listing of the loaded code, e.g. `1+ 1+ [ 1>] .` for
+    /// `++[>].`, for a debugger to show what's executing. This is the legacy
+    /// listing format of the crate, kept for the existing debuggers: the
+    /// amounts come before the commands and the additions are unsigned, e.g.
+    /// `255+` for `-`. [`Program`] displays the code in the newer
+    /// indented format instead. After a call
+    /// to [`Interpreter::run_with_optimization`] this is the optimized code
+    /// that was run, otherwise the parsed code, which is parsed first if
+    /// needed. Returns an [`std::io::Error`] if the code fails to parse.
+    pub fn peek_program_source(&mut self) -> Result<String> {
+        if let Some(statements) = &self.optimized_statements {
+            return Ok(Code::generate_string(statements));
+        }
+        Ok(Code::generate_string(&self.parsed_statements()?))
+    }
+
+    /// Returns a reference to the underlying [`BrainfuckMachine`], e.g. to
+    /// inspect single cells after a run.
+    pub fn machine(&self) -> &BrainfuckMachine<S> {
+        &self.machine
+    }
+
+    /// Returns a mutable reference to the underlying [`BrainfuckMachine`],
+    /// e.g. to seed the tape before a run.
+    pub fn machine_mut(&mut self) -> &mut BrainfuckMachine<S> {
+        &mut self.machine
+    }
+
+    /// Returns the CRC-32 checksum (as computed by e.g. zlib) of the bytes
+    /// output by the last run, or `None` if it isn't computed, see
+    /// [`Interpreter::with_output_hash`].
+    pub fn output_hash(&self) -> Option<u32> {
+        self.output_crc.map(|crc| !crc)
+    }
+
+    /// Returns the cache of the outputs set with
+    /// [`Interpreter::with_memoization`], including the outputs of the runs
+    /// done since, or `None` if memoization is disabled.
+    pub fn memoization_cache(&self) -> Option<&MemoCache> {
+        self.memoization.as_ref()
+    }
+
+    /// Returns the [`ExecStats`] gathered during the last run.
+    pub fn stats(&self) -> &ExecStats {
+        &self.stats
+    }
+
+    /// Returns the [`OptimizationReport`] of the last
+    /// [`Interpreter::run_with_optimization`] call.
+    pub fn optimization_report(&self) -> &OptimizationReport {
+        &self.optimization_report
+    }
+
+    /// Consumes the interpreter, returning the underlying [machine] and the
+    /// parsed (unoptimized) [`Program`]. If the code hasn't been run yet, it
+    /// is parsed first. Returns an [`std::io::Error`] if the code fails to
+    /// parse.
+    ///
+    /// [machine]: BrainfuckMachine
+    pub fn into_parts(mut self) -> Result<(BrainfuckMachine<S>, Program)> {
+        let statements = self.parsed_statements()?;
+        Ok((self.machine, Program { statements }))
+    }
+}
+
+/// Fully optimizes a program with the passes enabled in a given config,
+/// assuming that it is run from the start on a zeroed tape. Returns the
+/// optimized program along with the statistics of the optimization.
+pub fn optimize_program(program: Program, config: OptimizerConfig) -> (Program, OptStats) {
+    let statements_before = Statement::count(&program.statements);
+    let mut optimizer = Optimizer::new(program.statements).with_config(config);
+    let report = optimizer.optimize(0);
+    let stats = OptStats {
+        iterations: report.iterations,
+        passes_run: optimizer.passes_run,
+        converged: report.converged,
+        statements_before,
+        statements_after: Statement::count(&optimizer.statements),
+        applied: optimizer.applied.clone(),
+    };
+    let statements = optimizer.yield_back();
+    (Program { statements }, stats)
+}
+
+// Largest amount of pixels of an image rendered from a tape, 256 MiB.
+#[cfg(feature = "image")]
+const MAX_IMAGE_PIXELS: usize = 1 << 28;
+
+/// Renders a tape as a grayscale image of a given width, one pixel per cell.
+/// The cells are laid out row-major and the last row is padded with black
+/// pixels. Returns an [`std::io::Error`] if the width is equal to `0`, or
+/// if the image would have more than 2^28 pixels or can't be allocated.
+#[cfg(feature = "image")]
+pub fn tape_to_image(tape: &[u8], width: u32) -> Result<image::GrayImage> {
+    if width == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Image width must be greater than zero.",
+        ));
+    }
+    let too_large = || Error::new(ErrorKind::InvalidInput, "Image is too large.");
+    let height = tape.len().div_ceil(width as usize).max(1);
+    let size = (width as usize)
+        .checked_mul(height)
+        .filter(|&size| size <= MAX_IMAGE_PIXELS)
+        .ok_or_else(too_large)?;
+    let height = u32::try_from(height).map_err(|_| too_large())?;
+    let mut pixels = Vec::new();
+    pixels
+        .try_reserve_exact(size)
+        .map_err(|_| Error::new(ErrorKind::OutOfMemory, "Image is too large."))?;
+    pixels.extend_from_slice(tape);
+    pixels.resize(size, 0);
+    image::GrayImage::from_raw(width, height, pixels).ok_or_else(too_large)
 }
 
-struct Code<'a> {
-    code: &'a Vec<Statement>,
+/// Renders a tape using [`tape_to_image`] and saves it as a PNG file.
+#[cfg(feature = "image")]
+pub fn save_tape_image(tape: &[u8], width: u32, file_name: &str) -> Result<()> {
+    let image = tape_to_image(tape, width)?;
+    image
+        .save_with_format(file_name, image::ImageFormat::Png)
+        .map_err(|error| match error {
+            image::ImageError::IoError(error) => error,
+            other => Error::other(other),
+        })
 }
-impl<'a> Code<'a> {
+
+// The legacy listing format, see `Interpreter::peek_program_source`.
+struct Code;
+impl Code {
     fn generate_string(statements: &Vec<Statement>) -> String {
         let mut info: String = String::new();
         for statement in statements {
@@ -577,9 +4974,14 @@ impl<'a> Code<'a> {
                 Statement::MoveRight(value) => format!("{}> ", *value),
                 Statement::ReadChar => ", ".to_string(),
                 Statement::PutChar => ". ".to_string(),
-                Statement::Loop(boxed) => {
-                    let loop_stmt = boxed;
-                    format!("[ {}] ", Self::generate_string(&loop_stmt))
+                Statement::Loop(code) => format!("[ {}] ", Self::generate_string(code)),
+                Statement::SideEffect(kind) => format!("{:?} ", kind),
+                Statement::LoadTapeSize => "size ".to_string(),
+                Statement::Random => "rand ".to_string(),
+                Statement::Comment(text) => format!("{:?} ", String::from_utf8_lossy(text)),
+                Statement::ZeroCells(offsets) => format!("0{:?} ", offsets),
+                Statement::FillValue { from, to, value } => {
+                    format!("{}[{}..{}] ", value, from, to)
                 }
             };
             info.push_str(&to_push);
@@ -588,9 +4990,156 @@ impl<'a> Code<'a> {
     }
 }
 
-impl<'a> std::fmt::Debug for Code<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let info: String = Self::generate_string(self.code);
-        f.debug_struct("Code").field("code", &info).finish()
+pub mod codegen;
+
+/// [`proptest`] strategies generating random programs, e.g. to check that
+/// custom optimization passes keep the behavior of the code. Requires the
+/// `proptest` feature.
+#[cfg(feature = "proptest")]
+pub mod strategies {
+    use proptest::prelude::*;
+
+    /// Generates valid code of about `size` commands, with the loops nested
+    /// at most `max_depth` deep. Only the standard commands are used.
+    pub fn code(size: u32, max_depth: u32) -> impl Strategy<Value = String> {
+        let command =
+            prop::sample::select(vec!["+", "-", ">", "<", ".", ","]).prop_map(String::from);
+        command.prop_recursive(max_depth, size, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 1..8).prop_map(|parts| parts.concat()),
+                prop::collection::vec(inner, 0..8)
+                    .prop_map(|parts| format!("[{}]", parts.concat())),
+            ]
+        })
+    }
+
+    /// Generates an input of at most `max_len` printable ASCII bytes.
+    pub fn input(max_len: usize) -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(b' '..=b'~', 0..=max_len)
+    }
+}
+
+/// Helpers for testing brainfuck routines tersely, see [`assert_bf_output`].
+/// Requires the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing {
+    use std::io::{self, Read, Result};
+
+    pub use crate::captured_output::CapturedOutput;
+    use crate::{EofPolicy, Interpreter, Program, Termination};
+
+    /// Amount of steps after which [`run_program`] stops the program.
+    pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+    /// Size of the tape used by [`run_program`].
+    pub const DEFAULT_TAPE_SIZE: usize = 30000;
+
+    /// A reader yielding a fixed sequence of bytes and then the end of the
+    /// input, never touching the terminal.
+    #[derive(Clone, Default, Debug)]
+    pub struct ScriptedInput {
+        bytes: Vec<u8>,
+        position: usize,
+    }
+
+    impl ScriptedInput {
+        /// Creates a reader yielding the given bytes.
+        pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+            Self {
+                bytes: bytes.into(),
+                position: 0,
+            }
+        }
+    }
+
+    impl Read for ScriptedInput {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let amount = (&self.bytes[self.position..]).read(buf)?;
+            self.position += amount;
+            Ok(amount)
+        }
+    }
+
+    /// Creates an interpreter of a parsed program with a tape of a given
+    /// size, reading the given input and writing to the given output. It
+    /// never touches the terminal, the standard streams or the file system,
+    /// and the rest of its settings can be changed with the usual builder
+    /// methods.
+    pub fn interpreter(
+        program: &Program,
+        tape_size: usize,
+        input: ScriptedInput,
+        output: CapturedOutput,
+    ) -> Interpreter<io::Empty> {
+        Interpreter::from_program(program, tape_size)
+            .with_input(Box::new(input))
+            .with_output(Box::new(output))
+    }
+
+    /// Runs the unoptimized source code with a given input on a tape of
+    /// [`DEFAULT_TAPE_SIZE`] cells, stopping it after [`DEFAULT_STEP_LIMIT`]
+    /// steps. Reading past the end of the input sets the cell to zero, see
+    /// [`EofPolicy::Zero`]. Returns the reason why the run has stopped and
+    /// the output. The same source and input always give the same result.
+    pub fn run_program(source: &[u8], input: &[u8]) -> Result<(Termination, Vec<u8>)> {
+        let output = CapturedOutput::default();
+        let termination = Interpreter::from_reader(source, DEFAULT_TAPE_SIZE)
+            .with_input(Box::new(ScriptedInput::new(input)))
+            .with_eof_policy(EofPolicy::Zero)
+            .with_output(Box::new(output.clone()))
+            .with_step_limit(DEFAULT_STEP_LIMIT)
+            .run()?;
+        Ok((termination, output.take()))
     }
+
+    /// Checks that the source code, run with [`run_program`], completes and
+    /// outputs exactly the expected bytes. Panics with both outputs and the
+    /// offset of the first difference otherwise. Used by
+    /// [`assert_bf_output`](crate::assert_bf_output).
+    #[track_caller]
+    pub fn assert_output(source: &[u8], input: &[u8], expected: &[u8]) {
+        let (termination, actual) = match run_program(source, input) {
+            Ok(result) => result,
+            Err(error) => panic!("the program has failed: {}", error),
+        };
+        if termination != Termination::Completed {
+            panic!(
+                "the program has stopped early ({:?}), having output \"{}\"",
+                termination,
+                actual.escape_ascii()
+            );
+        }
+        if actual != expected {
+            let difference = actual
+                .iter()
+                .zip(expected)
+                .position(|(actual, expected)| actual != expected)
+                .unwrap_or(actual.len().min(expected.len()));
+            panic!(
+                "the outputs differ at byte {}\n\
+                 expected: \"{}\" ({} bytes)\n  \
+                 actual: \"{}\" ({} bytes)",
+                difference,
+                expected.escape_ascii(),
+                expected.len(),
+                actual.escape_ascii(),
+                actual.len()
+            );
+        }
+    }
+}
+
+/// Asserts that a brainfuck program, given as anything viewable as bytes
+/// (e.g. a `&str`), outputs the expected bytes for a given input. The
+/// program is run with [`testing::run_program`], so it can't hang the test.
+/// Requires the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+#[macro_export]
+macro_rules! assert_bf_output {
+    ($source:expr, $input:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_output(
+            ::std::convert::AsRef::<[u8]>::as_ref(&$source),
+            ::std::convert::AsRef::<[u8]>::as_ref(&$input),
+            ::std::convert::AsRef::<[u8]>::as_ref(&$expected),
+        )
+    };
 }
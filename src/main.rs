@@ -1,9 +1,15 @@
-use binter::Interpreter;
-use clap::Parser as ClapParser;
+use binter::{
+    codegen::{to_asm_x86_64, to_c},
+    diagnose, diagnostics_to_json, BrainfuckMachine, CapturedOutput, Interpreter, OptimizerConfig,
+    Program, Tape, TapePolicy, Termination,
+};
+use clap::{Parser as ClapParser, ValueEnum};
 use std::{
     fmt::Debug,
-    fs::File,
-    io::{Error, ErrorKind, Result, Write},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Error, IsTerminal, Result, Write},
+    path::Path,
+    process,
 };
 
 #[derive(ClapParser, Debug)]
@@ -14,7 +20,19 @@ struct Cli {
     #[arg(short, long, value_name = "SIZE")]
     size: Option<usize>,
 
-    /// Name of the file to open.
+    #[arg(default_value_t = false, long)]
+    /// Allocates the cells of the tape only once the program writes to them,
+    /// making "--size" a cap on the tape rather than its allocation.
+    lazy_tape: bool,
+
+    #[arg(default_value_t = false, long, conflicts_with = "lazy_tape")]
+    /// Extends the tape with zeroed cells in both directions as the pointer
+    /// leaves it, instead of failing. "--size" is then the initial size.
+    two_sided: bool,
+
+    /// Name of the file to open. Use "-" to read the code from the standard
+    /// input, up to the first '!' or the end of the input. Anything after the
+    /// '!' is passed to the program as its input.
     file: Option<String>,
 
     #[arg(short = 'O', long, value_name = "COUNT")]
@@ -23,7 +41,13 @@ struct Cli {
     /// optimized.
     optimize: Option<u32>,
 
-    #[arg(default_value_t = false, short, long)]
+    #[arg(default_value_t = false, long)]
+    /// If set alongside the "--optimize" flag, disables the optimizations
+    /// that could affect the program's input and output, keeping only the
+    /// arithmetic and movement folding.
+    no_optimize_io: bool,
+
+    #[arg(default_value_t = false, short, long, conflicts_with = "hex")]
     /// If set alongside the "--output" flag, outputs the data in binary
     /// format. Exclusive with "--hex".
     binary: bool,
@@ -37,47 +61,395 @@ struct Cli {
     /// Outputs the machine data to a given FILE. Use "--hex" and "--binary" to
     /// switch from ASCII encoding to other formats.
     output: Option<String>,
+
+    #[arg(default_value_t = false, long)]
+    /// If set alongside the "--output" flag, writes the ASCII and hex data in
+    /// the old format: every value followed by a comma, unpadded hex values
+    /// and no final newline.
+    legacy_format: bool,
+
+    #[arg(long, value_name = "WIDTH", conflicts_with_all = ["binary", "hex", "legacy_format"], value_parser = clap::value_parser!(u64).range(1..))]
+    /// If set alongside the "--output" flag, writes the data as a grid of
+    /// WIDTH columns, e.g. for programs laying out a 2D board on the tape.
+    /// Every cell is a character: "." if it's zero and "#" otherwise.
+    grid: Option<u64>,
+
+    #[arg(short, long, value_name = "FILE")]
+    /// Reads the program's input from a given FILE instead of the standard
+    /// input.
+    input: Option<String>,
+
+    #[arg(long, value_name = "EXPECTED_FILE")]
+    /// Instead of printing the program's output, compares it with the
+    /// contents of EXPECTED_FILE. On a mismatch, the difference is reported
+    /// and the exit status is nonzero.
+    verify: Option<String>,
+
+    #[arg(long, value_name = "FILE")]
+    /// Writes frames with a window of the tape around the pointer to a given
+    /// FILE while the program runs, e.g. to animate the execution. Use
+    /// "--trace-interval" and "--trace-window" to set how often the frames are
+    /// taken and how many cells they show.
+    trace_tape: Option<String>,
+
+    #[arg(long, value_name = "K", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    /// Takes a frame for the "--trace-tape" flag every K steps.
+    trace_interval: u64,
+
+    #[arg(long, value_name = "W", default_value_t = 16)]
+    /// Number of cells shown in a frame of the "--trace-tape" flag.
+    trace_window: usize,
+
+    #[arg(long, value_name = "LANGUAGE", conflicts_with_all = ["binary", "hex", "grid", "legacy_format", "verify", "batch_inputs"])]
+    /// Instead of running the program, translates it to C or to x86-64
+    /// assembly for Linux ("asm"), optimized if "--optimize" is set, and
+    /// writes the result to the "--output" FILE, or to the standard output
+    /// without it.
+    emit: Option<Language>,
+
+    #[arg(long, value_name = "FILE")]
+    /// Counts the reads and the writes of every cell during the run and
+    /// writes them to a given FILE as CSV, with a "cell,accesses" header and
+    /// a line for every cell of the tape.
+    access_counts: Option<String>,
+
+    #[arg(default_value_t = false, long)]
+    /// Instead of running the program, prints a JSON array of the problems
+    /// found in its code, e.g. for an editor to show. Every diagnostic has a
+    /// "severity", a "message", a "line" and a "column" (both counted from
+    /// 1) and a "span" of bytes.
+    diagnostics_json: bool,
+
+    #[arg(default_value_t = false, long)]
+    /// If set alongside the "--diagnostics-json" flag, reports any text other
+    /// than the commands and whitespace as an error instead of treating it as
+    /// a comment.
+    strict: bool,
+
+    #[arg(default_value_t = false, short, long)]
+    /// Writes nothing to the standard error: no warnings, prompts or error
+    /// messages. Failures are only reported by the exit status.
+    quiet: bool,
+
+    #[arg(default_value_t = false, long)]
+    /// Enables the "?" command, which writes a pseudorandom byte into the
+    /// current cell. Use "--seed" to get different bytes.
+    enable_rand: bool,
+
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "enable_rand")]
+    /// Seed of the random bytes written by the "?" command.
+    seed: u64,
+
+    #[arg(long, value_name = "DIR", requires = "batch_outputs", conflicts_with_all = ["input", "verify", "output"])]
+    /// Runs the program once for every file in DIR, with the file as its
+    /// input, and writes each output to the file of the same name in the
+    /// "--batch-outputs" directory. The code is parsed once and the tape is
+    /// cleared before every run.
+    batch_inputs: Option<String>,
+
+    #[arg(long, value_name = "DIR", requires = "batch_inputs")]
+    /// Directory where the outputs of the "--batch-inputs" runs are written.
+    /// It is created if needed.
+    batch_outputs: Option<String>,
+
+    #[cfg(feature = "image")]
+    #[arg(long, value_name = "FILE")]
+    /// Renders the machine data as a grayscale PNG image and saves it to a
+    /// given FILE. Use "--image-width" to set the width of the image.
+    output_image: Option<String>,
+
+    #[cfg(feature = "image")]
+    #[arg(long, value_name = "WIDTH", default_value_t = 256)]
+    /// Width of the image saved with the "--output-image" flag.
+    image_width: u32,
+}
+
+// The languages of the "--emit" flag.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Language {
+    C,
+    Asm,
+}
+
+fn run<T: BufRead, S: Tape<Cell = u8>>(interpreter: Interpreter<T, S>, args: Cli) -> Result<()> {
+    let mut interpreter = interpreter.with_optimizer_config(OptimizerConfig {
+        optimize_io: !args.no_optimize_io,
+        ..Default::default()
+    });
+    if args.quiet {
+        interpreter = interpreter.with_message_output(Box::new(io::sink()));
+    }
+    if args.two_sided {
+        interpreter = interpreter.with_tape_policy(TapePolicy::GrowBoth);
+    }
+    if args.enable_rand {
+        interpreter = interpreter.with_random_command(b'?', args.seed);
+    }
+    if let (Some(inputs), Some(outputs)) = (&args.batch_inputs, &args.batch_outputs) {
+        return run_batch(interpreter, &args, inputs, outputs);
+    }
+    if let Some(language) = args.emit {
+        let size = interpreter.machine_mut().tape_size();
+        let program = compile(&mut interpreter, &args)?;
+        let code = match language {
+            Language::C => to_c(&program, size),
+            Language::Asm => to_asm_x86_64(&program, size),
+        };
+        return match &args.output {
+            Some(path) => fs::write(path, code),
+            None => io::stdout().write_all(code.as_bytes()),
+        };
+    }
+    if let Some(path) = &args.input {
+        interpreter = interpreter.with_input(Box::new(File::open(path)?));
+    }
+    let captured = CapturedOutput::default();
+    if args.verify.is_some() {
+        interpreter = interpreter.with_output(Box::new(captured.clone()));
+    }
+    if args.access_counts.is_some() {
+        interpreter = interpreter.with_access_counts();
+    }
+    if let Some(path) = &args.trace_tape {
+        let trace = BufWriter::new(File::create(path)?);
+        interpreter =
+            interpreter.with_tape_trace(Box::new(trace), args.trace_interval, args.trace_window);
+    }
+    let termination = if let Some(value) = args.optimize {
+        // the warnings of the optimization are written to the standard
+        // error, unless it's quiet
+        interpreter.run_with_optimization(value)?
+    } else {
+        interpreter.run()?
+    };
+    match termination {
+        Termination::Completed | Termination::Halted => {}
+        _ if args.quiet => {}
+        reason => eprintln!("Warning: the run was stopped early ({:?}).", reason),
+    }
+    if let Some(path) = &args.access_counts {
+        let (first, _) = interpreter.machine_mut().tape_range();
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "cell,accesses")?;
+        for (cell, count) in (first..).zip(interpreter.access_counts()) {
+            writeln!(csv, "{},{}", cell, count)?;
+        }
+        csv.flush()?;
+    }
+    #[cfg(feature = "image")]
+    if let Some(path) = &args.output_image {
+        binter::save_tape_image(&interpreter.get_tape(), args.image_width, path)?;
+    }
+    if let Some(path) = args.output {
+        let mut out_file = File::create(path)?;
+        let tape = interpreter.get_tape();
+        let tape_data = tape.as_slice();
+        if args.binary {
+            out_file.write_all(tape_data)?;
+        } else if let Some(width) = args.grid {
+            out_file.write_all(format_grid(tape_data, width as usize).as_bytes())?;
+        } else {
+            out_file.write_all(format_tape(tape_data, args.hex, args.legacy_format).as_bytes())?;
+        }
+    }
+    if let Some(path) = &args.verify {
+        let expected = fs::read(path)?;
+        if let Some(report) = compare_output(&expected, &captured.contents()) {
+            if !args.quiet {
+                eprintln!("Output doesn't match {}: {}", path, report);
+            }
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// Parses the code once, to be run or translated, and optimizes it with the
+// iteration count of the "--optimize" flag if it is set.
+fn compile<T: BufRead, S: Tape>(
+    interpreter: &mut Interpreter<T, S>,
+    args: &Cli,
+) -> Result<Program> {
+    match args.optimize {
+        Some(count) => interpreter.optimized_program(count),
+        None => interpreter.program(),
+    }
+}
+
+// Runs the program on every file of the inputs directory, in the order of
+// their names, writing the outputs to the outputs directory.
+fn run_batch<T: BufRead, S: Tape>(
+    mut interpreter: Interpreter<T, S>,
+    args: &Cli,
+    inputs: &str,
+    outputs: &str,
+) -> Result<()> {
+    let program = compile(&mut interpreter, args)?;
+    fs::create_dir_all(outputs)?;
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(inputs)? {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    for path in paths {
+        let input = fs::read(&path)?;
+        let (termination, output) = interpreter.run_captured(&program, &input)?;
+        match termination {
+            Termination::Completed | Termination::Halted => {}
+            _ if args.quiet => {}
+            reason => eprintln!(
+                "Warning: the run on {} was stopped early ({:?}).",
+                path.display(),
+                reason
+            ),
+        }
+        let name = path.file_name().unwrap_or_default();
+        fs::write(Path::new(outputs).join(name), output)?;
+    }
+    Ok(())
+}
+
+// Describes the first difference between the outputs, if there is any.
+fn compare_output(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let offset = match expected.iter().zip(actual).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if expected.len() == actual.len() => return None,
+        None => expected.len().min(actual.len()),
+    };
+    let line = actual[..offset]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count()
+        + 1;
+    // a few bytes around the difference
+    let context = |bytes: &[u8]| {
+        let start = offset.saturating_sub(8);
+        let end = bytes.len().min(offset + 8);
+        bytes
+            .get(start..end)
+            .unwrap_or_default()
+            .escape_ascii()
+            .to_string()
+    };
+    Some(format!(
+        "the outputs differ at byte {} (line {}), expected \"{}\", got \"{}\" \
+         ({} bytes expected, {} bytes output).",
+        offset,
+        line,
+        context(expected),
+        context(actual),
+        expected.len(),
+        actual.len()
+    ))
+}
+
+// Formats the tape as comma separated values, ended with a newline.
+fn format_tape(tape: &[u8], hex: bool, legacy: bool) -> String {
+    let values = tape.iter().map(|value| match (hex, legacy) {
+        (true, false) => format!("0x{value:02x}"),
+        (true, true) => format!("0x{value:x}"),
+        (false, _) => value.to_string(),
+    });
+    if legacy {
+        values.map(|value| value + ",").collect()
+    } else {
+        values.collect::<Vec<String>>().join(",") + "\n"
+    }
+}
+
+// Formats the tape as rows of a given amount of cells, each ended with a
+// newline. The last row may be shorter.
+fn format_grid(tape: &[u8], width: usize) -> String {
+    tape.chunks(width)
+        .map(|row| {
+            let mut line: String = row
+                .iter()
+                .map(|&value| if value == 0 { '.' } else { '#' })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
 }
 
-fn main() -> Result<()> {
+// Prints the diagnostics of the code for the "--diagnostics-json" flag.
+fn print_diagnostics(file_name: &str, strict: bool) -> Result<()> {
+    let code = if file_name == "-" {
+        let mut code = Vec::new();
+        io::stdin().lock().read_until(b'!', &mut code)?;
+        if code.last() == Some(&b'!') {
+            code.pop();
+        }
+        code
+    } else {
+        fs::read(file_name)?
+    };
+    println!("{}", diagnostics_to_json(&diagnose(&code, strict)));
+    Ok(())
+}
+
+// The errors of the library are already prefixed with "Error: ", the other
+// ones are prefixed the same way.
+fn report(error: &Error) {
+    let message = error.to_string();
+    match message.starts_with("Error: ") {
+        true => eprintln!("{}", message),
+        false => eprintln!("Error: {}", message),
+    }
+}
+
+fn main() {
     let args = Cli::parse();
-    match &args.file {
-        Some(file_name) => {
-            let size = args.size.unwrap_or(30000);
-            let mut interpreter = Interpreter::from_file(&file_name, size)?;
-            if let Some(value) = args.optimize {
-                interpreter.run_with_optimization(value)?;
-            } else {
-                interpreter.run()?;
+    let quiet = args.quiet;
+    if let Err(error) = run_cli(args) {
+        if !quiet {
+            report(&error);
+        }
+        process::exit(1);
+    }
+}
+
+fn run_cli(args: Cli) -> Result<()> {
+    if let (true, Some(file_name)) = (args.diagnostics_json, args.file.as_deref()) {
+        return print_diagnostics(file_name, args.strict);
+    }
+    let size = args.size.unwrap_or(30000);
+    match args.file.as_deref() {
+        Some("-") => {
+            let stdin = io::stdin();
+            if stdin.is_terminal() && !args.quiet {
+                eprintln!("Enter the code, then finish it with '!' or Ctrl-D.");
             }
-            if let Some(path) = args.output {
-                let mut out_file = File::create(path)?;
-                let tape = interpreter.get_tape();
-                let tape_data = tape.as_slice();
-                if args.binary && args.hex {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "Binary and hex flags can't be set simultaneously.",
-                    ));
-                } else if args.binary {
-                    out_file.write_all(tape_data)?;
-                } else if args.hex {
-                    for value in tape {
-                        out_file.write_all(format!("0x{value:x}").as_bytes())?;
-                        out_file.write_all(",".as_bytes())?;
-                    }
-                } else {
-                    for value in tape {
-                        out_file.write_all(value.to_string().as_bytes())?;
-                        out_file.write_all(",".as_bytes())?;
-                    }
+            // reading a single byte at a time leaves everything after the '!'
+            // in the standard input, to be read by the program
+            let reader = BufReader::with_capacity(1, stdin);
+            match args.lazy_tape {
+                true => {
+                    let machine = BrainfuckMachine::lazy(size);
+                    let interpreter =
+                        Interpreter::from_machine(reader, machine).with_code_terminator(b'!');
+                    run(interpreter, args)
+                }
+                false => {
+                    let interpreter =
+                        Interpreter::from_reader(reader, size).with_code_terminator(b'!');
+                    run(interpreter, args)
                 }
             }
-            Ok(())
         }
-        None => Err(Error::new(
-            ErrorKind::Other,
-            "Interactive mode not yet implemented.",
-        )),
+        Some(file_name) => match args.lazy_tape {
+            true => {
+                let machine = BrainfuckMachine::lazy(size);
+                run(
+                    Interpreter::from_file_with_machine(file_name, machine)?,
+                    args,
+                )
+            }
+            false => run(Interpreter::from_file(file_name, size)?, args),
+        },
+        None => Err(Error::other("Interactive mode not yet implemented.")),
     }
 }
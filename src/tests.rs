@@ -1,17 +1,344 @@
+mod checkpoint;
+mod codegen;
+mod diagnostics;
+mod execution;
+#[cfg(feature = "image")]
+mod image;
+mod interpreter;
 mod lexer;
 mod machine;
+mod mutations;
 mod optimizer;
 mod parser;
+mod program;
+mod report;
+mod snapshots;
+mod stress;
+mod testing;
 
 // helper testing functions
 mod utils {
-    use crate::{Lexer, Optimizer, Parser, Statement, Token};
-    use std::io::Error;
-    pub(in crate::tests) fn test_lexer(code: &String, expected: &Vec<Option<Token>>) {
-        let lexer = Lexer {
-            reader: code.as_bytes(),
-        };
-        let mut actual: Vec<Option<Token>> = Vec::new();
+    use crate::{BrainfuckError, Lexer, Optimizer, Parser, Statement, TerminalControl, Token};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::{Cell, RefCell};
+    use std::fs;
+    use std::io::{BufRead, Error, ErrorKind, Read, Result, Write};
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    // an allocator counting the allocations made by each thread, along with
+    // the amount of memory that they hold, so that tests running in parallel
+    // don't disturb each other's counts
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        // memory freed by a thread may have been allocated by another one,
+        // so the amount held can be negative
+        static HELD: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            let _ = HELD.try_with(|held| {
+                held.set(held.get() + layout.size() as isize);
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(held.get())));
+            });
+            unsafe { System.alloc(layout) }
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = HELD.try_with(|held| held.set(held.get() - layout.size() as isize));
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    pub(in crate::tests) fn count_allocations<R>(function: impl FnOnce() -> R) -> (R, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = function();
+        (result, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    // Returns the most memory held at once by the current thread while
+    // running the function, on top of what it held before.
+    pub(in crate::tests) fn peak_memory<R>(function: impl FnOnce() -> R) -> (R, usize) {
+        let before = HELD.with(Cell::get);
+        PEAK.with(|peak| peak.set(before));
+        let result = function();
+        (result, (PEAK.with(Cell::get) - before) as usize)
+    }
+
+    // a tiny deterministic generator, so that the "random" programs are the
+    // same on every run
+    pub(in crate::tests) struct Lcg(pub u64);
+
+    impl Lcg {
+        pub(in crate::tests) fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    // a terminal counting how many times the raw mode was switched on and off
+    #[derive(Clone, Default)]
+    pub(in crate::tests) struct MockTerminal {
+        enabled: Rc<Cell<usize>>,
+        disabled: Rc<Cell<usize>>,
+    }
+
+    impl MockTerminal {
+        pub(in crate::tests) fn calls(&self) -> (usize, usize) {
+            (self.enabled.get(), self.disabled.get())
+        }
+    }
+
+    impl TerminalControl for MockTerminal {
+        fn enable_raw_mode(&mut self) {
+            self.enabled.set(self.enabled.get() + 1);
+        }
+        fn disable_raw_mode(&mut self) {
+            self.disabled.set(self.disabled.get() + 1);
+        }
+    }
+
+    // a writer that only makes the written bytes visible when flushed
+    #[derive(Clone, Default)]
+    pub(in crate::tests) struct FlushedBuffer {
+        pending: Rc<RefCell<Vec<u8>>>,
+        flushed: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl FlushedBuffer {
+        pub(in crate::tests) fn contents(&self) -> Vec<u8> {
+            self.flushed.borrow().clone()
+        }
+    }
+
+    impl Write for FlushedBuffer {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.pending.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            let mut pending = self.pending.borrow_mut();
+            self.flushed.borrow_mut().append(&mut pending);
+            Ok(())
+        }
+    }
+
+    // a reader delivering its data in small chunks, counting how many times
+    // a new chunk had to be fetched
+    pub(in crate::tests) struct ChunkedReader {
+        data: Vec<u8>,
+        chunk: usize,
+        position: usize,
+        chunk_end: usize,
+        pub fetches: usize,
+    }
+
+    impl ChunkedReader {
+        pub(in crate::tests) fn new(data: &[u8], chunk: usize) -> Self {
+            Self {
+                data: data.to_vec(),
+                chunk,
+                position: 0,
+                chunk_end: 0,
+                fetches: 0,
+            }
+        }
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let available = self.fill_buf()?;
+            let amount = available.len().min(buf.len());
+            buf[..amount].copy_from_slice(&available[..amount]);
+            self.consume(amount);
+            Ok(amount)
+        }
+    }
+
+    impl BufRead for ChunkedReader {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            if self.position == self.chunk_end {
+                self.chunk_end = (self.position + self.chunk).min(self.data.len());
+                self.fetches += 1;
+            }
+            Ok(&self.data[self.position..self.chunk_end])
+        }
+        fn consume(&mut self, amount: usize) {
+            self.position += amount;
+        }
+    }
+
+    // a reader made of separate segments, where `fill_buf` only ever shows
+    // the current segment while `read` goes across the segment boundaries
+    pub(in crate::tests) struct SegmentedReader {
+        segments: Vec<Vec<u8>>,
+        segment: usize,
+        position: usize,
+    }
+
+    impl SegmentedReader {
+        pub(in crate::tests) fn new(segments: &[&[u8]]) -> Self {
+            Self {
+                segments: segments.iter().map(|segment| segment.to_vec()).collect(),
+                segment: 0,
+                position: 0,
+            }
+        }
+    }
+
+    impl Read for SegmentedReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let mut amount = 0;
+            while amount < buf.len() && self.segment < self.segments.len() {
+                let available = &self.segments[self.segment][self.position..];
+                let count = available.len().min(buf.len() - amount);
+                buf[amount..amount + count].copy_from_slice(&available[..count]);
+                amount += count;
+                self.consume(count);
+                if self.position == self.segments[self.segment].len() {
+                    self.segment += 1;
+                    self.position = 0;
+                }
+            }
+            Ok(amount)
+        }
+    }
+
+    impl BufRead for SegmentedReader {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            while self.segment < self.segments.len()
+                && self.position == self.segments[self.segment].len()
+            {
+                self.segment += 1;
+                self.position = 0;
+            }
+            match self.segments.get(self.segment) {
+                Some(segment) => Ok(&segment[self.position..]),
+                None => Ok(&[]),
+            }
+        }
+        fn consume(&mut self, amount: usize) {
+            self.position += amount;
+        }
+    }
+
+    // a writer failing every write with a given error kind
+    pub(in crate::tests) struct FailingWriter(pub ErrorKind);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> Result<usize> {
+            Err(Error::from(self.0))
+        }
+        fn flush(&mut self) -> Result<()> {
+            Err(Error::from(self.0))
+        }
+    }
+
+    // a reader failing every read with a given error kind
+    pub(in crate::tests) struct FailingReader(pub ErrorKind);
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+            Err(Error::from(self.0))
+        }
+    }
+
+    // a writer discarding the data, counting the calls made to it
+    #[derive(Clone, Default)]
+    pub(in crate::tests) struct CountingWriter {
+        writes: Rc<Cell<usize>>,
+        flushes: Rc<Cell<usize>>,
+    }
+
+    impl CountingWriter {
+        pub(in crate::tests) fn calls(&self) -> (usize, usize) {
+            (self.writes.get(), self.flushes.get())
+        }
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.writes.set(self.writes.get() + 1);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> Result<()> {
+            self.flushes.set(self.flushes.get() + 1);
+            Ok(())
+        }
+    }
+
+    // a reader counting the calls made to it
+    pub(in crate::tests) struct CountingReader<R> {
+        inner: R,
+        reads: Rc<Cell<usize>>,
+    }
+
+    impl<R> CountingReader<R> {
+        pub(in crate::tests) fn new(inner: R) -> (Self, Rc<Cell<usize>>) {
+            let reads = Rc::new(Cell::new(0));
+            let reader = Self {
+                inner,
+                reads: reads.clone(),
+            };
+            (reader, reads)
+        }
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read(buf)
+        }
+    }
+
+    // a clock that only moves when told to, its clones share the time
+    #[derive(Clone, Default)]
+    pub(in crate::tests) struct MockClock(Rc<Cell<Duration>>);
+
+    impl MockClock {
+        pub(in crate::tests) fn now(&self) -> Duration {
+            self.0.get()
+        }
+        pub(in crate::tests) fn advance(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+    }
+
+    // a reader and a writer taking a given time of a clock for every call
+    pub(in crate::tests) struct SlowReader<R>(pub R, pub MockClock, pub Duration);
+
+    impl<R: Read> Read for SlowReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.1.advance(self.2);
+            self.0.read(buf)
+        }
+    }
+
+    pub(in crate::tests) struct SlowWriter(pub MockClock, pub Duration);
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.advance(self.1);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    pub(in crate::tests) fn test_lexer(code: &String, expected: &Vec<Token>) {
+        let lexer = Lexer::new(code.as_bytes());
+        let mut actual: Vec<Token> = Vec::new();
         for token in lexer {
             actual.push(token);
         }
@@ -30,10 +357,66 @@ mod utils {
         assert_eq!(parsed.to_string(), error.to_string(),);
     }
 
-    pub(in crate::tests) fn test_optimize_once(input: &Vec<Statement>, output: &Vec<Statement>) {
-        let mut optimizer = Optimizer::new(input.clone());
+    pub(in crate::tests) fn test_optimize_once(input: &[Statement], output: &Vec<Statement>) {
+        let mut optimizer = Optimizer::new(input.to_vec());
         optimizer.optimize_once();
         let optimized = optimizer.yield_back();
         assert_eq!(*optimized, *output);
     }
+
+    pub(in crate::tests) fn test_fuse_clears(input: &[Statement], output: &Vec<Statement>) {
+        let mut optimizer = Optimizer::new(input.to_vec());
+        optimizer.fuse_clears();
+        let optimized = optimizer.yield_back();
+        assert_eq!(*optimized, *output);
+    }
+
+    pub(in crate::tests) fn test_fill_regions(input: &[Statement], output: &Vec<Statement>) {
+        let mut optimizer = Optimizer::new(input.to_vec());
+        optimizer.fill_regions();
+        let optimized = optimizer.yield_back();
+        assert_eq!(*optimized, *output);
+    }
+
+    pub(in crate::tests) fn test_remove_dead_loops(input: &[Statement], output: &Vec<Statement>) {
+        let mut optimizer = Optimizer::new(input.to_vec());
+        optimizer.remove_dead_loops();
+        let optimized = optimizer.yield_back();
+        assert_eq!(*optimized, *output);
+    }
+
+    // Compares a rendering with the snapshot of a given name, kept in
+    // `src/tests/snapshots`. Trailing whitespace and blank lines at the end
+    // are ignored, so that only the content is compared. Run the tests with
+    // `UPDATE_SNAPSHOTS=1` to write the current renderings instead, then
+    // review the diff.
+    pub(in crate::tests) fn assert_snapshot(name: &str, actual: &str) {
+        let normalize = |text: &str| {
+            let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+            lines.join("\n").trim_end().to_string() + "\n"
+        };
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests/snapshots")
+            .join(format!("{name}.txt"));
+        let actual = normalize(actual);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected =
+            fs::read_to_string(&path).unwrap_or_else(|error| panic!("{}: {error}", path.display()));
+        assert!(
+            normalize(&expected) == actual,
+            "snapshot {name} differs, run with UPDATE_SNAPSHOTS=1 to update it\n\
+             expected:\n{expected}\nactual:\n{actual}"
+        );
+    }
+
+    // The BrainfuckError wrapped in an error of a run, e.g. a move past the
+    // tape.
+    pub(in crate::tests) fn bounds_error(error: &Error) -> BrainfuckError {
+        assert_eq!(error.kind(), ErrorKind::Other);
+        let inner = error.get_ref().unwrap();
+        inner.downcast_ref::<BrainfuckError>().unwrap().clone()
+    }
 }
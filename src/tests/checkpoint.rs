@@ -0,0 +1,189 @@
+use std::fs;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::{
+    testing::CapturedOutput, Checkpoint, Interpreter, LoopSemantics, Statement, Termination,
+};
+
+// Saves and loads a checkpoint the way it would be stored on disk.
+#[cfg(feature = "serde")]
+fn round_trip(checkpoint: Checkpoint) -> Checkpoint {
+    let json = serde_json::to_string(&checkpoint).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[cfg(not(feature = "serde"))]
+fn round_trip(checkpoint: Checkpoint) -> Checkpoint {
+    checkpoint
+}
+
+fn uninterrupted(code: &str, optimize: Option<u32>) -> (Vec<u8>, Vec<u8>, u64) {
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 1000).with_output(Box::new(output.clone()));
+    let termination = match optimize {
+        Some(max_iterations) => interpreter.run_with_optimization(max_iterations),
+        None => interpreter.run(),
+    };
+    assert_eq!(termination.unwrap(), Termination::Completed);
+    (
+        output.contents(),
+        interpreter.get_tape(),
+        interpreter.stats().steps,
+    )
+}
+
+// Runs the code for a given amount of steps, then continues it from a
+// checkpoint, returning the output of both parts, the final tape and steps.
+fn resumed(code: &str, steps: u64, optimize: Option<u32>) -> (Vec<u8>, Vec<u8>, u64) {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 1000)
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(steps);
+    let termination = match optimize {
+        Some(max_iterations) => interpreter.run_with_optimization(max_iterations),
+        None => interpreter.run(),
+    };
+    assert_eq!(termination.unwrap(), Termination::StepLimit);
+    let checkpoint = round_trip(interpreter.checkpoint().unwrap());
+    assert_eq!(checkpoint.steps, steps);
+    let mut resumed = Interpreter::resume(checkpoint)
+        .unwrap()
+        .with_output(Box::new(output.clone()));
+    assert_eq!(resumed.run().unwrap(), Termination::Completed);
+    (output.contents(), resumed.get_tape(), resumed.stats().steps)
+}
+
+#[test]
+fn test_resume_matches_uninterrupted_run() {
+    let code = fs::read_to_string("tests/programs/hello.b").unwrap();
+    let expected = uninterrupted(&code, None);
+    for steps in [1, 2, 17, 100, expected.2 / 2, expected.2 - 1] {
+        assert_eq!(resumed(&code, steps, None), expected, "{steps} steps");
+    }
+}
+
+#[test]
+fn test_resume_optimized_run() {
+    let code = fs::read_to_string("tests/programs/sierpinski.b").unwrap();
+    let expected = uninterrupted(&code, Some(0));
+    for steps in [5, 1234, expected.2 / 2, expected.2 - 1] {
+        assert_eq!(resumed(&code, steps, Some(0)), expected, "{steps} steps");
+    }
+}
+
+#[test]
+fn test_resume_twice() {
+    let code = fs::read_to_string("tests/programs/hello.b").unwrap();
+    let expected = uninterrupted(&code, None);
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 1000)
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(300);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    let checkpoint = round_trip(interpreter.checkpoint().unwrap());
+    // the limit counts the steps of the whole run
+    let mut interpreter = Interpreter::resume(checkpoint)
+        .unwrap()
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(600);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    let checkpoint = round_trip(interpreter.checkpoint().unwrap());
+    assert_eq!(checkpoint.steps, 600);
+    let mut interpreter = Interpreter::resume(checkpoint)
+        .unwrap()
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), expected.0);
+    // a completed run is continued with nothing
+    let checkpoint = interpreter.checkpoint().unwrap();
+    assert_eq!(checkpoint.pc, [checkpoint.program.len()]);
+    let mut interpreter = Interpreter::resume(checkpoint)
+        .unwrap()
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), expected.0);
+}
+
+#[test]
+fn test_resume_do_while_loops() {
+    let code = "+++[>++<-]>[>+++<--].>.";
+    let run = |steps: Option<u64>| {
+        let output = CapturedOutput::default();
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+            .with_output(Box::new(output.clone()))
+            .with_loop_semantics(LoopSemantics::DoWhile)
+            .with_step_limit(steps.unwrap_or(u64::MAX));
+        if interpreter.run().unwrap() == Termination::Completed {
+            return (output.contents(), interpreter.get_tape());
+        }
+        let checkpoint = round_trip(interpreter.checkpoint().unwrap());
+        let mut interpreter = Interpreter::resume(checkpoint)
+            .unwrap()
+            .with_output(Box::new(output.clone()))
+            .with_loop_semantics(LoopSemantics::DoWhile);
+        assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+        (output.contents(), interpreter.get_tape())
+    };
+    let expected = run(None);
+    for steps in 1..40 {
+        assert_eq!(run(Some(steps)), expected, "{steps} steps");
+    }
+}
+
+#[test]
+fn test_resume_after_interrupt() {
+    let code = "++++[>+++<-]>.";
+    let flag = Arc::new(AtomicBool::new(true));
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_interrupt(flag, None);
+    assert_eq!(interpreter.run().unwrap(), Termination::Interrupted);
+    let checkpoint = round_trip(interpreter.checkpoint().unwrap());
+    // stopped at the guard of the loop, past the end of its body
+    assert_eq!(checkpoint.pc, [4, 6]);
+    assert_eq!(checkpoint.steps, 5);
+    let mut interpreter = Interpreter::resume(checkpoint)
+        .unwrap()
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [12]);
+}
+
+#[test]
+fn test_checkpoint_not_resumable() {
+    let interpreter =
+        Interpreter::from_reader("+.+.".as_bytes(), 10).with_output(Box::new(std::io::sink()));
+    assert!(interpreter.checkpoint().is_err());
+    let mut interpreter = interpreter.with_output_limit(1);
+    assert_eq!(interpreter.run().unwrap(), Termination::OutputLimit);
+    assert!(interpreter.checkpoint().is_err());
+}
+
+#[test]
+fn test_resume_inconsistent_checkpoint() {
+    let checkpoint = Checkpoint {
+        program: vec![Statement::Add(1), Statement::PutChar],
+        tape: vec![0; 4],
+        pointer: 0,
+        pc: vec![0, 0],
+        steps: 0,
+        random: 0,
+    };
+    let pcs: [&[usize]; 3] = [&[], &[0, 0], &[3]];
+    for pc in pcs {
+        let checkpoint = Checkpoint {
+            pc: pc.to_vec(),
+            ..checkpoint.clone()
+        };
+        assert!(Interpreter::resume(checkpoint).is_err(), "{pc:?}");
+    }
+    let checkpoint = Checkpoint {
+        pc: vec![2],
+        pointer: 4,
+        ..checkpoint
+    };
+    assert!(Interpreter::resume(checkpoint).is_err());
+}
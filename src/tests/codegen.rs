@@ -0,0 +1,162 @@
+use crate::{
+    codegen::{emit_c, to_asm_x86_64, to_c},
+    optimize_program, OptimizerConfig, Program, Statement,
+};
+
+#[test]
+fn test_asm_of_folded_code() {
+    let program: Program = ">++>+++<<-[->+<],.".parse().unwrap();
+    let config = OptimizerConfig {
+        remove_dead_loops: false,
+        fuse_clears: false,
+        ..Default::default()
+    };
+    let (program, _) = optimize_program(program, config);
+    let asm = to_asm_x86_64(&program, 300);
+    let expected = [
+        "_start:",
+        "    lea tape(%rip), %rsi",
+        "    add $1, %rsi",
+        "    addb $2, (%rsi)",
+        "    add $1, %rsi",
+        "    addb $3, (%rsi)",
+        "    sub $2, %rsi",
+        "    addb $255, (%rsi)",
+        "    cmpb $0, (%rsi)",
+        "    je .Lend0",
+        ".Lloop0:",
+        "    addb $255, (%rsi)",
+        "    add $1, %rsi",
+        "    addb $1, (%rsi)",
+        "    sub $1, %rsi",
+        "    cmpb $0, (%rsi)",
+        "    jne .Lloop0",
+        ".Lend0:",
+        // `,`
+        "    xor %eax, %eax",
+        "    xor %edi, %edi",
+        "    mov $1, %edx",
+        "    syscall",
+        // `.`
+        "    mov $1, %eax",
+        "    mov $1, %edi",
+        "    mov $1, %edx",
+        "    syscall",
+        // exit(0)
+        "    mov $60, %eax",
+        "    xor %edi, %edi",
+        "    syscall",
+    ];
+    let lines: Vec<&str> = asm.lines().collect();
+    let start = lines.iter().position(|&line| line == "_start:").unwrap();
+    assert_eq!(lines[start..start + expected.len()], expected);
+    assert!(asm.contains(".lcomm tape, 300"));
+}
+
+#[test]
+fn test_asm_labels_unique() {
+    let program: Program = "+[>[-]<[>+<-]]".parse().unwrap();
+    let asm = to_asm_x86_64(&program, 10);
+    for label in 0..3 {
+        assert_eq!(asm.matches(&format!(".Lloop{label}:")).count(), 1);
+        assert_eq!(asm.matches(&format!(".Lend{label}:")).count(), 1);
+    }
+}
+
+#[test]
+fn test_asm_of_cleared_cells() {
+    let program: Program = ",>,[-]>[-]<".parse().unwrap();
+    let (program, _) = optimize_program(program, OptimizerConfig::default());
+    let asm = to_asm_x86_64(&program, 10);
+    assert!(asm.contains("    movb $0, 0(%rsi)\n    movb $0, 1(%rsi)\n"));
+    assert!(!asm.contains("cmpb"));
+}
+
+#[test]
+fn test_asm_of_random() {
+    let program = Program {
+        statements: vec![Statement::Random, Statement::PutChar],
+    };
+    let asm = to_asm_x86_64(&program, 10);
+    // the state of the generator starts at the zero seed
+    assert!(asm.contains("random:\n    .quad 0\n"));
+    assert_eq!(asm.matches("random(%rip)").count(), 2);
+    assert!(asm.contains("    mov %al, (%rsi)\n"));
+}
+
+#[test]
+fn test_c_of_folded_code() {
+    let program: Program = ">++>+++<<-[->+<],.".parse().unwrap();
+    let config = OptimizerConfig {
+        remove_dead_loops: false,
+        fuse_clears: false,
+        ..Default::default()
+    };
+    let (program, _) = optimize_program(program, config);
+    let c = to_c(&program, 300);
+    let expected = [
+        "int main(void) {",
+        "    unsigned char *p = tape;",
+        "    int c;",
+        "    p += 1;",
+        "    *p += 2;",
+        "    p += 1;",
+        "    *p += 3;",
+        "    p -= 2;",
+        "    *p += 255;",
+        "    while (*p) {",
+        "        *p += 255;",
+        "        p += 1;",
+        "        *p += 1;",
+        "        p -= 1;",
+        "    }",
+        "    fflush(stdout); if ((c = getchar()) != EOF) *p = c;",
+        "    putchar(*p);",
+        "    return 0;",
+        "}",
+    ];
+    let lines: Vec<&str> = c.lines().collect();
+    let start = lines.iter().position(|&line| line == expected[0]).unwrap();
+    assert_eq!(lines[start..], expected);
+    assert!(c.contains("static unsigned char tape[300];"));
+    assert!(!c.contains("next_random"));
+}
+
+#[test]
+fn test_c_of_optimized_statements() {
+    let program = Program {
+        statements: vec![
+            Statement::ZeroCells(vec![-1, 2]),
+            Statement::FillValue {
+                from: -2,
+                to: 3,
+                value: 7,
+            },
+            Statement::LoadTapeSize,
+            Statement::Random,
+            Statement::PutChar,
+        ],
+    };
+    let c = to_c(&program, 300);
+    assert!(c.contains("    p[-1] = 0; p[2] = 0;\n"));
+    assert!(c.contains("    memset(p + -2, 7, 5);\n"));
+    assert!(c.contains("    *p = 44;\n"));
+    assert!(c.contains("    *p = next_random();\n"));
+    assert!(c.contains("static unsigned long long random_state;"));
+    // nothing is read
+    assert!(!c.contains("int c;"));
+}
+
+#[test]
+fn test_emit_c_of_statements() {
+    let statements = vec![
+        Statement::ReadChar,
+        Statement::new_loop(vec![Statement::Add(255), Statement::PutChar]),
+    ];
+    let c = emit_c(&statements, 30);
+    assert!(c.contains("static unsigned char tape[30];"));
+    assert!(c.contains("    int c;\n"));
+    assert!(c.contains("    while (*p) {\n        *p += 255;\n        putchar(*p);\n    }\n"));
+    let program = Program { statements };
+    assert_eq!(to_c(&program, 30), c);
+}
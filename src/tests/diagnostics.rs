@@ -0,0 +1,45 @@
+use crate::{diagnose, diagnostics_to_json, Diagnostic, Severity, Span};
+
+#[test]
+fn test_diagnose_valid_code() {
+    assert_eq!(diagnose(b"+[->+<]>. a comment", false), vec![]);
+}
+
+#[test]
+fn test_diagnose_unbalanced_loops() {
+    // only the innermost loop is reported, the outer one moves the pointer
+    // by an amount depending on the data
+    let diagnostics = diagnose(b"+[>[<<]>]", false);
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic {
+            severity: Severity::Warning,
+            message: "The loop moves the pointer by -2 on every iteration.".to_string(),
+            line: 1,
+            column: 4,
+            span: Span { start: 3, end: 7 },
+        }]
+    );
+    assert!(diagnose(b"+[>[-]<]", false).is_empty());
+}
+
+#[test]
+fn test_diagnose_strict() {
+    let code = "+ dé\t-".as_bytes();
+    assert!(diagnose(code, false).is_empty());
+    let diagnostics = diagnose(code, true);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Unexpected text \"dé\".");
+    assert_eq!(diagnostics[0].span, Span { start: 2, end: 5 });
+}
+
+#[test]
+fn test_diagnostics_json_escaping() {
+    let diagnostics = diagnose(b"\"\\\x01", true);
+    assert_eq!(
+        diagnostics_to_json(&diagnostics),
+        "[{\"severity\":\"error\",\"message\":\"Unexpected text \\\"\\\"\\\\\\u0001\\\".\",\
+         \"line\":1,\"column\":1,\"span\":{\"start\":0,\"end\":3}}]"
+    );
+    assert_eq!(diagnostics_to_json(&[]), "[]");
+}
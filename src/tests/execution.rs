@@ -0,0 +1,139 @@
+// Direct tests of the execution of parsed programs, through interpreters
+// that have no access to the terminal, the standard streams or files.
+use crate::testing::{interpreter, CapturedOutput, ScriptedInput};
+use crate::{BrainfuckError, EofPolicy, Interpreter, LoopSemantics, Program, Termination};
+
+use std::io;
+
+use super::utils::bounds_error;
+
+// An interpreter of the code, reading a given input.
+fn scripted(code: &str, tape_size: usize, input: &str) -> (Interpreter<io::Empty>, CapturedOutput) {
+    let program: Program = code.parse().unwrap();
+    let output = CapturedOutput::default();
+    let interpreter = interpreter(
+        &program,
+        tape_size,
+        ScriptedInput::new(input),
+        output.clone(),
+    );
+    (interpreter, output)
+}
+
+#[test]
+fn test_loop_execution_count() {
+    let (mut interpreter, _) = scripted("+++++[>++<-]", 10, "");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..2], [0, 10]);
+    // 5 additions, the loop, then 5 iterations of a check and 5 statements
+    assert_eq!(interpreter.stats().steps, 5 + 1 + 5 * 6);
+    assert_eq!(interpreter.stats().max_depth, 1);
+}
+
+#[test]
+fn test_loop_not_entered() {
+    let (mut interpreter, _) = scripted("[>+<-]+", 10, "");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..2], [1, 0]);
+    assert_eq!(interpreter.stats().steps, 2);
+    assert_eq!(interpreter.stats().max_depth, 0);
+}
+
+#[test]
+fn test_nested_loops() {
+    let (mut interpreter, _) = scripted("++[>+++[>++<-]<-]", 10, "");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..3], [0, 0, 12]);
+    assert_eq!(interpreter.stats().max_depth, 2);
+}
+
+#[test]
+fn test_io_ordering() {
+    // every byte is written before the next one is read
+    let (mut interpreter, output) = scripted(",.>,.<.>>,[.[-],]", 10, "abcd");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"abacd");
+}
+
+#[test]
+fn test_input_end_under_each_eof_policy() {
+    for (eof_policy, expected) in [
+        (EofPolicy::Unchanged, 3),
+        (EofPolicy::Zero, 0),
+        (EofPolicy::Max, 255),
+    ] {
+        let (interpreter, _) = scripted(",>+++,", 10, "x");
+        let mut interpreter = interpreter.with_eof_policy(eof_policy);
+        assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+        assert_eq!(
+            interpreter.get_tape()[..2],
+            [b'x', expected],
+            "{eof_policy:?}"
+        );
+    }
+}
+
+#[test]
+fn test_tape_boundaries() {
+    // the last cell can be reached, but not passed
+    let (mut interpreter, _) = scripted(">>+", 3, "");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape(), [0, 0, 1]);
+    let (mut interpreter, _) = scripted("+[>+]", 3, "");
+    let error = interpreter.run().unwrap_err();
+    // there is no source code to point at
+    assert_eq!(
+        bounds_error(&error),
+        BrainfuckError::TapeOverflow {
+            index: 2,
+            shift: 1,
+            span: None
+        }
+    );
+    assert_eq!(interpreter.get_tape(), [1, 1, 1]);
+}
+
+#[test]
+fn test_tape_boundaries_under_each_loop_semantics() {
+    // the loop is only entered with do-while semantics, moving the pointer
+    // off the tape
+    for (loop_semantics, underflows) in [
+        (LoopSemantics::While, false),
+        (LoopSemantics::DoWhile, true),
+    ] {
+        let (interpreter, _) = scripted("[<]", 3, "");
+        let mut interpreter = interpreter.with_loop_semantics(loop_semantics);
+        let result = interpreter.run();
+        match underflows {
+            false => assert_eq!(result.unwrap(), Termination::Completed),
+            true => assert_eq!(
+                bounds_error(&result.unwrap_err()),
+                BrainfuckError::TapeUnderflow {
+                    index: 0,
+                    shift: 1,
+                    span: None
+                }
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_limits_stop_the_run() {
+    let (interpreter, output) = scripted("+[.]", 3, "");
+    let mut interpreter = interpreter.with_output_limit(4);
+    assert_eq!(interpreter.run().unwrap(), Termination::OutputLimit);
+    assert_eq!(output.contents(), [1; 4]);
+    let (interpreter, _) = scripted("+[]", 3, "");
+    let mut interpreter = interpreter.with_step_limit(100);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    assert_eq!(interpreter.stats().steps, 101);
+}
+
+#[test]
+fn test_program_runs_without_terminal() {
+    let (mut interpreter, output) = scripted(",[.[-],]", 3, "abc");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert!(interpreter.terminal.is_none());
+    assert_eq!(output.contents(), b"abc");
+}
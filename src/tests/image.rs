@@ -0,0 +1,37 @@
+use crate::tape_to_image;
+
+#[test]
+fn test_tape_to_image_dimensions() {
+    let tape: Vec<u8> = (0..10).collect();
+    let image = tape_to_image(&tape, 4).unwrap();
+    assert_eq!(image.dimensions(), (4, 3));
+    assert_eq!(image.get_pixel(1, 0).0, [1]);
+    assert_eq!(image.get_pixel(0, 2).0, [8]);
+    // padding
+    assert_eq!(image.get_pixel(3, 2).0, [0]);
+}
+
+#[test]
+fn test_tape_to_image_png() {
+    let tape: Vec<u8> = (0..=255).collect();
+    let image = tape_to_image(&tape, 16).unwrap();
+    let mut png: Vec<u8> = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let decoded = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (16, 16));
+    assert_eq!(decoded.into_luma8().into_raw(), tape);
+}
+
+#[test]
+fn test_tape_to_image_zero_width() {
+    assert!(tape_to_image(&[0, 1, 2], 0).is_err());
+}
+
+#[test]
+fn test_tape_to_image_too_large() {
+    let error = tape_to_image(&[0, 1, 2], u32::MAX).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(tape_to_image(&[0; 16], 1 << 24).is_ok());
+}
@@ -0,0 +1,1936 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{self, BufWriter, Cursor, ErrorKind};
+use std::iter::zip;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    testing::CapturedOutput, BrainfuckError, BrainfuckMachine, CellOverflow, CostTable, EofPolicy,
+    InputSource, Interpreter, LoopSemantics, MemoCache, NewlineTranslation, OnWriteError, Parser,
+    PhaseTimings, Program, SideEffectKind, Span, Statement, StatementTimings, Tape, TapePolicy,
+    Termination, TerminationVerdict, VecTape,
+};
+
+use super::utils::{
+    bounds_error, count_allocations, ChunkedReader, CountingReader, CountingWriter, FailingWriter,
+    FlushedBuffer, Lcg, MockClock, MockTerminal, SlowReader, SlowWriter,
+};
+
+#[test]
+fn test_run_infinite_empty_loop_hits_step_limit() {
+    let code = String::from("+[]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10).with_step_limit(1000);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+}
+
+#[test]
+fn test_run_hits_output_limit() {
+    let code = String::from("+[.]");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_output_limit(5);
+    assert_eq!(interpreter.run().unwrap(), Termination::OutputLimit);
+    assert_eq!(output.contents(), [1; 5]);
+}
+
+#[test]
+fn test_output_hash() {
+    // prints "Hello World!" followed by a newline
+    let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.\
+                <-.<.+++.------.--------.>>+.>++.";
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(std::io::sink()))
+        .with_output_hash();
+    assert_eq!(interpreter.output_hash(), Some(0));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.output_hash(), Some(0x7d14dddd));
+}
+
+#[test]
+fn test_output_hash_of_large_output() {
+    // prints the bytes from 1 to 255, 40 times, without keeping the output
+    let code = "++++++++[>+++++<-]>[<+[.+]>-]";
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(std::io::sink()))
+        .with_output_hash();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.output_hash(), Some(0x5838c6e3));
+    assert_eq!(
+        Interpreter::from_reader(code.as_bytes(), 10).output_hash(),
+        None
+    );
+}
+
+#[test]
+fn test_run_hits_timeout() {
+    let code = String::from("+[]");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_timeout(Duration::from_millis(10));
+    assert_eq!(interpreter.run().unwrap(), Termination::Timeout);
+    assert!(interpreter.stats().steps > 1);
+}
+
+#[test]
+fn test_run_interrupted_from_another_thread() {
+    let code = String::from("+[>+<]");
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_interrupt(flag.clone(), None);
+    let host = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        flag.store(true, Ordering::Relaxed);
+        flag
+    });
+    assert_eq!(interpreter.run().unwrap(), Termination::Interrupted);
+    // the run is paused at a loop guard check, between the iterations
+    assert_eq!(interpreter.get_tape()[0], 1);
+    assert!(interpreter.stats().steps > 1);
+    assert_eq!(interpreter.machine_mut().index(), 0);
+    assert!(!host.join().unwrap().load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_run_interrupt_handler() {
+    // the handler ends the otherwise infinite loop
+    let code = String::from("+[]>+");
+    let handler: Program = "-".parse().unwrap();
+    let flag = Arc::new(AtomicBool::new(true));
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_interrupt(flag.clone(), Some(handler));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..2], [0, 1]);
+    assert!(!flag.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_run_with_optimization_infinite_empty_loop_hits_step_limit() {
+    let code = String::from("+[]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10).with_step_limit(1000);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::StepLimit
+    );
+}
+
+#[test]
+fn test_run_within_step_limit() {
+    let code = String::from("+++[>++<-][]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10).with_step_limit(1000);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..2], [0, 6]);
+}
+
+#[test]
+fn test_saturating_cells() {
+    let mut interpreter = Interpreter::from_reader("+[+]".as_bytes(), 10)
+        .with_cell_overflow(CellOverflow::Saturate)
+        .with_step_limit(10_000);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    assert_eq!(interpreter.get_tape()[0], 255);
+    // the warning is given before running
+    let mut interpreter = Interpreter::from_reader("+[+]".as_bytes(), 10)
+        .with_cell_overflow(CellOverflow::Saturate)
+        .with_step_limit(10_000);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::StepLimit
+    );
+    assert_eq!(
+        interpreter.optimization_report().termination,
+        TerminationVerdict::GuaranteedInfinite
+    );
+    // with wrapping, the cell gets back to zero
+    let mut interpreter = Interpreter::from_reader("+[+]".as_bytes(), 10);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(
+        interpreter.optimization_report().termination,
+        TerminationVerdict::Unknown
+    );
+    // subtracting stops at zero
+    let mut interpreter = Interpreter::from_reader("--+++[-]".as_bytes(), 10)
+        .with_cell_overflow(CellOverflow::Saturate);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[0], 0);
+}
+
+#[test]
+fn test_long_add_run_wraps() {
+    let code = "+".repeat(300);
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[0], 44);
+
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape()[0], 44);
+}
+
+#[test]
+fn test_long_move_run_is_not_truncated() {
+    let code = ">".repeat(70_000) + "+";
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 70_001);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    let tape = interpreter.get_tape();
+    assert_eq!(tape[70_000], 1);
+    assert_eq!(tape.iter().filter(|&&cell| cell != 0).count(), 1);
+}
+
+#[test]
+fn test_max_pointer_exceeded() {
+    let code = String::from(">>+[>+]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 100).with_max_pointer(5);
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(
+        error.get_ref().unwrap().downcast_ref::<BrainfuckError>(),
+        Some(&BrainfuckError::PointerLimitExceeded {
+            limit: 5,
+            index: 5,
+            shift: 1,
+            span: Some(Span { start: 4, end: 5 }),
+        })
+    );
+    assert_eq!(
+        error.to_string(),
+        "Error: moving the pointer right by 1 from cell 5 exceeds the pointer limit of 5 \
+         (at byte 4)."
+    );
+    assert_eq!(interpreter.get_tape()[..7], [0, 0, 1, 1, 1, 1, 0]);
+}
+
+#[test]
+fn test_max_pointer_within_limit() {
+    let code = String::from(">>>>>+<<<<");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 100).with_max_pointer(5);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().max_pointer, 5);
+    assert_eq!(interpreter.get_tape()[5], 1);
+}
+
+// Runs the code both as parsed and optimized with a given policy, checking
+// that both runs leave the same tape.
+fn run_with_tape_policy(code: &str, size: usize, policy: TapePolicy) -> Vec<u8> {
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), size).with_tape_policy(policy);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let tape = interpreter.get_tape();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), size).with_tape_policy(policy);
+    assert_eq!(
+        interpreter.run_with_optimization(10).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape(), tape, "{code}");
+    tape
+}
+
+#[test]
+fn test_wrap_tape_policy() {
+    assert_eq!(
+        run_with_tape_policy("<+<++>>>>+++", 4, TapePolicy::Wrap),
+        [0, 0, 5, 1]
+    );
+    // a region crossing the end of the tape
+    assert_eq!(
+        run_with_tape_policy(">>+++[->[-]++>[-]++<<]", 4, TapePolicy::Wrap),
+        [2, 0, 0, 2]
+    );
+}
+
+#[test]
+fn test_saturate_tape_policy() {
+    assert_eq!(
+        run_with_tape_policy("<<+>>>>>>++", 4, TapePolicy::Saturate),
+        [1, 0, 0, 2]
+    );
+    // the clears don't end up where the optimizer would expect them
+    assert_eq!(
+        run_with_tape_policy("+>+<[-]<[-]>+++", 2, TapePolicy::Saturate),
+        [0, 4]
+    );
+}
+
+#[test]
+fn test_grow_tape_policy() {
+    let tape = run_with_tape_policy("+>>>>>>>>>>+", 3, TapePolicy::Grow);
+    assert_eq!(tape, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    let mut interpreter =
+        Interpreter::from_reader("<".as_bytes(), 3).with_tape_policy(TapePolicy::Grow);
+    assert!(interpreter.run().is_err());
+}
+
+#[test]
+fn test_grow_both_tape_policy() {
+    let mut interpreter =
+        Interpreter::from_reader("<+<+".as_bytes(), 10).with_tape_policy(TapePolicy::GrowBoth);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let machine = interpreter.machine_mut();
+    assert_eq!(machine.position(), -2);
+    let (first, _) = machine.tape_range();
+    let tape = machine.get_tape();
+    let cell = |position: isize| tape[(position - first) as usize];
+    assert_eq!((cell(-2), cell(-1), cell(0)), (1, 1, 0));
+    // clears and fills crossing the start of the tape
+    let tape = run_with_tape_policy("++[-<<<[-]++>[-]++>>]<<<<[-]>[-]+", 4, TapePolicy::GrowBoth);
+    assert_eq!(tape, [0, 1, 2, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_grow_tape_policy_respects_max_pointer() {
+    let mut interpreter = Interpreter::from_reader("+[>+]".as_bytes(), 3)
+        .with_tape_policy(TapePolicy::Grow)
+        .with_max_pointer(7);
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(interpreter.get_tape(), [1; 8]);
+    assert_eq!(interpreter.stats().max_pointer, 7);
+    // the limit applies to the positions relative to the starting cell
+    let mut interpreter = Interpreter::from_reader("<<<<<+[>+]".as_bytes(), 3)
+        .with_tape_policy(TapePolicy::GrowBoth)
+        .with_max_pointer(2);
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    assert!(matches!(
+        error.get_ref().unwrap().downcast_ref::<BrainfuckError>(),
+        Some(BrainfuckError::PointerLimitExceeded { limit: 2, .. })
+    ));
+    assert_eq!(interpreter.machine_mut().position(), 2);
+    assert_eq!(interpreter.stats().max_pointer, 2);
+}
+
+#[test]
+fn test_run_twice_reexecutes_code() {
+    let code = String::from("+>++.");
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape()[..3], [1, 3, 2]);
+    assert_eq!(output.contents(), [2, 2]);
+}
+
+#[test]
+fn test_fused_clears_preserve_pointer() {
+    let code = String::from("+++>++>+>+<<<[-]>>>[-]<<[-]<+");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let expected = interpreter.get_tape();
+
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape(), expected);
+    assert_eq!(expected[..4], [1, 0, 1, 0]);
+}
+
+// Runs the code with and without optimization, returning whether each of the
+// runs succeeded along with the output it produced.
+fn run_differential(code: &str, machine_size: usize) -> [(bool, Vec<u8>); 2] {
+    [false, true].map(|optimize| {
+        let output = CapturedOutput::default();
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), machine_size)
+            .with_output(Box::new(output.clone()));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match optimize {
+            false => interpreter.run(),
+            true => interpreter.run_with_optimization(0),
+        }));
+        (
+            matches!(result, Ok(Ok(Termination::Completed))),
+            output.contents(),
+        )
+    })
+}
+
+#[test]
+fn test_optimization_keeps_trap_after_output() {
+    // the pointer leaves the tape on the right before coming back
+    let [unoptimized, optimized] = run_differential("+.>>>>>>>>>>>><<<<<<<<<<<<.", 10);
+    assert_eq!(unoptimized, (false, vec![1]));
+    assert_eq!(optimized, unoptimized);
+}
+
+#[test]
+fn test_optimization_keeps_trap_on_left_excursion() {
+    let [unoptimized, optimized] = run_differential("+>.<<>>>.", 10);
+    assert_eq!(unoptimized, (false, vec![0]));
+    assert_eq!(optimized, unoptimized);
+}
+
+#[test]
+fn test_optimization_keeps_trap_in_loop() {
+    let [unoptimized, optimized] = run_differential("+++[.>>>>>>>>>><<<<<<<<<<-]", 10);
+    assert_eq!(unoptimized, (false, vec![3]));
+    assert_eq!(optimized, unoptimized);
+}
+
+#[test]
+fn test_into_parts() {
+    let code = String::from("++>+++<");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let (mut machine, program) = interpreter.into_parts().unwrap();
+    assert_eq!(program, code.parse::<Program>().unwrap());
+    assert_eq!(machine.peek(0), Some(2));
+    assert_eq!(machine.peek(1), Some(3));
+    assert_eq!(machine.peek(10), None);
+    machine.move_right(1);
+    machine.add(1);
+    assert_eq!(machine.peek(1), Some(4));
+}
+
+#[test]
+fn test_into_parts_parse_error() {
+    let interpreter = Interpreter::from_reader("+[".as_bytes(), 10);
+    let error = interpreter.into_parts().err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_stats_cycle_count() {
+    // 2 adds, 3 guard checks, 2 iterations of 2 statements (one of them IO)
+    let code = String::from("++[.-]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let stats = interpreter.stats();
+    assert_eq!(stats.steps, 2 + 1 + 2 * (1 + 2));
+    assert_eq!(stats.cycles, 2 + 3 * 2 + 2 * (10 + 1));
+}
+
+#[test]
+fn test_stats_custom_cost_table() {
+    let code = String::from("++[.-]");
+    let costs = CostTable {
+        moves: 0,
+        add: 0,
+        loop_branch: 0,
+        io: 1,
+    };
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10).with_cost_table(costs);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().cycles, 2);
+}
+
+#[test]
+fn test_stats_compare_algorithms() {
+    // both programs set the second cell to 64
+    let straight = format!(">{}", "+".repeat(64));
+    let looped = String::from("++++++++[>++++++++<-]");
+
+    let mut interpreter = Interpreter::from_reader(straight.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[1], 64);
+    let straight_cycles = interpreter.stats().cycles;
+
+    let mut interpreter = Interpreter::from_reader(looped.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[1], 64);
+    let looped_cycles = interpreter.stats().cycles;
+
+    let mut interpreter = Interpreter::from_reader(straight.as_bytes(), 10);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    let optimized_cycles = interpreter.stats().cycles;
+
+    assert_eq!(straight_cycles, 65);
+    assert_eq!(looped_cycles, 8 + 9 * 2 + 8 * 11);
+    assert!(optimized_cycles < straight_cycles);
+    assert!(straight_cycles < looped_cycles);
+}
+
+#[test]
+fn test_put_char_writes_raw_bytes() {
+    let code = ".+".repeat(256);
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let expected: Vec<u8> = (0..=255).collect();
+    assert_eq!(output.contents(), expected);
+}
+
+#[test]
+fn test_read_char_consumes_single_bytes() {
+    let code = String::from(",.,.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new("é".as_bytes()))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), "é".as_bytes());
+}
+
+#[test]
+fn test_read_char_end_of_input_leaves_cell_unchanged() {
+    let code = String::from("+,.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(std::io::empty()))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [1]);
+}
+
+#[test]
+fn test_loop_semantics_zero_guard() {
+    let code = String::from("[>+<]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[1], 0);
+
+    for max_iterations in [None, Some(0)] {
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+            .with_loop_semantics(LoopSemantics::DoWhile);
+        let termination = match max_iterations {
+            Some(value) => interpreter.run_with_optimization(value).unwrap(),
+            None => interpreter.run().unwrap(),
+        };
+        assert_eq!(termination, Termination::Completed);
+        assert_eq!(interpreter.get_tape()[1], 1);
+    }
+}
+
+#[test]
+fn test_loop_semantics_do_while_repeats() {
+    let code = String::from("+++[>+<-]");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_loop_semantics(LoopSemantics::DoWhile);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[..2], [0, 3]);
+}
+
+#[test]
+fn test_run_without_terminal() {
+    // under `cargo test` the standard input is not guaranteed to be a TTY
+    let code = String::from("++[>+<-]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert!(interpreter.terminal.is_none());
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert!(interpreter.terminal.is_none());
+    assert_eq!(interpreter.get_tape()[..2], [0, 2]);
+}
+
+#[test]
+fn test_run_with_input_skips_terminal() {
+    let code = String::from(",.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new("a".as_bytes()))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert!(interpreter.terminal.is_none());
+    assert_eq!(output.contents(), b"a");
+}
+
+#[test]
+fn test_raw_mode_toggled_once_per_run() {
+    let code = String::from(",[>+++[>++<-]<-].");
+    let terminal = MockTerminal::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new([100].as_slice()))
+        .with_output(Box::new(CapturedOutput::default()));
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[2], 600u32 as u8);
+    assert_eq!(terminal.calls(), (1, 1));
+}
+
+#[test]
+fn test_input_delivered_byte_by_byte_in_order() {
+    // the input arrives in chunks, each read consumes a single byte of them
+    let code = String::from(",[.,]");
+    let terminal = MockTerminal::default();
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(ChunkedReader::new(b"typed ahead", 3)))
+        .with_eof_policy(EofPolicy::Zero)
+        .with_output(Box::new(output.clone()));
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"typed ahead");
+    assert_eq!(terminal.calls(), (1, 1));
+}
+
+#[test]
+fn test_buffered_input_kept_between_runs() {
+    let code = String::from(",.,.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(ChunkedReader::new(b"abcdef", 4)))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"abcd");
+}
+
+#[test]
+fn test_raw_mode_restored_on_error() {
+    let code = String::from(",[<]");
+    let terminal = MockTerminal::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_input(Box::new([1].as_slice()));
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    interpreter.run().unwrap_err();
+    assert_eq!(terminal.calls(), (1, 1));
+}
+
+#[test]
+fn test_raw_mode_untouched_without_input() {
+    let code = String::from("+++[>+++[>++<-]<-]");
+    let terminal = MockTerminal::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(terminal.calls(), (0, 0));
+}
+
+fn run_with_failing_output(
+    kind: ErrorKind,
+    policy: Option<OnWriteError>,
+) -> (std::io::Result<Termination>, Vec<u8>) {
+    let code = String::from("+.+.+");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(FailingWriter(kind)));
+    if let Some(policy) = policy {
+        interpreter = interpreter.with_on_write_error(policy);
+    }
+    let result = interpreter.run();
+    (result, interpreter.get_tape())
+}
+
+#[test]
+fn test_on_write_error_default() {
+    let (result, tape) = run_with_failing_output(ErrorKind::BrokenPipe, None);
+    assert_eq!(result.unwrap(), Termination::Halted);
+    assert_eq!(tape[0], 1);
+    let (result, tape) = run_with_failing_output(ErrorKind::Other, None);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(tape[0], 1);
+}
+
+#[test]
+fn test_on_write_error_stop() {
+    let (result, tape) = run_with_failing_output(ErrorKind::Other, Some(OnWriteError::Stop));
+    assert_eq!(result.unwrap(), Termination::Halted);
+    assert_eq!(tape[0], 1);
+}
+
+#[test]
+fn test_on_write_error_error() {
+    let (result, tape) = run_with_failing_output(ErrorKind::BrokenPipe, Some(OnWriteError::Error));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::BrokenPipe);
+    assert_eq!(tape[0], 1);
+}
+
+#[test]
+fn test_on_write_error_ignore() {
+    let (result, tape) = run_with_failing_output(ErrorKind::BrokenPipe, Some(OnWriteError::Ignore));
+    assert_eq!(result.unwrap(), Termination::Completed);
+    assert_eq!(tape[0], 3);
+}
+
+#[test]
+fn test_stats_max_depth() {
+    let code = String::from("+[>+[>+[-]<-]<-]");
+    let program: Program = code.parse().unwrap();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(program.max_nesting_depth(), 3);
+    assert_eq!(interpreter.stats().max_depth, program.max_nesting_depth());
+}
+
+#[test]
+fn test_stats_max_depth_not_entered() {
+    // the inner loops are never entered
+    let code = String::from("+[-[[-]]]");
+    let program: Program = code.parse().unwrap();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(program.max_nesting_depth(), 3);
+    assert_eq!(interpreter.stats().max_depth, 1);
+}
+
+#[test]
+fn test_output_flushed_after_run() {
+    let code = String::from("++++++++[>++++++++<-]>+.+.");
+    let output = FlushedBuffer::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"AB");
+}
+
+#[test]
+fn test_output_flushed_after_step_limit() {
+    let code = String::from("++++++++[>++++++++<-]>+.+.[]");
+    let output = FlushedBuffer::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(1000);
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    assert_eq!(output.contents(), b"AB");
+}
+
+#[test]
+fn test_output_flushed_after_bounds_error() {
+    let code = String::from("++++++++[>++++++++<-]>+.+.<<");
+    let output = FlushedBuffer::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    interpreter.run().unwrap_err();
+    assert_eq!(output.contents(), b"AB");
+}
+
+#[test]
+fn test_eof_policy() {
+    for (policy, expected) in [
+        (EofPolicy::Unchanged, 3),
+        (EofPolicy::Zero, 0),
+        (EofPolicy::Max, 255),
+    ] {
+        let code = String::from("+++,");
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+            .with_input(Box::new(std::io::empty()))
+            .with_eof_policy(policy);
+        assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+        assert_eq!(interpreter.get_tape()[0], expected);
+    }
+}
+
+#[test]
+fn test_input_source_tape_region() {
+    // copies the region at cells 5..8 to cells 0..3, the fourth read is EOF
+    let code = String::from(",>,>,>,");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input_source(InputSource::TapeRegion { start: 5, len: 3 })
+        .with_eof_policy(EofPolicy::Max);
+    interpreter.machine_mut().load(5, b"abc").unwrap();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape(), b"abc\xff\0abc\0\0");
+}
+
+#[test]
+fn test_input_source_tape_region_optimized() {
+    // the seeded data must not be treated as a zeroed tape
+    let code = String::from(">>>>>[<<<<<,.>>>>>-]");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input_source(InputSource::TapeRegion { start: 6, len: 2 })
+        .with_output(Box::new(output.clone()));
+    interpreter.machine_mut().load(5, &[2, b'x', b'y']).unwrap();
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(output.contents(), b"xy");
+}
+
+#[test]
+fn test_input_source_tape_region_grown_tape() {
+    // the tape grows to the left, shifting the indices of the seeded cells
+    let code = String::from("<+>>,");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_tape_policy(TapePolicy::GrowBoth)
+        .with_input_source(InputSource::TapeRegion { start: 5, len: 1 });
+    interpreter.machine_mut().load(5, b"a").unwrap();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let machine = interpreter.machine_mut();
+    assert_eq!(machine.position(), 1);
+    assert_eq!(machine.current_cell(), b'a');
+    let origin = -machine.tape_range().0 as usize;
+    assert!(origin > 0);
+    assert_eq!(
+        machine.get_tape()[origin - 1..origin + 6],
+        *b"\x01\0a\0\0\0a"
+    );
+}
+
+#[test]
+fn test_input_source_tape_region_out_of_tape() {
+    let code = String::from(",");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input_source(InputSource::TapeRegion { start: 8, len: 3 });
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_code_terminator_inline_input() {
+    let code = String::from(",.,.!ab");
+    let mut reader = code.as_bytes();
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(&mut reader, 10)
+        .with_code_terminator(b'!')
+        .with_input(Box::new(std::io::empty()))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    drop(interpreter);
+    assert_eq!(output.contents(), [0, 0]);
+    assert_eq!(reader, b"ab");
+}
+
+#[test]
+fn test_side_effect_callback() {
+    let code = String::from("+++[*-]**");
+    let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = calls.clone();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_side_effect(b'*', SideEffectKind::Custom(b'*'))
+        .with_side_effect_handler(Box::new(move |kind| {
+            assert_eq!(kind, SideEffectKind::Custom(b'*'));
+            counter.set(counter.get() + 1);
+        }));
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(calls.get(), 5);
+}
+
+#[test]
+fn test_side_effect_bell() {
+    let code = String::from("+.#.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_side_effect(b'#', SideEffectKind::Bell);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [1, 0x07, 1]);
+}
+
+#[test]
+fn test_side_effect_keeps_standard_commands() {
+    let code = String::from("++.#");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_side_effect(b'+', SideEffectKind::Bell);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [2]);
+}
+
+fn random_output(code: &str, seed: u64) -> Vec<u8> {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_random_command(b'?', seed);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    output.contents()
+}
+
+#[test]
+fn test_random_same_seed_same_output() {
+    let code = "?.?.?.?.?.?.?.?.";
+    let output = random_output(code, 42);
+    assert_eq!(output.len(), 8);
+    assert_eq!(random_output(code, 42), output);
+    // every run starts over from the seed
+    let captured = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(captured.clone()))
+        .with_random_command(b'?', 42);
+    for _ in 0..2 {
+        assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    }
+    assert_eq!(captured.contents(), [output.clone(), output].concat());
+}
+
+#[test]
+fn test_random_different_seeds_differ() {
+    let code = "?.?.?.?.?.?.?.?.";
+    assert_ne!(random_output(code, 1), random_output(code, 2));
+}
+
+#[test]
+fn test_random_optimized_same_output() {
+    let code = "?.?+.>?.";
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_random_command(b'?', 7);
+    assert_eq!(
+        interpreter.run_with_optimization(10).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(output.contents(), random_output(code, 7));
+}
+
+#[test]
+fn test_random_disabled_is_comment() {
+    let code = String::from("?+?.");
+    let statements = Parser::from_reader(code.as_bytes()).parse().unwrap();
+    assert_eq!(statements, [Statement::Add(1), Statement::PutChar]);
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [1]);
+}
+
+#[test]
+fn test_long_move_past_end_error() {
+    let code = ">".repeat(25);
+    for optimize in [None, Some(0)] {
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+        let error = match optimize {
+            Some(max_iterations) => interpreter.run_with_optimization(max_iterations),
+            None => interpreter.run(),
+        }
+        .unwrap_err();
+        let expected_index = match optimize {
+            Some(_) => 0,
+            None => 9,
+        };
+        assert!(matches!(
+            bounds_error(&error),
+            BrainfuckError::TapeOverflow { index, .. } if index == expected_index
+        ));
+    }
+}
+
+#[test]
+fn test_tape_underflow_error() {
+    let code = String::from("+ <");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    let error = interpreter.run().unwrap_err();
+    let span = Some(Span { start: 2, end: 3 });
+    assert_eq!(
+        bounds_error(&error),
+        BrainfuckError::TapeUnderflow {
+            index: 0,
+            shift: 1,
+            span
+        }
+    );
+}
+
+#[test]
+fn test_tape_overflow_error_in_loop() {
+    let code = String::from("+[>+]\n");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 5);
+    let error = interpreter.run().unwrap_err();
+    let span = Some(Span { start: 2, end: 3 });
+    assert_eq!(
+        bounds_error(&error),
+        BrainfuckError::TapeOverflow {
+            index: 4,
+            shift: 1,
+            span
+        }
+    );
+    assert_eq!(interpreter.get_tape(), [1; 5]);
+}
+
+#[test]
+fn test_tape_error_without_span_when_optimized() {
+    let code = String::from(">>>[-]<<<<");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    let error = interpreter.run_with_optimization(0).unwrap_err();
+    assert_eq!(
+        bounds_error(&error),
+        BrainfuckError::TapeUnderflow {
+            index: 3,
+            shift: 4,
+            span: None
+        }
+    );
+}
+
+#[test]
+fn test_raw_mode_restored_on_bounds_error() {
+    let code = String::from(",<");
+    let terminal = MockTerminal::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_input(Box::new([1].as_slice()));
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    let error = interpreter.run().unwrap_err();
+    assert!(matches!(
+        bounds_error(&error),
+        BrainfuckError::TapeUnderflow { .. }
+    ));
+    assert_eq!(terminal.calls(), (1, 1));
+}
+
+#[test]
+fn test_comments_are_not_steps() {
+    let code = String::from("+ one [ - two ]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    interpreter.parser.lexer.keep_comments = true;
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().steps, 4);
+}
+
+#[test]
+fn test_tape_size_command() {
+    let code = String::from("+++#>#-");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 300).with_tape_size_command(b'#');
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape()[..3], [44, 43, 0]);
+}
+
+#[test]
+fn test_tape_size_command_keeps_standard_commands() {
+    let code = String::from("+++");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 300).with_tape_size_command(b'+');
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[0], 3);
+}
+
+// Generates code that never leaves the tape (given enough cells on the
+// right) and always terminates: a loop only decrements its guard cell, while
+// its body works on the cells to the right of it.
+fn random_code(rng: &mut Lcg, depth: u32) -> String {
+    let mut code = String::new();
+    let mut position = 0;
+    for _ in 0..rng.next(12) {
+        match rng.next(6) {
+            0 => code.push_str(&"+".repeat(rng.next(5) as usize + 1)),
+            1 => code.push_str(&"-".repeat(rng.next(5) as usize + 1)),
+            2 => {
+                let shift = rng.next(3) as usize + 1;
+                code.push_str(&">".repeat(shift));
+                position += shift;
+            }
+            3 => {
+                let shift = (rng.next(3) as usize + 1).min(position);
+                code.push_str(&"<".repeat(shift));
+                position -= shift;
+            }
+            4 => code.push('.'),
+            _ if depth < 2 => {
+                let offset = rng.next(2) as usize + 1;
+                code.push('[');
+                code.push_str(&">".repeat(offset));
+                code.push_str(&random_code(rng, depth + 1));
+                code.push_str(&"<".repeat(offset));
+                code.push_str("-]");
+            }
+            _ => {}
+        }
+    }
+    // the pointer is brought back, so that loops stay balanced
+    code.push_str(&"<".repeat(position));
+    code
+}
+
+#[test]
+fn test_optimization_differential_random_programs() {
+    let mut rng = Lcg(42);
+    for _ in 0..200 {
+        let code = random_code(&mut rng, 0);
+        let results = [false, true].map(|optimize| {
+            let output = CapturedOutput::default();
+            let mut interpreter = Interpreter::from_reader(code.as_bytes(), 1000)
+                .with_output(Box::new(output.clone()))
+                .with_step_limit(10_000_000);
+            let termination = match optimize {
+                false => interpreter.run().unwrap(),
+                true => interpreter.run_with_optimization(0).unwrap(),
+            };
+            assert_eq!(termination, Termination::Completed);
+            (interpreter.get_tape(), output.contents())
+        });
+        assert_eq!(results[0], results[1], "code: {}", code);
+    }
+}
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/programs/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn test_run_with_input_file() {
+    let output =
+        Interpreter::run_with_input_file(&fixture("rot13.b"), &fixture("rot13.in"), 30000).unwrap();
+    assert_eq!(output, std::fs::read(fixture("rot13.out")).unwrap());
+}
+
+#[test]
+fn test_run_with_missing_input_file() {
+    let error = Interpreter::run_with_input_file(&fixture("rot13.b"), &fixture("missing.in"), 10)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn test_run_captured_clears_tape() {
+    let mut interpreter = Interpreter::from_reader(b"".as_slice(), 10);
+    let program: Program = ",>+.".parse().unwrap();
+    for _ in 0..2 {
+        let (termination, output) = interpreter.run_captured(&program, b"a").unwrap();
+        assert_eq!(termination, Termination::Completed);
+        assert_eq!(output, [1]);
+        assert_eq!(interpreter.get_tape()[..2], [b'a', 1]);
+    }
+}
+
+#[test]
+fn test_run_captured_persistent_tape() {
+    let mut interpreter = Interpreter::from_reader(b"".as_slice(), 10).with_persistent_tape(true);
+    // the first program stores two values, the second one prints their sum
+    let store: Program = ",>,".parse().unwrap();
+    let sum: Program = "[<+>-]<.".parse().unwrap();
+    let (_, output) = interpreter.run_captured(&store, &[2, 3]).unwrap();
+    assert!(output.is_empty());
+    let (termination, output) = interpreter.run_captured(&sum, b"").unwrap();
+    assert_eq!(termination, Termination::Completed);
+    assert_eq!(output, [5]);
+    interpreter.reset();
+    assert_eq!(interpreter.get_tape(), [0; 10]);
+    assert_eq!(interpreter.machine_mut().index(), 0);
+}
+
+#[test]
+fn test_seed_and_inspect_cells() {
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(">>>.+".as_bytes(), 5).with_output(Box::new(output.clone()));
+    interpreter.machine_mut().set_cell(3, 65).unwrap();
+    assert!(interpreter.machine_mut().set_cell(5, 65).is_err());
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"A");
+    assert_eq!(interpreter.machine().get_cell(3), Some(66));
+    assert_eq!(interpreter.machine().current_index(), 3);
+}
+
+// Two loops, nested: `++[>++[>+<-]<-]>>.`, the inner one is at [2, 3].
+fn stepped_interpreter() -> (Interpreter<&'static [u8]>, CapturedOutput) {
+    let output = CapturedOutput::default();
+    let interpreter = Interpreter::from_reader("++[>++[>+<-]<-]>>.".as_bytes(), 4)
+        .with_output(Box::new(output.clone()));
+    (interpreter, output)
+}
+
+#[test]
+fn test_step_into_and_over() {
+    let (mut interpreter, output) = stepped_interpreter();
+    assert_eq!(interpreter.step_into().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[1][..]));
+    assert_eq!(interpreter.step_into().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2][..]));
+    // into the outer loop
+    assert_eq!(interpreter.step_into().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 0][..]));
+    for position in 1..=3 {
+        assert_eq!(interpreter.step_over().unwrap(), Termination::Paused);
+        assert_eq!(interpreter.stopped_at(), Some(&[2, position][..]));
+    }
+    // the whole inner loop is run at once
+    assert_eq!(interpreter.step_over().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 4][..]));
+    assert_eq!(interpreter.get_tape(), vec![2, 0, 2, 0]);
+    // from the last statement of the body to the first one, as the guard
+    // isn't zero
+    assert_eq!(interpreter.step_over().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 5][..]));
+    assert_eq!(interpreter.step_over().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 0][..]));
+    assert_eq!(interpreter.get_tape(), vec![1, 0, 2, 0]);
+    // and into the inner loop
+    for _ in 0..4 {
+        assert_eq!(interpreter.step_into().unwrap(), Termination::Paused);
+    }
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 3, 0][..]));
+    assert!(output.contents().is_empty());
+}
+
+#[test]
+fn test_step_out() {
+    let (mut interpreter, output) = stepped_interpreter();
+    for _ in 0..7 {
+        assert_eq!(interpreter.step_into().unwrap(), Termination::Paused);
+    }
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 3, 0][..]));
+    // out of the inner loop, past it
+    assert_eq!(interpreter.step_out().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[2, 4][..]));
+    assert_eq!(interpreter.get_tape(), vec![2, 0, 2, 0]);
+    // out of the outer loop, after all of its iterations
+    assert_eq!(interpreter.step_out().unwrap(), Termination::Paused);
+    assert_eq!(interpreter.stopped_at(), Some(&[3][..]));
+    assert_eq!(interpreter.get_tape(), vec![0, 0, 4, 0]);
+    assert!(output.contents().is_empty());
+    // outside of any loop, to the end
+    assert_eq!(interpreter.step_out().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stopped_at(), Some(&[6][..]));
+    assert_eq!(output.contents(), [4]);
+    // the steps add up to the ones of a whole run
+    let steps = interpreter.stats().steps;
+    let (mut interpreter, _) = stepped_interpreter();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().steps, steps);
+}
+
+#[test]
+fn test_run_with_limit() {
+    // three additions and a move
+    let mut interpreter = Interpreter::from_reader("+++>".as_bytes(), 10);
+    interpreter.run_with_limit(4).unwrap();
+    assert_eq!(interpreter.stats().steps, 4);
+    assert_eq!(interpreter.get_tape()[0], 3);
+    let mut interpreter = Interpreter::from_reader("+++>".as_bytes(), 10);
+    let error = interpreter.run_with_limit(3).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn test_run_with_limit_stops_infinite_loop() {
+    let mut interpreter = Interpreter::from_reader("+[]".as_bytes(), 10).with_step_limit(1_000_000);
+    let error = interpreter.run_with_limit(1000).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    assert!(error
+        .to_string()
+        .starts_with("Error: the program has taken more than 1000 steps."));
+    assert!(interpreter.stats().steps <= 1001);
+    // the limit set on the interpreter applies again to the next runs
+    assert_eq!(interpreter.run().unwrap(), Termination::StepLimit);
+    assert!(interpreter.stats().steps > 1001);
+}
+
+#[test]
+fn test_run_captured_memoization() {
+    let mut interpreter =
+        Interpreter::from_reader(b"".as_slice(), 10).with_memoization(MemoCache::new());
+    let program: Program = ",[>+<-]>.".parse().unwrap();
+    let (termination, output) = interpreter.run_captured(&program, &[3]).unwrap();
+    assert_eq!((termination, output), (Termination::Completed, vec![3]));
+    assert!(interpreter.stats().steps > 0);
+    // the second run with the same input only hits the cache
+    let (termination, output) = interpreter.run_captured(&program, &[3]).unwrap();
+    assert_eq!((termination, output), (Termination::Completed, vec![3]));
+    assert_eq!(interpreter.stats().steps, 0);
+    // a different input, or an equal program given anew, is keyed apart
+    let (_, output) = interpreter.run_captured(&program, &[4]).unwrap();
+    assert_eq!(output, [4]);
+    assert!(interpreter.stats().steps > 0);
+    let same: Program = ",[>+<-]>.".parse().unwrap();
+    let (_, output) = interpreter.run_captured(&same, &[4]).unwrap();
+    assert_eq!(output, [4]);
+    assert_eq!(interpreter.stats().steps, 0);
+    let cache = interpreter.memoization_cache().unwrap();
+    assert_eq!(cache.len(), 2);
+    let ((fingerprint, _, _), output) = cache.iter().find(|(key, _)| key.2 == [3]).unwrap();
+    assert_eq!(
+        (*fingerprint, output.as_slice()),
+        (program.fingerprint(), [3].as_slice())
+    );
+}
+
+#[test]
+fn test_run_captured_memoization_keyed_by_settings() {
+    let program: Program = ",[>+<-]>.".parse().unwrap();
+    let mut interpreter =
+        Interpreter::from_reader(b"".as_slice(), 10).with_memoization(MemoCache::new());
+    assert_eq!(interpreter.run_captured(&program, &[3]).unwrap().1, [3]);
+    let cache = interpreter.memoization_cache().unwrap().clone();
+    // an interpreter with the same settings shares the outputs
+    let mut interpreter =
+        Interpreter::from_reader(b"".as_slice(), 10).with_memoization(cache.clone());
+    assert_eq!(interpreter.run_captured(&program, &[3]).unwrap().1, [3]);
+    assert_eq!(interpreter.stats().steps, 0);
+    // but not one with a tape of another size, or other limits
+    let mut interpreter =
+        Interpreter::from_reader(b"".as_slice(), 20).with_memoization(cache.clone());
+    assert_eq!(interpreter.run_captured(&program, &[3]).unwrap().1, [3]);
+    assert!(interpreter.stats().steps > 0);
+    assert_eq!(interpreter.memoization_cache().unwrap().len(), 2);
+    let mut interpreter = Interpreter::from_reader(b"".as_slice(), 10)
+        .with_memoization(cache)
+        .with_step_limit(5);
+    let (termination, _) = interpreter.run_captured(&program, &[3]).unwrap();
+    assert_eq!(termination, Termination::StepLimit);
+}
+
+#[test]
+fn test_run_captured_memoization_skips_impure_and_stopped_runs() {
+    let mut interpreter = Interpreter::from_reader(b"".as_slice(), 10)
+        .with_memoization(MemoCache::new())
+        .with_step_limit(100);
+    let random = Program {
+        statements: vec![Statement::Random, Statement::PutChar],
+    };
+    let size = Program {
+        statements: vec![Statement::LoadTapeSize, Statement::PutChar],
+    };
+    let looping: Program = "+[]".parse().unwrap();
+    for _ in 0..2 {
+        let (termination, output) = interpreter.run_captured(&random, b"").unwrap();
+        assert_eq!((termination, output.len()), (Termination::Completed, 1));
+        assert!(interpreter.stats().steps > 0);
+        let (termination, output) = interpreter.run_captured(&size, b"").unwrap();
+        assert_eq!((termination, output), (Termination::Completed, vec![10]));
+        assert!(interpreter.stats().steps > 0);
+        let (termination, _) = interpreter.run_captured(&looping, b"").unwrap();
+        assert_eq!(termination, Termination::StepLimit);
+    }
+    assert!(interpreter.memoization_cache().unwrap().is_empty());
+}
+
+// The tests below bound the allocations and the IO calls made by a run, so
+// that the hot path can't silently regress to e.g. a write per byte.
+
+#[test]
+fn test_loop_steady_state_allocates_nothing() {
+    // the same code, with 16 and about a million iterations of the
+    // innermost loop
+    let count = |code: &str| {
+        let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+        let (result, allocations) = count_allocations(|| interpreter.run());
+        assert_eq!(result.unwrap(), Termination::Completed);
+        allocations
+    };
+    let short = count("+[>+[>++++++++++++++++[-]<-]<-]");
+    let long = count("-[>-[>++++++++++++++++[-]<-]<-]");
+    assert_eq!(long, short);
+}
+
+#[test]
+fn test_buffered_output_written_at_once() {
+    // prints "Hello World!" followed by a newline
+    let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.\
+                <-.<.+++.------.--------.>>+.>++.";
+    let writer = CountingWriter::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(BufWriter::new(writer.clone())));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let (writes, flushes) = writer.calls();
+    assert_eq!(writes, 1);
+    assert_eq!(flushes, 1);
+}
+
+#[test]
+fn test_output_to_given_writer() {
+    // prints "Hello World!" followed by a newline
+    let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.\
+                <-.<.+++.------.--------.>>+.>++.";
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader_with_output(code.as_bytes(), 10, Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"Hello World!\n");
+    // a buffered writer is flushed at the end of the run
+    let writer = FlushedBuffer::default();
+    let mut interpreter = Interpreter::from_reader_with_output(
+        code.as_bytes(),
+        10,
+        Box::new(BufWriter::new(writer.clone())),
+    );
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(writer.contents(), b"Hello World!\n");
+}
+
+#[test]
+fn test_input_read_in_blocks() {
+    let (reader, reads) = CountingReader::new(Cursor::new(vec![b'a'; 1000]));
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(",[.,]".as_bytes(), 10)
+        .with_input(Box::new(reader))
+        .with_eof_policy(EofPolicy::Zero)
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents().len(), 1000);
+    // one read for the data, one more to find the end of the input
+    assert_eq!(reads.get(), 2);
+}
+
+#[test]
+fn test_raw_mode_untouched_by_output() {
+    let code = String::from("+[.+]");
+    let terminal = MockTerminal::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(CountingWriter::default()));
+    interpreter.terminal = Some(Box::new(terminal.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(terminal.calls(), (0, 0));
+}
+
+#[test]
+fn test_peek_program_source() {
+    let code = String::from(",[>++<-]>.");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new([3].as_slice()))
+        .with_output(Box::new(CapturedOutput::default()));
+    assert_eq!(
+        interpreter.peek_program_source().unwrap(),
+        ", [ 1> 1+ 1+ 1< 255+] 1> ."
+    );
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(
+        interpreter.peek_program_source().unwrap(),
+        ", [ 1> 2+ 1< 255+] 1> ."
+    );
+    // the parsed code is listed again once it's run
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(
+        interpreter.peek_program_source().unwrap(),
+        ", [ 1> 1+ 1+ 1< 255+] 1> ."
+    );
+}
+
+#[test]
+fn test_peek_program_source_of_invalid_code() {
+    let code = String::from("+]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    let error = interpreter.peek_program_source().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_statement_timing_off_by_default() {
+    let code = String::from("++[-]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().timings, None);
+}
+
+#[test]
+fn test_statement_timing_of_io() {
+    // every read takes 3ms and every write 5ms, while the rest takes no time
+    let clock = MockClock::default();
+    let code = String::from(",[.>,]");
+    let time = clock.clone();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(SlowReader(
+            b"abc".as_slice(),
+            clock.clone(),
+            Duration::from_millis(3),
+        )))
+        .with_output(Box::new(SlowWriter(
+            clock.clone(),
+            Duration::from_millis(5),
+        )))
+        .with_statement_clock(Box::new(move || time.now()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    // the input is buffered, so it's read once, and once more to find its end
+    let expected = StatementTimings {
+        input: Duration::from_millis(3 * 2),
+        output: Duration::from_millis(5 * 3),
+        ..Default::default()
+    };
+    assert_eq!(interpreter.stats().timings, Some(expected));
+    assert_eq!(expected.total(), clock.now());
+}
+
+#[test]
+fn test_statement_timing_of_computation() {
+    // the clock ticks on every reading, so each timed part takes a tick
+    let clock = MockClock::default();
+    let time = clock.clone();
+    let code = String::from("++[->+<]");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_statement_clock(Box::new(move || {
+            time.advance(Duration::from_micros(1));
+            time.now()
+        }));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let timings = interpreter.stats().timings.unwrap();
+    // 2 adds before the loop and 2 in each of the 2 iterations
+    assert_eq!(timings.arithmetic, Duration::from_micros(2 + 2 * 2));
+    assert_eq!(timings.movement, Duration::from_micros(2 * 2));
+    assert_eq!(timings.input, Duration::ZERO);
+    assert!(timings.loops > Duration::ZERO);
+    // the first reading of the clock starts the run
+    assert_eq!(timings.total() + Duration::from_micros(1), clock.now());
+}
+
+#[test]
+fn test_statement_timing_resets_between_runs() {
+    let clock = MockClock::default();
+    let time = clock.clone();
+    let code = String::from(".");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(SlowWriter(
+            clock.clone(),
+            Duration::from_millis(5),
+        )))
+        .with_statement_clock(Box::new(move || time.now()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let timings = interpreter.stats().timings.unwrap();
+    assert_eq!(timings.output, Duration::from_millis(5));
+}
+
+#[test]
+fn test_input_provider_supplies_batches() {
+    let calls = Rc::new(Cell::new(0));
+    let mut batches = vec![b"cd".to_vec(), b"e".to_vec()].into_iter();
+    let counter = calls.clone();
+    let provider = Box::new(move || {
+        counter.set(counter.get() + 1);
+        batches.next()
+    });
+    let code = String::from(",.,.,.,.,.,.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(b"ab".as_slice()))
+        .with_output(Box::new(output.clone()))
+        .with_input_provider(provider)
+        .with_eof_policy(EofPolicy::Zero);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"abcde\0");
+    // once for each batch, then for the read that hits the end
+    assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn test_input_provider_called_on_demand() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    let provider = Box::new(move || {
+        counter.set(counter.get() + 1);
+        Some(vec![b'x'])
+    });
+    let code = String::from(",.,.");
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(b"ab".as_slice()))
+        .with_output(Box::new(output.clone()))
+        .with_input_provider(provider);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"ab");
+    assert_eq!(calls.get(), 0);
+}
+
+#[test]
+fn test_input_provider_after_tape_region() {
+    let code = String::from(",>,>,");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_input(Box::new(io::empty()))
+        .with_input_source(InputSource::TapeRegion { start: 5, len: 1 })
+        .with_input_provider(Box::new(|| Some(b"yz".to_vec())));
+    interpreter.machine_mut().tape[5] = b'x';
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(&interpreter.get_tape()[..3], b"xyz");
+}
+
+#[test]
+fn test_phase_timings_without_optimization() {
+    let code = "++++++++[>++++++++<-]>+.<".repeat(100);
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(CapturedOutput::default()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let phases = interpreter.stats().phases;
+    assert!(phases.parse > Duration::ZERO);
+    assert_eq!(phases.optimize, Duration::ZERO);
+    assert!(phases.execute > Duration::ZERO);
+    // the code is parsed only once
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.stats().phases.parse, Duration::ZERO);
+}
+
+#[test]
+fn test_phase_timings_with_optimization() {
+    let code = "++++++++[>++++++++<-]>+.<".repeat(100);
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(CapturedOutput::default()));
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    let phases = interpreter.stats().phases;
+    assert!(phases.parse > Duration::ZERO);
+    assert!(phases.optimize > Duration::ZERO);
+    assert!(phases.execute > Duration::ZERO);
+}
+
+#[test]
+fn test_phase_timings_of_captured_run() {
+    let program = Program::from_reader("+.".as_bytes()).unwrap();
+    let mut interpreter = Interpreter::from_reader("".as_bytes(), 10);
+    let (termination, _) = interpreter.run_captured(&program, b"").unwrap();
+    assert_eq!(termination, Termination::Completed);
+    let phases = interpreter.stats().phases;
+    assert!(phases.execute > Duration::ZERO);
+    assert_eq!(
+        phases,
+        PhaseTimings {
+            execute: phases.execute,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_messages_kept_apart_from_output() {
+    // the loop never terminates, as the cell is odd and goes up by 2
+    let code = String::from("+.[++]");
+    let output = CapturedOutput::default();
+    let messages = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_message_output(Box::new(messages.clone()))
+        .with_step_limit(100);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::StepLimit
+    );
+    assert_eq!(output.contents(), [1]);
+    assert_eq!(
+        messages.contents(),
+        b"Warning: The code contains a loop that never terminates.\n"
+    );
+}
+
+fn translated_output(code: &str, translation: NewlineTranslation) -> Vec<u8> {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(output.clone()))
+        .with_newline_translation(translation);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    output.contents()
+}
+
+#[test]
+fn test_newline_translation_lf_to_crlf() {
+    // outputs "a\n\n"
+    let code = "++++++++++[>++++++++++<-]>---.<++++++++++..";
+    assert_eq!(
+        translated_output(code, NewlineTranslation::LfToCrlf),
+        b"a\r\n\r\n"
+    );
+    assert_eq!(
+        translated_output(code, NewlineTranslation::default()),
+        b"a\n\n"
+    );
+}
+
+#[test]
+fn test_newline_translation_crlf_to_lf() {
+    // outputs "\r\n\r\r\n\r", the last `\r` is written at the end of the run
+    let code = "+++++++++++++.---.+++..---.+++.";
+    assert_eq!(
+        translated_output(code, NewlineTranslation::CrlfToLf),
+        b"\n\r\n\r"
+    );
+    assert_eq!(
+        translated_output(code, NewlineTranslation::None),
+        b"\r\n\r\r\n\r"
+    );
+}
+
+#[test]
+fn test_output_callback_receives_every_byte() {
+    let code = fs::read_to_string("tests/programs/hello.b").unwrap();
+    let expected = fs::read("tests/programs/hello.out").unwrap();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let collected = received.clone();
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 100)
+        .with_output(Box::new(output.clone()))
+        .with_output_callback(Box::new(move |byte| collected.borrow_mut().push(byte)));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(*received.borrow(), expected);
+    assert_eq!(output.contents(), expected);
+}
+
+#[test]
+fn test_run_over_stack_buffer() {
+    let mut buffer = [0xaa; 12];
+    buffer[2..10].fill(0);
+    let code = String::from("++>+++[->++<]>.");
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::over(&mut buffer[2..10]))
+            .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.machine_mut().index(), 2);
+    drop(interpreter);
+    assert_eq!(output.contents(), [6]);
+    assert_eq!(buffer, [0xaa, 0xaa, 2, 0, 6, 0, 0, 0, 0, 0, 0xaa, 0xaa]);
+}
+
+#[test]
+fn test_run_over_vec_slice_stops_at_its_ends() {
+    let mut arena = [0xaa; 64].to_vec();
+    arena[16..24].fill(0);
+    let code = String::from("+[>+]");
+    let mut interpreter =
+        Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::over(&mut arena[16..24]));
+    let error = interpreter.run().unwrap_err();
+    assert!(matches!(
+        error.get_ref().unwrap().downcast_ref::<BrainfuckError>(),
+        Some(BrainfuckError::TapeOverflow { index: 7, .. })
+    ));
+    // the optimized run and a reset work on the buffer as well
+    interpreter.reset();
+    interpreter.machine_mut().read_byte(5);
+    let error = interpreter.run_with_optimization(0).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Other);
+    drop(interpreter);
+    assert!(arena[16..24].iter().all(|&cell| cell != 0));
+    assert!(arena[..16]
+        .iter()
+        .chain(&arena[24..])
+        .all(|&cell| cell == 0xaa));
+}
+
+// A tape keeping only the non-zero cells, counting the writes.
+struct SparseTape {
+    cells: std::collections::BTreeMap<usize, u8>,
+    len: usize,
+    writes: Rc<Cell<usize>>,
+}
+
+impl Tape for SparseTape {
+    type Cell = u8;
+    fn get(&self, index: usize) -> u8 {
+        assert!(index < self.len);
+        self.cells.get(&index).copied().unwrap_or(0)
+    }
+    fn set(&mut self, index: usize, value: u8) {
+        assert!(index < self.len);
+        self.writes.set(self.writes.get() + 1);
+        match value {
+            0 => self.cells.remove(&index),
+            value => self.cells.insert(index, value),
+        };
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[test]
+fn test_lazy_tape_allocates_visited_cells_only() {
+    let code = ">".repeat(100_000);
+    let mut interpreter =
+        Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::lazy(1 << 20));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.machine_mut().index(), 100_000);
+    assert_eq!(interpreter.machine_mut().tape.allocated(), 0);
+    let code = format!("{code}+");
+    let mut interpreter =
+        Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::lazy(1 << 20));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.machine_mut().tape.allocated(), 100_001);
+    let tape = interpreter.get_tape();
+    assert_eq!(tape.len(), 1 << 20);
+    assert_eq!(tape[100_000], 1);
+    assert_eq!(tape.iter().filter(|&&cell| cell != 0).count(), 1);
+}
+
+#[test]
+fn test_lazy_tape_cap_exceeded() {
+    let code = ">".repeat(100);
+    let mut interpreter = Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::lazy(100));
+    let error = interpreter.run().unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Error: moving the pointer right by 1 from cell 99 leaves the tape"));
+}
+
+#[test]
+fn test_program_parsed_once_runs_on_many_inputs() {
+    let mut interpreter =
+        Interpreter::from_reader("?>,+.".as_bytes(), 10).with_random_command(b'?', 3);
+    let program = interpreter.program().unwrap();
+    assert_eq!(
+        program,
+        Program {
+            statements: vec![
+                Statement::Random,
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::Add(1),
+                Statement::PutChar,
+            ]
+        }
+    );
+    for input in [b"a", b"b"] {
+        let (termination, output) = interpreter.run_captured(&program, input).unwrap();
+        assert_eq!(termination, Termination::Completed);
+        assert_eq!(output, [input[0] + 1]);
+    }
+}
+
+#[test]
+fn test_optimized_program() {
+    let messages = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader("+-+[++]".as_bytes(), 10)
+        .with_message_output(Box::new(messages.clone()));
+    let program = interpreter.optimized_program(1).unwrap();
+    assert_eq!(
+        program.statements,
+        [
+            Statement::Add(1),
+            Statement::new_loop(vec![Statement::Add(2)])
+        ]
+    );
+    let report = interpreter.optimization_report();
+    assert_eq!(report.iterations, 1);
+    assert!(!report.converged);
+    assert_eq!(report.termination, TerminationVerdict::GuaranteedInfinite);
+    assert_eq!(
+        messages.contents(),
+        b"Warning: The code contains a loop that never terminates.\n"
+    );
+    assert_eq!(interpreter.optimized_program(0).unwrap(), program);
+    assert!(interpreter.optimization_report().converged);
+}
+
+#[test]
+fn test_access_counts() {
+    let mut interpreter =
+        Interpreter::from_reader("+++++[>+<-]>.".as_bytes(), 6).with_access_counts();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    // the additions and the six checks of the guard
+    assert_eq!(interpreter.access_counts(), [26, 11, 0, 0, 0, 0]);
+    assert!(interpreter.access_counts()[0] > interpreter.access_counts()[5]);
+    // the counts start over with every run
+    interpreter.reset();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.access_counts(), [26, 11, 0, 0, 0, 0]);
+    let mut interpreter = Interpreter::from_reader("+++++[>+<-]>.".as_bytes(), 6);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert!(interpreter.access_counts().is_empty());
+}
+
+#[test]
+fn test_access_counts_of_optimized_code() {
+    let mut interpreter =
+        Interpreter::from_reader("+>[-]+>[-]+<<.".as_bytes(), 4).with_access_counts();
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    // the fill writes each of its cells once
+    assert_eq!(interpreter.access_counts(), [3, 1, 1, 0]);
+}
+
+#[test]
+fn test_access_counts_follow_growing_tape() {
+    let mut interpreter = Interpreter::from_reader("+<<+>>>>>".as_bytes(), 2)
+        .with_tape_policy(TapePolicy::GrowBoth)
+        .with_access_counts();
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    let tape = interpreter.get_tape();
+    let counts = interpreter.access_counts();
+    assert_eq!(counts.len(), tape.len());
+    assert_eq!(counts.iter().sum::<u64>(), 4);
+    for (cell, count) in zip(tape, counts) {
+        assert_eq!(cell != 0, *count != 0);
+    }
+}
+
+#[test]
+fn test_run_over_custom_tape() {
+    let code = fs::read_to_string("tests/programs/hello.b").unwrap();
+    let expected = fs::read("tests/programs/hello.out").unwrap();
+    let writes = Rc::new(Cell::new(0));
+    let tape = SparseTape {
+        cells: Default::default(),
+        len: 30000,
+        writes: writes.clone(),
+    };
+    let output = CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_machine(code.as_bytes(), BrainfuckMachine::with_tape(tape))
+            .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), expected);
+    assert!(writes.get() > 0);
+    let tape = interpreter.get_tape();
+    assert_eq!(tape.len(), 30000);
+    interpreter.reset();
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(output.contents(), expected.repeat(2));
+    assert_eq!(interpreter.get_tape(), tape);
+}
+
+// Runs the code on a tape of 16-bit cells, optimized if a level is given,
+// returns the tape and the output.
+fn run_wide(code: &str, size: usize, optimization: Option<u32>) -> (Vec<u16>, Vec<u8>) {
+    let output = CapturedOutput::default();
+    let machine = BrainfuckMachine::with_tape(VecTape::<u16>::new(size));
+    let mut interpreter = Interpreter::from_machine(code.as_bytes(), machine)
+        .with_input(Box::new(Cursor::new(Vec::new())))
+        .with_eof_policy(EofPolicy::Max)
+        .with_output(Box::new(output.clone()));
+    let termination = match optimization {
+        Some(max_iterations) => interpreter.run_with_optimization(max_iterations),
+        None => interpreter.run(),
+    };
+    assert_eq!(termination.unwrap(), Termination::Completed, "{code}");
+    (interpreter.get_tape(), output.contents())
+}
+
+#[test]
+fn test_wide_cells_do_not_wrap_at_256() {
+    let code = "+".repeat(300);
+    for optimization in [None, Some(1), Some(0)] {
+        assert_eq!(run_wide(&code, 1, optimization).0, vec![300]);
+    }
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 1);
+    assert_eq!(
+        interpreter.run_with_optimization(0).unwrap(),
+        Termination::Completed
+    );
+    assert_eq!(interpreter.get_tape(), vec![44]);
+}
+
+#[test]
+fn test_wide_cells_arithmetic_and_io() {
+    // negative deltas wrap at the cell's width, the output is the lowest
+    // byte and the EOF sets the largest value
+    assert_eq!(
+        run_wide(&format!("-.>{}.>,", "+".repeat(258)), 3, None),
+        (vec![u16::MAX, 258, u16::MAX], vec![255, 2])
+    );
+    let machine = BrainfuckMachine::with_tape(VecTape::<u32>::new(2));
+    let mut interpreter = Interpreter::from_machine("->--".as_bytes(), machine);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape(), vec![u32::MAX, u32::MAX - 1]);
+}
+
+#[test]
+fn test_wide_cells_optimized_like_parsed() {
+    let codes = [
+        // 256 is not zero, so the loop isn't dead
+        format!("{}[>+<-]>.", "+".repeat(256)),
+        // a fill of -1 is not a fill of 255
+        ">[-]->[-]-<<".to_string(),
+        format!("{}{}[-]>", "+".repeat(200), "-".repeat(100)),
+    ];
+    for code in codes {
+        let expected = run_wide(&code, 4, None);
+        assert_eq!(run_wide(&code, 4, Some(0)), expected, "{code}");
+    }
+    assert_eq!(
+        run_wide(&format!("{}[>+<-]>.", "+".repeat(256)), 2, Some(0)).0,
+        vec![0, 256]
+    );
+    assert_eq!(
+        run_wide(">[-]->[-]-<<", 3, Some(0)).0,
+        vec![0, u16::MAX, u16::MAX]
+    );
+}
@@ -1,33 +1,27 @@
 use std::iter::zip;
 
-use crate::{Lexer, Token};
+use crate::{Lexer, SideEffectKind, Token};
 
-use super::utils::test_lexer;
+use super::utils::{test_lexer, ChunkedReader, Lcg, SegmentedReader};
 
 #[test]
 fn test_eof_true() {
     let code = String::from("");
-    let mut lexer = Lexer {
-        reader: code.as_bytes(),
-    };
-    assert!(lexer.eof());
+    let mut lexer = Lexer::new(code.as_bytes());
+    assert!(lexer.next_token().is_none());
 }
 
 #[test]
 fn test_eof_false() {
     let code = String::from(".");
-    let mut lexer = Lexer {
-        reader: code.as_bytes(),
-    };
-    assert!(!lexer.eof());
+    let mut lexer = Lexer::new(code.as_bytes());
+    assert!(lexer.next_token().is_some());
 }
 
 #[test]
 fn test_next_token_valid_tokens() {
     let code = String::from("><,.+-[]");
-    let mut lexer = Lexer {
-        reader: code.as_bytes(),
-    };
+    let mut lexer = Lexer::new(code.as_bytes());
     let expected: Vec<Token> = vec![
         Token::ShiftRight,
         Token::ShiftLeft,
@@ -39,46 +33,46 @@ fn test_next_token_valid_tokens() {
         Token::EndLoop,
     ];
     for exp in expected {
-        assert!(!lexer.eof());
         let token = lexer.next_token();
         assert!(token.is_some());
         assert_eq!(token.unwrap(), exp);
     }
-    assert!(lexer.eof());
     let token = lexer.next_token();
     assert!(token.is_none());
-    assert!(lexer.eof());
+    assert!(lexer.next_token().is_none());
 }
 
 #[test]
 fn test_next_token_other_symbols() {
     let code = String::from("abcdef");
-    let mut lexer = Lexer {
-        reader: code.as_bytes(),
-    };
-    while !lexer.eof() {
-        let token = lexer.next_token();
-        assert!(token.is_none());
-    }
+    let mut lexer = Lexer::new(code.as_bytes());
+    assert!(lexer.next_token().is_none());
+}
+
+#[test]
+fn test_next_token_skips_other_symbols() {
+    let code = String::from("a+b c\n-");
+    let mut lexer = Lexer::new(code.as_bytes());
+    assert_eq!(lexer.next_token(), Some(Token::Increment));
+    assert_eq!(lexer.next_token(), Some(Token::Decrement));
+    assert_eq!(lexer.next_token(), None);
 }
 
 #[test]
 fn test_iter_valid_tokens() {
     let code = String::from("><,.+-[]");
-    let lexer = Lexer {
-        reader: code.as_bytes(),
-    };
-    let expected: Vec<Option<Token>> = vec![
-        Some(Token::ShiftRight),
-        Some(Token::ShiftLeft),
-        Some(Token::ReadChar),
-        Some(Token::PutChar),
-        Some(Token::Increment),
-        Some(Token::Decrement),
-        Some(Token::StartLoop),
-        Some(Token::EndLoop),
+    let lexer = Lexer::new(code.as_bytes());
+    let expected: Vec<Token> = vec![
+        Token::ShiftRight,
+        Token::ShiftLeft,
+        Token::ReadChar,
+        Token::PutChar,
+        Token::Increment,
+        Token::Decrement,
+        Token::StartLoop,
+        Token::EndLoop,
     ];
-    let mut actual: Vec<Option<Token>> = Vec::new();
+    let mut actual: Vec<Token> = Vec::new();
     for token in lexer.into_iter() {
         actual.push(token);
     }
@@ -91,22 +85,185 @@ fn test_iter_valid_tokens() {
 #[test]
 fn test_iter_other_symbols() {
     let code = String::from("abcdef");
-    let expected: Vec<Option<Token>> = vec![None, None, None, None, None, None];
+    let expected: Vec<Token> = Vec::new();
     test_lexer(&code, &expected);
 }
 
 #[test]
 fn test_for_loop() {
     let code = String::from("><,.+-[]");
-    let expected: Vec<Option<Token>> = vec![
-        Some(Token::ShiftRight),
-        Some(Token::ShiftLeft),
-        Some(Token::ReadChar),
-        Some(Token::PutChar),
-        Some(Token::Increment),
-        Some(Token::Decrement),
-        Some(Token::StartLoop),
-        Some(Token::EndLoop),
+    let expected: Vec<Token> = vec![
+        Token::ShiftRight,
+        Token::ShiftLeft,
+        Token::ReadChar,
+        Token::PutChar,
+        Token::Increment,
+        Token::Decrement,
+        Token::StartLoop,
+        Token::EndLoop,
     ];
     test_lexer(&code, &expected);
 }
+
+#[test]
+fn test_chunked_reader() {
+    let code = "+[>,.<-] comment ]";
+    let mut lexer = Lexer::new(ChunkedReader::new(code.as_bytes(), 3));
+    let actual: Vec<Token> = lexer.iter().collect();
+    let expected: Vec<Token> = code
+        .chars()
+        .filter_map(|chr| Lexer::<&[u8]>::tokenize(&chr))
+        .collect();
+    assert_eq!(actual, expected);
+    assert!(lexer.next_token().is_none());
+    // every chunk is fetched once, plus a single fetch noticing the EOF
+    assert_eq!(lexer.reader.fetches, code.len().div_ceil(3) + 1);
+}
+
+#[test]
+fn test_segmented_reader() {
+    let segments: [&[u8]; 4] = [b"+[>", b"", b",.<-] x", b"]"];
+    let lexer = Lexer::new(SegmentedReader::new(&segments));
+    let actual: Vec<Token> = lexer.into_iter().collect();
+    let expected: Vec<Token> = segments
+        .concat()
+        .iter()
+        .filter_map(|&byte| Lexer::<&[u8]>::tokenize(&(byte as char)))
+        .collect();
+    assert_eq!(actual, expected);
+    assert_eq!(actual.len(), 9);
+}
+
+#[test]
+fn test_peek_does_not_consume() {
+    let code = String::from("+-");
+    let mut lexer = Lexer::new(ChunkedReader::new(code.as_bytes(), 1));
+    assert_eq!(lexer.peek_byte(), Some(b'+'));
+    assert_eq!(lexer.peek_byte(), Some(b'+'));
+    assert_eq!(lexer.next_token(), Some(Token::Increment));
+    assert_eq!(lexer.peek_byte(), Some(b'-'));
+    assert_eq!(lexer.next_token(), Some(Token::Decrement));
+    assert_eq!(lexer.next_token(), None);
+    assert_eq!(lexer.reader.fetches, 3);
+}
+
+#[test]
+fn test_terminator() {
+    let code = String::from("+.!,rest");
+    let mut reader = code.as_bytes();
+    let mut lexer = Lexer::new(&mut reader);
+    lexer.terminator = Some(b'!');
+    let actual: Vec<Token> = lexer.iter().collect();
+    assert_eq!(actual, vec![Token::Increment, Token::PutChar]);
+    assert!(lexer.next_token().is_none());
+    assert_eq!(reader, b",rest");
+}
+
+// The semantics of the lexer, spelled out in the simplest way. The code ends
+// at the end of the input or at the first terminator, which isn't part of
+// it. Every command is a token of its own, the other bytes are skipped or,
+// with the comments kept, every run of them becomes a single comment.
+fn reference_tokens(code: &[u8], keep_comments: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut comment = Vec::new();
+    for &byte in code {
+        let token = match byte {
+            b'+' => Token::Increment,
+            b'-' => Token::Decrement,
+            b'<' => Token::ShiftLeft,
+            b'>' => Token::ShiftRight,
+            b'[' => Token::StartLoop,
+            b']' => Token::EndLoop,
+            b'.' => Token::PutChar,
+            b',' => Token::ReadChar,
+            _ => {
+                if keep_comments {
+                    comment.push(byte);
+                }
+                continue;
+            }
+        };
+        if !comment.is_empty() {
+            tokens.push(Token::Comment(std::mem::take(&mut comment)));
+        }
+        tokens.push(token);
+    }
+    if !comment.is_empty() {
+        tokens.push(Token::Comment(comment));
+    }
+    tokens
+}
+
+// Code made of about 30% commands, the rest being words, whitespace and
+// non-ASCII text, with a `!` somewhere in its second half.
+fn random_source(rng: &mut Lcg, length: usize) -> Vec<u8> {
+    let pieces: [&[u8]; 8] = [
+        b"+",
+        b"-<",
+        b">>",
+        b"[",
+        b"].,",
+        b" word ",
+        b"\n\t",
+        "\u{e9}t\u{e9} ".as_bytes(),
+    ];
+    let mut code = Vec::new();
+    while code.len() < length {
+        code.extend_from_slice(pieces[rng.next(pieces.len() as u64) as usize]);
+    }
+    let terminator = length / 2 + rng.next(length as u64 / 2 + 1) as usize;
+    code.insert(terminator, b'!');
+    code
+}
+
+#[test]
+fn test_lexer_matches_reference() {
+    let mut rng = Lcg(7);
+    for length in [0, 1, 10, 1000, 100_000] {
+        let code = random_source(&mut rng, length);
+        for (chunk, terminator, keep_comments) in [
+            (1, None, false),
+            (3, None, true),
+            (7, Some(b'!'), false),
+            (64, Some(b'!'), true),
+            (8192, None, false),
+        ] {
+            let mut lexer = Lexer::new(ChunkedReader::new(&code, chunk));
+            lexer.terminator = terminator;
+            lexer.keep_comments = keep_comments;
+            let actual: Vec<Token> = lexer.iter().collect();
+            // the code ends right before the terminator
+            let end = terminator
+                .and_then(|terminator| code.iter().position(|&byte| byte == terminator))
+                .unwrap_or(code.len());
+            let case = format!("length {length}, chunk {chunk}, terminator {terminator:?}");
+            assert!(
+                actual == reference_tokens(&code[..end], keep_comments),
+                "{case}, comments {keep_comments}"
+            );
+            assert_eq!(lexer.position, end, "{case}");
+        }
+    }
+}
+
+#[test]
+fn test_display_tokens() {
+    let cases = [
+        (Token::Increment, "+"),
+        (Token::Decrement, "-"),
+        (Token::ShiftLeft, "<"),
+        (Token::ShiftRight, ">"),
+        (Token::StartLoop, "["),
+        (Token::EndLoop, "]"),
+        (Token::PutChar, "."),
+        (Token::ReadChar, ","),
+        (Token::SideEffect(SideEffectKind::Bell), "bell"),
+        (Token::SideEffect(SideEffectKind::Custom(7)), "custom 7"),
+        (Token::LoadTapeSize, "size"),
+        (Token::Random, "rand"),
+        (Token::Comment(b"a \"b\"\n".to_vec()), r#""a \"b\"\n""#),
+    ];
+    for (token, expected) in cases {
+        assert_eq!(token.to_string(), expected);
+    }
+}
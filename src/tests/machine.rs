@@ -1,4 +1,10 @@
-use crate::BrainfuckMachine;
+use crate::{
+    BrainfuckError, BrainfuckMachine, CellOverflow, LazyTape, MachineError, Tape, TapePolicy,
+    VecTape,
+};
+
+use super::utils::count_allocations;
+
 #[test]
 fn test_index_change_base() {
     let mut machine = BrainfuckMachine::new(10);
@@ -22,6 +28,114 @@ fn test_index_change_right_panic() {
     machine.move_right(11);
 }
 
+#[test]
+fn test_index_change_right_last_cell() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(9);
+    assert_eq!(machine.index, 9);
+    machine.add(3);
+    assert_eq!(machine.put_byte(), 3);
+    assert_eq!(machine.tape[9], 3);
+}
+
+#[test]
+#[should_panic = "Index out of bounds.\nIndex before move: 0.\nRight shift value: 10.\nMax possible index: 9."]
+fn test_index_change_right_one_past_last_cell_panic() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(10);
+}
+
+#[test]
+#[should_panic = "Index out of bounds.\nIndex before move: 9.\nRight shift value: 1."]
+fn test_index_change_right_from_last_cell_panic() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(9);
+    machine.move_right(1);
+}
+
+#[test]
+fn test_try_move_left_off_fresh_machine() {
+    let mut machine = BrainfuckMachine::new(10);
+    assert_eq!(
+        machine.try_move_left(1),
+        Err(MachineError::Underflow {
+            index: 0,
+            shift: 1,
+            size: 10
+        })
+    );
+    assert_eq!(machine.index, 0);
+}
+
+#[test]
+fn test_try_move_left_past_first_cell() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(3);
+    assert_eq!(
+        machine.try_move_left(4),
+        Err(MachineError::Underflow {
+            index: 3,
+            shift: 4,
+            size: 10
+        })
+    );
+    assert_eq!(machine.index, 3);
+    assert_eq!(machine.try_move_left(3), Ok(()));
+    assert_eq!(machine.index, 0);
+}
+
+#[test]
+fn test_try_moves_out_of_bounds_fields() {
+    // both directions carry the same fields
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(2);
+    for (error, expected_shift) in [
+        (machine.try_move_left(5).unwrap_err(), 5),
+        (machine.try_move_right(8).unwrap_err(), 8),
+    ] {
+        let (MachineError::Overflow { index, shift, size }
+        | MachineError::Underflow { index, shift, size }) = error
+        else {
+            panic!("unexpected error: {error:?}");
+        };
+        assert_eq!((index, shift, size), (2, expected_shift, 10));
+    }
+    assert_eq!(machine.index, 2);
+}
+
+#[test]
+fn test_try_move_right_past_end() {
+    let mut machine = BrainfuckMachine::new(10);
+    let error = (0..20)
+        .map(|_| machine.try_move_right(1))
+        .find_map(Result::err)
+        .unwrap();
+    assert_eq!(
+        error,
+        MachineError::Overflow {
+            index: 9,
+            shift: 1,
+            size: 10
+        }
+    );
+    assert_eq!(machine.index, 9);
+    assert_eq!(
+        error.to_string(),
+        "Error: moving the pointer right by 1 from cell 9 leaves the tape of 10 cells."
+    );
+    assert!(machine.try_move_right(usize::MAX).is_err());
+    assert_eq!(machine.try_move_left(9), Ok(()));
+    assert_eq!(machine.index, 0);
+}
+
+#[test]
+fn test_index_change_left_first_cell() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.move_right(4);
+    machine.move_left(4);
+    assert_eq!(machine.index, 0);
+}
+
 #[test]
 fn test_value_change_base() {
     let mut machine = BrainfuckMachine::new(10);
@@ -68,13 +182,46 @@ fn test_put_char() {
         result as u8
     );
 }
+#[test]
+fn test_put_byte() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.substract(1);
+    assert_eq!(machine.put_byte(), 0xFF);
+    // the char conversion is lossy when encoded as UTF-8
+    assert_eq!(machine.put_char().len_utf8(), 2);
+}
+
 #[test]
 fn test_read_char() {
     let mut machine = BrainfuckMachine::new(10);
-    machine.read_char('A');
+    machine.read_char('A').unwrap();
     let result = machine.tape[machine.index];
     assert_eq!(result, 65, "Different char read. Char read: {}.", result);
 }
+
+#[test]
+fn test_read_char_latin1() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.read_char('é').unwrap();
+    assert_eq!(machine.put_byte(), 0xE9);
+    assert_eq!(machine.put_char(), 'é');
+}
+
+#[test]
+fn test_read_char_too_wide() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.add(7);
+    let error = machine.read_char('ł').unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(machine.put_byte(), 7);
+}
+
+#[test]
+fn test_read_byte() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.read_byte(0xC3);
+    assert_eq!(machine.put_byte(), 0xC3);
+}
 #[test]
 fn test_check_loop() {
     let mut machine = BrainfuckMachine::new(10);
@@ -84,3 +231,358 @@ fn test_check_loop() {
     machine.substract(4);
     assert!(!machine.check_loop());
 }
+
+#[test]
+fn test_saturating_cells() {
+    let mut machine = BrainfuckMachine::new(2).with_cell_overflow(CellOverflow::Saturate);
+    machine.substract(1);
+    assert_eq!(machine.tape[0], 0);
+    machine.add(200);
+    machine.add(100);
+    assert_eq!(machine.tape[0], 255);
+    machine.substract(55);
+    assert_eq!(machine.tape[0], 200);
+
+    let mut machine = BrainfuckMachine::with_tape(VecTape::<u16>::new(1))
+        .with_cell_overflow(CellOverflow::Saturate);
+    machine.add(300);
+    machine.substract(400);
+    assert_eq!(machine.current_cell(), 0);
+}
+
+#[test]
+fn test_load() {
+    let mut machine = BrainfuckMachine::new(10);
+    machine.load(7, &[1, 2, 3]).unwrap();
+    assert_eq!(*machine.tape, [0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+    assert!(machine.load(8, &[1, 2, 3]).is_err());
+    assert_eq!(*machine.tape, [0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+}
+
+#[test]
+fn test_diff() {
+    let mut machine = BrainfuckMachine::new(10);
+    let mut other = BrainfuckMachine::new(10);
+    machine.load(0, &[1, 2, 3, 4]).unwrap();
+    other.load(0, &[1, 5, 3, 4, 0, 0, 0, 0, 0, 9]).unwrap();
+    other.move_right(3);
+    assert_eq!(machine.diff(&other), vec![(1, 2, 5), (9, 0, 9)]);
+    assert_eq!(other.diff(&other), vec![]);
+    assert_eq!(machine.diff_index(&other), Some((0, 3)));
+    assert_eq!(other.diff_index(&other), None);
+}
+
+#[test]
+fn test_diff_tapes_of_different_sizes() {
+    let mut machine = BrainfuckMachine::new(3);
+    let mut other = BrainfuckMachine::new(5);
+    machine.load(0, &[1, 0, 0]).unwrap();
+    other.load(0, &[1, 0, 0, 0, 7]).unwrap();
+    assert_eq!(machine.diff(&other), vec![(4, 0, 7)]);
+    assert_eq!(other.diff(&machine), vec![(4, 7, 0)]);
+}
+
+#[test]
+fn test_machine_over_buffer() {
+    let mut buffer = [9u8; 8];
+    let (mut machine, allocations) =
+        count_allocations(|| BrainfuckMachine::over(&mut buffer[2..6]));
+    assert_eq!(allocations, 0);
+    machine.read_byte(0);
+    machine.add(3);
+    machine.move_right(3);
+    machine.substract(2);
+    assert_eq!(machine.peek(4), None);
+    assert_eq!(buffer, [9, 9, 3, 9, 9, 7, 9, 9]);
+}
+
+#[test]
+#[should_panic]
+fn test_machine_over_buffer_right_panic() {
+    let mut buffer = [0u8; 8];
+    let mut machine = BrainfuckMachine::over(&mut buffer[..4]);
+    machine.move_right(4);
+}
+
+#[test]
+fn test_error_policy_at_both_edges() {
+    let mut machine = BrainfuckMachine::new(5).with_policy(TapePolicy::Error);
+    assert!(machine.try_move_left(1).is_err());
+    assert!(machine.try_move_right(5).is_err());
+    assert_eq!(machine.index, 0);
+}
+
+#[test]
+fn test_wrap_policy_at_both_edges() {
+    let mut machine = BrainfuckMachine::new(5).with_policy(TapePolicy::Wrap);
+    machine.try_move_left(1).unwrap();
+    assert_eq!(machine.index, 4);
+    machine.try_move_right(2).unwrap();
+    assert_eq!(machine.index, 1);
+    machine.try_move_left(13).unwrap();
+    assert_eq!(machine.index, 3);
+    machine.try_move_right(10).unwrap();
+    assert_eq!(machine.index, 3);
+    assert_eq!(machine.get_tape().len(), 5);
+}
+
+#[test]
+fn test_saturate_policy_at_both_edges() {
+    let mut machine = BrainfuckMachine::new(5).with_policy(TapePolicy::Saturate);
+    machine.try_move_left(3).unwrap();
+    assert_eq!(machine.index, 0);
+    machine.try_move_right(usize::MAX).unwrap();
+    assert_eq!(machine.index, 4);
+    assert_eq!(machine.get_tape().len(), 5);
+}
+
+#[test]
+fn test_grow_policy_at_both_edges() {
+    let mut machine = BrainfuckMachine::new(3).with_policy(TapePolicy::Grow);
+    assert_eq!(
+        machine.try_move_left(1),
+        Err(MachineError::Underflow {
+            index: 0,
+            shift: 1,
+            size: 3,
+        })
+    );
+    machine.add(7);
+    machine.try_move_right(6).unwrap();
+    assert_eq!(machine.index, 6);
+    assert_eq!(machine.get_tape(), vec![7, 0, 0, 0, 0, 0, 0]);
+    machine.add(1);
+    machine.move_right(2);
+    assert_eq!(machine.get_tape(), vec![7, 0, 0, 0, 0, 0, 1, 0, 0]);
+}
+
+#[test]
+fn test_grow_policy_over_buffer() {
+    let mut buffer = [0u8; 4];
+    let mut machine = BrainfuckMachine::over(&mut buffer[..]).with_policy(TapePolicy::Grow);
+    assert_eq!(
+        machine.try_move_right(4),
+        Err(MachineError::Overflow {
+            index: 0,
+            shift: 4,
+            size: 4,
+        })
+    );
+    assert_eq!(machine.index, 0);
+}
+
+#[test]
+fn test_state_accessors() {
+    let mut machine = BrainfuckMachine::new(6);
+    assert_eq!(machine.tape_size(), 6);
+    assert_eq!(machine.current_index(), 0);
+    assert_eq!(machine.current_cell(), 0);
+    machine.add(4);
+    machine.move_right(3);
+    machine.add(9);
+    machine.substract(2);
+    assert_eq!(machine.current_index(), 3);
+    assert_eq!(machine.current_cell(), 7);
+    machine.move_left(3);
+    assert_eq!(machine.current_index(), 0);
+    assert_eq!(machine.current_cell(), 4);
+    assert_eq!(machine.tape_size(), 6);
+    assert_eq!(machine.get_tape(), vec![4, 0, 0, 7, 0, 0]);
+}
+
+#[test]
+fn test_tape_size_of_growing_tape() {
+    let mut machine = BrainfuckMachine::new(2).with_policy(TapePolicy::Grow);
+    machine.move_right(4);
+    machine.add(1);
+    assert_eq!(machine.tape_size(), 5);
+    assert_eq!(machine.current_index(), 4);
+    assert_eq!(machine.current_cell(), 1);
+}
+
+#[test]
+fn test_lazy_tape_stores_written_cells() {
+    let mut tape = LazyTape::new(100);
+    assert_eq!(tape.len(), 100);
+    assert_eq!(tape.get(99), 0);
+    tape.set(50, 0);
+    assert_eq!(tape.allocated(), 0);
+    tape.set(9, 4);
+    assert_eq!(tape.allocated(), 10);
+    assert_eq!(tape.get(9), 4);
+    tape.fill(5..20, 1);
+    assert_eq!(tape.allocated(), 20);
+    tape.fill(0..8, 0);
+    assert_eq!(tape.allocated(), 20);
+    assert_eq!(tape.get(9), 1);
+    tape.fill(10..100, 0);
+    assert_eq!(tape.allocated(), 10);
+    assert_eq!(tape.get(8), 1);
+    assert_eq!(tape.get(10), 0);
+}
+
+#[test]
+fn test_tape_is_zeroed() {
+    let mut tape = VecTape::<u8>::new(10);
+    assert!(tape.is_zeroed());
+    tape.set(9, 1);
+    assert!(!tape.is_zeroed());
+    let mut cells = [0u8, 0, 2];
+    let mut slice = &mut cells[..];
+    assert!(!Tape::is_zeroed(&slice));
+    slice.set(2, 0);
+    assert!(Tape::is_zeroed(&slice));
+    // only the stored cells are scanned, however long the tape is
+    let mut tape = LazyTape::new(usize::MAX);
+    assert!(tape.is_zeroed());
+    tape.set(1000, 3);
+    assert!(!tape.is_zeroed());
+    tape.set(1000, 0);
+    assert!(tape.is_zeroed());
+}
+
+#[test]
+fn test_lazy_machine() {
+    let mut machine = BrainfuckMachine::lazy(1000);
+    machine.move_right(999);
+    assert_eq!(machine.tape.allocated(), 0);
+    assert!(machine.try_move_right(1).is_err());
+    machine.add(3);
+    machine.move_left(990);
+    machine.add(1);
+    assert_eq!(machine.tape.allocated(), 1000);
+    let tape = machine.get_tape();
+    assert_eq!(tape.len(), 1000);
+    assert_eq!((tape[9], tape[999]), (1, 3));
+}
+
+#[test]
+fn test_reset() {
+    let mut machine = BrainfuckMachine::new(8).with_policy(TapePolicy::Wrap);
+    for (shift, value) in [(1, 3), (2, 255), (4, 1)] {
+        machine.move_right(shift);
+        machine.add(value);
+    }
+    assert_eq!(machine.index, 7);
+    let (_, allocations) = count_allocations(|| machine.reset());
+    assert_eq!(allocations, 0);
+    assert_eq!(machine.index, 0);
+    assert_eq!(machine.get_tape(), vec![0; 8]);
+    assert_eq!(machine.tape_size(), 8);
+    // the policy is kept
+    machine.move_left(1);
+    assert_eq!(machine.index, 7);
+}
+
+#[test]
+fn test_grow_both_policy_left_of_origin() {
+    let mut machine = BrainfuckMachine::new(4).with_policy(TapePolicy::GrowBoth);
+    machine.move_left(1);
+    machine.add(1);
+    machine.move_left(1);
+    machine.add(1);
+    assert_eq!(machine.position(), -2);
+    assert_eq!(machine.tape_range(), (-4, 3));
+    assert_eq!(machine.get_tape(), vec![0, 0, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(machine.current_index(), 2);
+    machine.move_right(9);
+    assert_eq!(machine.position(), 7);
+    assert_eq!(machine.tape_range(), (-4, 7));
+    machine.reset();
+    assert_eq!(machine.position(), 0);
+    assert_eq!(machine.get_tape(), vec![0; 12]);
+}
+
+#[test]
+fn test_grow_both_policy_long_walk_left() {
+    let mut machine = BrainfuckMachine::new(1).with_policy(TapePolicy::GrowBoth);
+    for _ in 0..1000 {
+        machine.move_left(1);
+        machine.add(1);
+    }
+    assert_eq!(machine.position(), -1000);
+    let (first, last) = machine.tape_range();
+    assert!(first <= -1000 && first > -2000);
+    assert_eq!(last, 0);
+    let tape = machine.get_tape();
+    assert_eq!(tape.iter().filter(|&&cell| cell == 1).count(), 1000);
+}
+
+#[test]
+fn test_grow_both_policy_over_buffer() {
+    let mut buffer = [0u8; 4];
+    let mut machine = BrainfuckMachine::over(&mut buffer[..]).with_policy(TapePolicy::GrowBoth);
+    assert!(machine.try_move_left(1).is_err());
+    assert_eq!(machine.position(), 0);
+}
+
+#[test]
+fn test_wide_cells() {
+    let mut machine = BrainfuckMachine::with_tape(VecTape::<u16>::new(3));
+    for _ in 0..300 {
+        machine.add(1);
+    }
+    assert_eq!(machine.current_cell(), 300);
+    // only the lowest byte is output
+    assert_eq!(machine.put_byte(), 44);
+    machine.move_right(1);
+    machine.substract(1);
+    assert_eq!(machine.get_tape(), vec![300, u16::MAX, 0]);
+    machine.read_byte(200);
+    assert_eq!(machine.peek(1), Some(200));
+
+    let mut machine = BrainfuckMachine::with_tape(LazyTape::<u32>::with_len(2));
+    machine.substract(1);
+    machine.add(70_000);
+    assert_eq!(machine.get_tape(), vec![69_999, 0]);
+    machine.reset();
+    assert_eq!(machine.get_tape(), vec![0, 0]);
+}
+
+#[test]
+fn test_get_and_set_cell() {
+    let mut machine = BrainfuckMachine::new(4);
+    machine.set_cell(3, 65).unwrap();
+    assert_eq!(machine.get_cell(3), Some(65));
+    assert_eq!(machine.get_cell(4), None);
+    let error = machine.set_cell(4, 1).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        error.get_ref().unwrap().downcast_ref::<MachineError>(),
+        Some(&MachineError::CellOutOfBounds { index: 4, size: 4 })
+    );
+    assert_eq!(
+        error.to_string(),
+        "Error: cell 4 is past the end of the tape of 4 cells."
+    );
+    assert_eq!(machine.get_tape(), vec![0, 0, 0, 65]);
+    assert_eq!(machine.current_index(), 0);
+}
+
+#[test]
+fn test_machine_error_into_brainfuck_error() {
+    let error = BrainfuckError::from(MachineError::CellOutOfBounds { index: 4, size: 4 });
+    assert_eq!(error, BrainfuckError::CellOutOfBounds { index: 4, size: 4 });
+    assert_eq!(
+        error.to_string(),
+        "Error: cell 4 is past the end of the tape of 4 cells."
+    );
+    assert_eq!(
+        std::io::Error::from(error).kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+
+    let error = BrainfuckError::from(MachineError::Overflow {
+        index: 3,
+        shift: 2,
+        size: 4,
+    });
+    assert_eq!(
+        error,
+        BrainfuckError::TapeOverflow {
+            index: 3,
+            shift: 2,
+            span: None
+        }
+    );
+}
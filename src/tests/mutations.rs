@@ -0,0 +1,324 @@
+// Mutation-style tests of the optimization passes. Each built-in pass has a
+// table of cases: the canonical pattern that the pass rewrites, the code it
+// is rewritten to, and a "poisoned" variant differing in a single statement
+// (an extra `+`, an inner `.`, an unbalanced `>`, ...) that the pass has to
+// leave alone. Both variants are also run to check that the optimized code
+// behaves like the parsed one. A new pass needs rows of its own, otherwise
+// `test_every_pass_has_cases` fails.
+
+use std::io::Cursor;
+
+use crate::{
+    testing::CapturedOutput, Interpreter, OptimizationPass, Optimizer, Parser, Program, Statement,
+    Termination,
+};
+
+struct Case {
+    canonical: &'static str,
+    rewritten: Vec<Statement>,
+    poisoned: &'static str,
+    // the input given to both variants when they are run
+    input: &'static [u8],
+}
+
+fn cases(pass: OptimizationPass) -> Vec<Case> {
+    match pass {
+        OptimizationPass::Fold => fold_cases(),
+        OptimizationPass::FuseClears => fuse_clears_cases(),
+        OptimizationPass::FillRegions => fill_regions_cases(),
+        OptimizationPass::RemoveDeadLoops => remove_dead_loops_cases(),
+        OptimizationPass::Custom(_) => Vec::new(),
+    }
+}
+
+fn fold_cases() -> Vec<Case> {
+    vec![
+        Case {
+            canonical: ",++.",
+            rewritten: vec![Statement::ReadChar, Statement::Add(2), Statement::PutChar],
+            poisoned: ",+.+.",
+            input: b"a",
+        },
+        Case {
+            // additions cancelling out are dropped
+            canonical: ",+-.",
+            rewritten: vec![Statement::ReadChar, Statement::PutChar],
+            poisoned: ",+.-.",
+            input: b"a",
+        },
+        Case {
+            canonical: ",>>>.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::MoveRight(3),
+                Statement::PutChar,
+            ],
+            poisoned: ",><>.",
+            input: b"a",
+        },
+        Case {
+            canonical: ",[-->+<]>.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::new_loop(vec![
+                    Statement::Add(254),
+                    Statement::MoveRight(1),
+                    Statement::Add(1),
+                    Statement::MoveLeft(1),
+                ]),
+                Statement::MoveRight(1),
+                Statement::PutChar,
+            ],
+            poisoned: ",[-.->+<]>.",
+            input: &[6],
+        },
+    ]
+}
+
+fn fuse_clears_cases() -> Vec<Case> {
+    vec![
+        Case {
+            canonical: ",>,<[-]>[-]<.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::MoveLeft(1),
+                Statement::ZeroCells(vec![0, 1]),
+                Statement::PutChar,
+            ],
+            poisoned: ",>,<[-]>[-]>.",
+            input: b"ab",
+        },
+        Case {
+            canonical: ",>>,<<[-]>>[-]<<.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::MoveRight(1),
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::MoveLeft(1),
+                Statement::MoveLeft(1),
+                Statement::ZeroCells(vec![0, 2]),
+                Statement::PutChar,
+            ],
+            poisoned: ",>>,<<[-]>+>[-]<<.",
+            input: b"ab",
+        },
+        Case {
+            // the run doesn't have to start at the lowest cleared cell
+            canonical: ">,>,[-]<[-]>.",
+            rewritten: vec![
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::ZeroCells(vec![-1, 0]),
+                Statement::PutChar,
+            ],
+            poisoned: ">,>,[-]<[-]<.",
+            input: b"ab",
+        },
+        Case {
+            canonical: ",>,>,<<[-]>[-]>[-]<<.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::MoveLeft(1),
+                Statement::MoveLeft(1),
+                Statement::ZeroCells(vec![0, 1, 2]),
+                Statement::PutChar,
+            ],
+            poisoned: ",>,>,<<[-]>[-.]>[-]<<.",
+            input: b"abc",
+        },
+        Case {
+            canonical: ",[[-]>[-]<]",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::new_loop(vec![Statement::ZeroCells(vec![0, 1])]),
+            ],
+            poisoned: ",[[-]>[-]+<]",
+            input: b"a",
+        },
+    ]
+}
+
+fn fill_regions_cases() -> Vec<Case> {
+    vec![
+        Case {
+            canonical: ",>[-]+++>[-]+++<<.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::FillValue {
+                    from: 1,
+                    to: 3,
+                    value: 3,
+                },
+                Statement::PutChar,
+            ],
+            poisoned: ",>[-]+++>[-]++<<.",
+            input: b"a",
+        },
+        Case {
+            // the region may be on both sides of the pointer
+            canonical: ">,<[-]->[-]->[-]-<.",
+            rewritten: vec![
+                Statement::MoveRight(1),
+                Statement::ReadChar,
+                Statement::FillValue {
+                    from: -1,
+                    to: 2,
+                    value: 255,
+                },
+                Statement::PutChar,
+            ],
+            poisoned: ">,<[-]->+>[-]-<.",
+            input: b"a",
+        },
+        Case {
+            // only the additions to cleared cells are known
+            canonical: ",>[-]+>[-]+<<.",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::FillValue {
+                    from: 1,
+                    to: 3,
+                    value: 1,
+                },
+                Statement::PutChar,
+            ],
+            poisoned: ",>+>[-]+<<.",
+            input: b"a",
+        },
+        Case {
+            // an initialization loop only needs a single iteration
+            canonical: "+++[->[-]++>[-]++<<]>.",
+            rewritten: vec![
+                Statement::Add(1),
+                Statement::Add(1),
+                Statement::Add(1),
+                Statement::new_loop(vec![
+                    Statement::FillValue {
+                        from: 1,
+                        to: 3,
+                        value: 2,
+                    },
+                    Statement::ZeroCells(vec![0]),
+                ]),
+                Statement::MoveRight(1),
+                Statement::PutChar,
+            ],
+            poisoned: "+++[->[-]++>[-]+<<]>.",
+            input: b"",
+        },
+    ]
+}
+
+fn remove_dead_loops_cases() -> Vec<Case> {
+    vec![
+        Case {
+            canonical: "[-.]+.",
+            rewritten: vec![Statement::Add(1), Statement::PutChar],
+            poisoned: "+[-.]+.",
+            input: b"",
+        },
+        Case {
+            // a loop is only ever left with a zero guard
+            canonical: ",[-][.[-]]",
+            rewritten: vec![
+                Statement::ReadChar,
+                Statement::new_loop(vec![Statement::Add(255)]),
+            ],
+            poisoned: ",[-]+[.[-]]",
+            input: b"a",
+        },
+        Case {
+            // the rest of a pristine tape is known to be zero
+            canonical: ">[.]<.",
+            rewritten: vec![
+                Statement::MoveRight(1),
+                Statement::MoveLeft(1),
+                Statement::PutChar,
+            ],
+            poisoned: ",>[.]<.",
+            input: b"a",
+        },
+        Case {
+            canonical: "+-[.]",
+            rewritten: vec![Statement::Add(1), Statement::Add(255)],
+            poisoned: "+-,[.[-]]",
+            input: b"a",
+        },
+    ]
+}
+
+fn parse(source: &str) -> Vec<Statement> {
+    Parser::from_reader(source.as_bytes()).parse().unwrap()
+}
+
+// Runs a single pass over the parsed code, returns whether it reported a
+// change along with the code.
+fn apply(pass: OptimizationPass, statements: Vec<Statement>) -> (bool, Vec<Statement>) {
+    let mut optimizer = Optimizer::new(statements);
+    let (_, function) = optimizer.built_in_pass(pass).unwrap();
+    let changed = function(&mut optimizer);
+    (changed, optimizer.yield_back())
+}
+
+fn execute(statements: Vec<Statement>, input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_program(&Program { statements }, 16)
+        .with_input(Box::new(Cursor::new(input.to_vec())))
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(10_000);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    (output.contents(), interpreter.get_tape())
+}
+
+fn assert_equivalent(source: &str, optimized: Vec<Statement>, input: &[u8]) {
+    assert_eq!(
+        execute(parse(source), input),
+        execute(optimized, input),
+        "{source}"
+    );
+}
+
+#[test]
+fn test_every_pass_has_cases() {
+    for pass in OptimizationPass::BUILT_IN {
+        assert!(!cases(pass).is_empty(), "{pass:?} has no cases");
+    }
+}
+
+#[test]
+fn test_passes_fire_on_canonical_patterns() {
+    for pass in OptimizationPass::BUILT_IN {
+        for case in cases(pass) {
+            let (changed, optimized) = apply(pass, parse(case.canonical));
+            assert!(changed, "{pass:?} didn't fire on {}", case.canonical);
+            assert_eq!(optimized, case.rewritten, "{pass:?} on {}", case.canonical);
+            assert_equivalent(case.canonical, optimized, case.input);
+        }
+    }
+}
+
+#[test]
+fn test_passes_skip_poisoned_patterns() {
+    for pass in OptimizationPass::BUILT_IN {
+        for case in cases(pass) {
+            let parsed = parse(case.poisoned);
+            let (changed, optimized) = apply(pass, parsed.clone());
+            assert!(!changed, "{pass:?} fired on {}", case.poisoned);
+            assert_eq!(optimized, parsed, "{pass:?} on {}", case.poisoned);
+            // the other passes may still apply, but mustn't change the
+            // behavior either
+            let mut optimizer = Optimizer::new(parsed);
+            optimizer.optimize(0);
+            assert_equivalent(case.poisoned, optimizer.yield_back(), case.input);
+        }
+    }
+}
@@ -1,6 +1,14 @@
-use crate::Statement;
+use std::collections::BTreeSet;
 
-use super::utils::test_optimize_once;
+use crate::{
+    optimize_program, OptStats, OptimizationPass, Optimizer, OptimizerConfig, Program, Statement,
+    TerminationVerdict,
+};
+
+use super::utils::{
+    count_allocations, test_fill_regions, test_fuse_clears, test_optimize_once,
+    test_remove_dead_loops,
+};
 
 #[test]
 fn test_optimize_once_no_optimization() {
@@ -71,19 +79,23 @@ fn test_optimize_once_moves_left_and_right() {
         Statement::MoveRight(5),
         Statement::MoveRight(6),
     ];
-    let output = vec![Statement::MoveRight(4)];
+    let output = vec![Statement::MoveLeft(7), Statement::MoveRight(11)];
     test_optimize_once(&input, &output);
 }
 
 #[test]
-fn test_optimize_once_moves_left_and_right_no_shift() {
+fn test_optimize_once_moves_left_and_right_no_cancelling() {
     let input: Vec<Statement> = vec![
         Statement::MoveRight(3),
         Statement::MoveLeft(4),
         Statement::MoveLeft(5),
         Statement::MoveRight(6),
     ];
-    let output: Vec<Statement> = Vec::new();
+    let output = vec![
+        Statement::MoveRight(3),
+        Statement::MoveLeft(9),
+        Statement::MoveRight(6),
+    ];
     test_optimize_once(&input, &output);
 }
 
@@ -98,9 +110,11 @@ fn test_optimize_once_adds_and_moves() {
         Statement::MoveRight(6),
     ];
     let output = vec![
-        Statement::MoveLeft(1),
+        Statement::MoveRight(3),
+        Statement::MoveLeft(4),
         Statement::Add(7),
-        Statement::MoveRight(1),
+        Statement::MoveLeft(5),
+        Statement::MoveRight(6),
     ];
     test_optimize_once(&input, &output);
 }
@@ -119,3 +133,717 @@ fn test_optimize_once_adds_with_loop_end_of_file() {
     ])];
     test_optimize_once(&input, &output);
 }
+
+#[test]
+fn test_remove_dead_loops_start_of_program() {
+    // code: [][][]
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![]),
+        Statement::new_loop(vec![]),
+        Statement::new_loop(vec![]),
+    ];
+    let output: Vec<Statement> = Vec::new();
+    test_remove_dead_loops(&input, &output);
+}
+
+#[test]
+fn test_remove_dead_loops_after_move_on_zeroed_tape() {
+    // code: >[+]
+    let input: Vec<Statement> = vec![
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(1)]),
+    ];
+    let output = vec![Statement::MoveRight(1)];
+    test_remove_dead_loops(&input, &output);
+}
+
+#[test]
+fn test_remove_dead_loops_keep_infinite_loop() {
+    // code: +[]
+    let input: Vec<Statement> = vec![Statement::Add(1), Statement::new_loop(vec![])];
+    test_remove_dead_loops(&input, &input);
+}
+
+#[test]
+fn test_remove_dead_loops_after_loop() {
+    // code: +[-][+][.]
+    let input: Vec<Statement> = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::new_loop(vec![Statement::Add(1)]),
+        Statement::new_loop(vec![Statement::PutChar]),
+    ];
+    let output = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+    ];
+    test_remove_dead_loops(&input, &output);
+}
+
+#[test]
+fn test_remove_dead_loops_nested() {
+    // code: ,[>[-]<[-]]
+    let input: Vec<Statement> = vec![
+        Statement::ReadChar,
+        Statement::new_loop(vec![
+            Statement::MoveRight(1),
+            Statement::new_loop(vec![Statement::Add(255)]),
+            Statement::MoveLeft(1),
+            Statement::new_loop(vec![Statement::Add(255)]),
+        ]),
+    ];
+    test_remove_dead_loops(&input, &input);
+}
+
+#[test]
+fn test_optimize_without_io() {
+    let code = "[.,]+++--.>>,<<[-]";
+    let statements = code.parse::<Program>().unwrap().statements;
+    let config = OptimizerConfig {
+        optimize_io: false,
+        ..Default::default()
+    };
+    let mut optimizer = Optimizer::new(statements.clone()).with_config(config);
+    optimizer.optimize(0);
+    let output = vec![
+        Statement::new_loop(vec![Statement::PutChar, Statement::ReadChar]),
+        Statement::Add(1),
+        Statement::PutChar,
+        Statement::MoveRight(2),
+        Statement::ReadChar,
+        Statement::MoveLeft(2),
+        Statement::new_loop(vec![Statement::Add(255)]),
+    ];
+    assert_eq!(optimizer.yield_back(), output);
+
+    // the dead loop is only kept because it performs IO
+    let mut optimizer = Optimizer::new(statements);
+    optimizer.optimize(0);
+    assert_eq!(optimizer.yield_back()[..], output[1..]);
+}
+
+#[test]
+fn test_fuse_clears_pointer_preserving() {
+    // code: [-]>[-]>>[+]<<<
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::MoveRight(2),
+        Statement::new_loop(vec![Statement::Add(1)]),
+        Statement::MoveLeft(3),
+    ];
+    let output = vec![Statement::ZeroCells(vec![0, 1, 3])];
+    test_fuse_clears(&input, &output);
+}
+
+#[test]
+fn test_fuse_clears_nested_with_trailing_move() {
+    // code: ,[[-]<[-]>>]
+    let input: Vec<Statement> = vec![
+        Statement::ReadChar,
+        Statement::new_loop(vec![
+            Statement::new_loop(vec![Statement::Add(255)]),
+            Statement::MoveLeft(1),
+            Statement::new_loop(vec![Statement::Add(255)]),
+            Statement::MoveRight(1),
+            Statement::MoveRight(1),
+        ]),
+    ];
+    let output = vec![
+        Statement::ReadChar,
+        Statement::new_loop(vec![
+            Statement::ZeroCells(vec![-1, 0]),
+            Statement::MoveRight(1),
+        ]),
+    ];
+    test_fuse_clears(&input, &output);
+}
+
+#[test]
+fn test_fuse_clears_not_across_io() {
+    // code: [-]>.[-]<
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::MoveRight(1),
+        Statement::PutChar,
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::MoveLeft(1),
+    ];
+    test_fuse_clears(&input, &input);
+}
+
+#[test]
+fn test_fuse_clears_not_without_return() {
+    // code: [-]>[-][--]<
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::new_loop(vec![Statement::Add(254)]),
+        Statement::MoveLeft(1),
+    ];
+    test_fuse_clears(&input, &input);
+}
+
+#[test]
+fn test_optimize_oscillating_pass_terminates() {
+    let statements = vec![Statement::Add(1), Statement::PutChar];
+    let mut optimizer = Optimizer::new(statements.clone());
+    optimizer.extra_passes.push(|optimizer| {
+        optimizer.statements.reverse();
+        true
+    });
+    let report = optimizer.optimize(0);
+    assert_eq!(report.iterations, 2);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(optimizer.yield_back(), statements);
+}
+
+#[test]
+fn test_optimize_diverging_pass_hits_ceiling() {
+    let mut optimizer = Optimizer::new(Vec::new());
+    optimizer.extra_passes.push(|optimizer| {
+        optimizer.statements.push(Statement::MoveRight(1));
+        true
+    });
+    let report = optimizer.optimize(0);
+    assert_eq!(report.iterations, 1000);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(
+        optimizer.yield_back(),
+        vec![Statement::MoveRight(999), Statement::MoveRight(1)]
+    );
+}
+
+#[test]
+fn test_optimize_converged_code_is_not_cloned() {
+    let code = "++[->+<]>.".repeat(100);
+    let statements = code.parse::<Program>().unwrap().statements;
+    let mut optimizer = Optimizer::new(statements);
+    assert_eq!(optimizer.optimize(0).iterations, 2);
+
+    // the code is optimal now, so a single iteration of the passes is run
+    let (_, pass_allocations) = count_allocations(|| optimizer.run_passes());
+    let (report, allocations) = count_allocations(|| optimizer.optimize(0));
+    assert_eq!(report.iterations, 1);
+    assert!(report.warnings.is_empty());
+    // a clone of the code alone would take over 300 allocations
+    assert!(allocations < pass_allocations + 10);
+}
+
+// Optimizes the code until it stops changing, with the dead loops kept.
+fn optimize_fixpoint(input: &[Statement]) -> Vec<Statement> {
+    let mut optimizer = Optimizer::new(input.to_vec()).with_zeroed_tape(false);
+    optimizer.optimize(0);
+    optimizer.yield_back()
+}
+
+#[test]
+fn test_optimize_add_run_then_loop() {
+    // code: ++[-]+
+    let input: Vec<Statement> = vec![
+        Statement::Add(1),
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+    ];
+    let output = vec![
+        Statement::Add(2),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+    ];
+    test_optimize_once(&input, &output);
+    assert_eq!(optimize_fixpoint(&input), output);
+}
+
+#[test]
+fn test_optimize_loop_then_add_run() {
+    // code: [>]+++--
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::MoveRight(1)]),
+        Statement::Add(1),
+        Statement::Add(1),
+        Statement::Add(1),
+        Statement::Add(255),
+        Statement::Add(255),
+    ];
+    let output = vec![
+        Statement::new_loop(vec![Statement::MoveRight(1)]),
+        Statement::Add(1),
+    ];
+    test_optimize_once(&input, &output);
+    assert_eq!(optimize_fixpoint(&input), output);
+}
+
+#[test]
+fn test_optimize_moves_around_loop() {
+    // code: >>>[>>+<<-]>>
+    let input: Vec<Statement> = vec![
+        Statement::MoveRight(1),
+        Statement::MoveRight(1),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![
+            Statement::MoveRight(1),
+            Statement::MoveRight(1),
+            Statement::Add(1),
+            Statement::MoveLeft(1),
+            Statement::MoveLeft(1),
+            Statement::Add(255),
+        ]),
+        Statement::MoveRight(1),
+        Statement::MoveRight(1),
+    ];
+    let output = vec![
+        Statement::MoveRight(3),
+        Statement::new_loop(vec![
+            Statement::MoveRight(2),
+            Statement::Add(1),
+            Statement::MoveLeft(2),
+            Statement::Add(255),
+        ]),
+        Statement::MoveRight(2),
+    ];
+    test_optimize_once(&input, &output);
+    assert_eq!(optimize_fixpoint(&input), output);
+}
+
+#[test]
+fn test_optimize_adjacent_loops() {
+    // code: +[-][-]+
+    let input: Vec<Statement> = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+    ];
+    test_optimize_once(&input, &input);
+    // the second loop is dead, as the first one leaves a zero behind
+    let output = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+    ];
+    assert_eq!(optimize_fixpoint(&input), output);
+}
+
+#[test]
+fn test_optimize_program_stats() {
+    // a dead loop, a foldable run and two fusable clears
+    let code = "[.]+++--[-]>[-]<,";
+    let program = Program::from_reader(code.as_bytes()).unwrap();
+    let (program, stats) = optimize_program(program, OptimizerConfig::default());
+    assert_eq!(
+        program.statements,
+        vec![
+            Statement::Add(1),
+            Statement::ZeroCells(vec![0, 1]),
+            Statement::ReadChar,
+        ]
+    );
+    assert_eq!(
+        stats,
+        OptStats {
+            iterations: 2,
+            passes_run: 8,
+            converged: true,
+            statements_before: 14,
+            statements_after: 3,
+            applied: vec![
+                OptimizationPass::Fold,
+                OptimizationPass::FuseClears,
+                OptimizationPass::RemoveDeadLoops,
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_custom_pass_in_fixed_point() {
+    // removes the `><` pairs, which lets the folding merge the additions
+    let mut config = OptimizerConfig::default();
+    config.add_custom_pass(Box::new(|statements: &[Statement]| {
+        let mut result: Vec<Statement> = Vec::new();
+        for statement in statements {
+            match (result.last(), statement) {
+                (Some(Statement::MoveRight(1)), Statement::MoveLeft(1)) => {
+                    result.pop();
+                }
+                _ => result.push(statement.clone()),
+            }
+        }
+        result
+    }));
+    let program = Program::from_reader("+><+><+".as_bytes()).unwrap();
+    let (program, stats) = optimize_program(program, config);
+    assert_eq!(program.statements, vec![Statement::Add(3)]);
+    assert!(stats.converged);
+    assert_eq!(stats.iterations, 3);
+    assert_eq!(
+        stats.applied,
+        vec![OptimizationPass::Custom(0), OptimizationPass::Fold]
+    );
+}
+
+#[test]
+fn test_fuse_clears_keeps_moves_past_cleared_cells() {
+    // code: [+]<>>[+]<, the `<` could move off the tape, so it has to stay
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::Add(1)]),
+        Statement::MoveLeft(1),
+        Statement::MoveRight(2),
+        Statement::new_loop(vec![Statement::Add(1)]),
+        Statement::MoveLeft(1),
+    ];
+    test_fuse_clears(&input, &input);
+}
+
+#[test]
+fn test_fill_regions_nested_initialization_loop() {
+    // code: ++++[->[-]+++++>[-]+++++>[-]+++++<<<]
+    let fill = vec![
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(5),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(5),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(5),
+        Statement::MoveLeft(3),
+    ];
+    let input: Vec<Statement> = vec![
+        Statement::Add(4),
+        Statement::new_loop([vec![Statement::Add(255)], fill].concat()),
+    ];
+    let output = vec![
+        Statement::Add(4),
+        Statement::new_loop(vec![
+            Statement::FillValue {
+                from: 1,
+                to: 4,
+                value: 5,
+            },
+            Statement::ZeroCells(vec![0]),
+        ]),
+    ];
+    test_fill_regions(&input, &output);
+}
+
+#[test]
+fn test_fill_regions_keeps_loop_with_even_guard() {
+    // code: [-->[-]+>[-]+<<], the guard may never reach zero
+    let input: Vec<Statement> = vec![Statement::new_loop(vec![
+        Statement::Add(254),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+        Statement::MoveRight(1),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+        Statement::MoveLeft(2),
+    ])];
+    let output = vec![Statement::new_loop(vec![
+        Statement::Add(254),
+        Statement::FillValue {
+            from: 1,
+            to: 3,
+            value: 1,
+        },
+    ])];
+    test_fill_regions(&input, &output);
+}
+
+#[test]
+fn test_fill_regions_not_with_gaps() {
+    // code: [-]+>>[-]+<<
+    let input: Vec<Statement> = vec![
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+        Statement::MoveRight(2),
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::Add(1),
+        Statement::MoveLeft(2),
+    ];
+    test_fill_regions(&input, &input);
+}
+
+#[test]
+fn test_fill_regions_tape_contents() {
+    let code = "+>+++++>>,<<<++++[->[-]+++++>[-]+++++>[-]+++++<<<]";
+    let program = Program::from_reader(code.as_bytes()).unwrap();
+    let (optimized, stats) = optimize_program(program.clone(), OptimizerConfig::default());
+    assert!(stats.applied.contains(&OptimizationPass::FillRegions));
+    let run = |program: &Program| {
+        let mut interpreter = crate::Interpreter::from_program(program, 8)
+            .with_input(Box::new(std::io::Cursor::new(b"x".to_vec())));
+        assert_eq!(interpreter.run().unwrap(), crate::Termination::Completed);
+        interpreter.get_tape()
+    };
+    assert_eq!(run(&optimized), [0, 5, 5, 5, 0, 0, 0, 0]);
+    assert_eq!(run(&program), run(&optimized));
+}
+
+#[test]
+fn test_add_loop_trips() {
+    // [--] from 4 ends after two iterations, from 3 it wraps around forever
+    let statement = Statement::new_loop(vec![Statement::Add(254)]);
+    let value = statement.add_loop_value().unwrap();
+    assert_eq!(Statement::add_loop_trips(value, 4), Some(2));
+    assert_eq!(Statement::add_loop_trips(value, 3), None);
+    assert!(!statement.is_clear_loop());
+    // odd values end from every start, even ones only from the multiples of
+    // their greatest power of two
+    for value in 0..=255u8 {
+        for start in 0..=255u8 {
+            let expected =
+                (0..256u32).find(|trips| start.wrapping_add(value.wrapping_mul(*trips as u8)) == 0);
+            assert_eq!(
+                Statement::add_loop_trips(value, start),
+                expected,
+                "value {value}, start {start}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_optimize_reports_infinite_add_loop() {
+    // code: +++[--] never ends, ++++[--] does after two iterations
+    for (start, infinite) in [(3, true), (4, false)] {
+        let statements = vec![
+            Statement::Add(start),
+            Statement::new_loop(vec![Statement::Add(254)]),
+        ];
+        let mut optimizer = Optimizer::new(statements.clone());
+        let report = optimizer.optimize(0);
+        let expected = match infinite {
+            true => TerminationVerdict::GuaranteedInfinite,
+            false => TerminationVerdict::Unknown,
+        };
+        assert_eq!(report.termination, expected, "start {start}");
+        assert!(report.warnings.is_empty());
+        assert_eq!(optimizer.yield_back(), statements);
+    }
+}
+
+#[test]
+fn test_optimize_reports_infinite_balanced_scan() {
+    // code: +[><] and +[>><<] never end, the guard is never changed
+    let bodies = [
+        vec![Statement::MoveRight(1), Statement::MoveLeft(1)],
+        vec![Statement::MoveRight(2), Statement::MoveLeft(2)],
+    ];
+    for body in bodies {
+        let statements = vec![Statement::Add(1), Statement::new_loop(body.clone())];
+        let mut optimizer = Optimizer::new(statements.clone());
+        let report = optimizer.optimize(0);
+        assert_eq!(
+            report.termination,
+            TerminationVerdict::GuaranteedInfinite,
+            "{body:?}"
+        );
+        assert_eq!(optimizer.yield_back(), statements);
+    }
+}
+
+#[test]
+fn test_optimize_scans_not_reported() {
+    // code: ,[><] may not be entered, +[>+<] changes the cells and +[>] moves
+    // the pointer away
+    let scans = [
+        vec![
+            Statement::ReadChar,
+            Statement::new_loop(vec![Statement::MoveRight(1), Statement::MoveLeft(1)]),
+        ],
+        vec![
+            Statement::Add(1),
+            Statement::new_loop(vec![
+                Statement::MoveRight(1),
+                Statement::Add(1),
+                Statement::MoveLeft(1),
+            ]),
+        ],
+        vec![
+            Statement::Add(1),
+            Statement::new_loop(vec![Statement::MoveRight(1)]),
+        ],
+    ];
+    for statements in scans {
+        let mut optimizer = Optimizer::new(statements.clone());
+        let report = optimizer.optimize(0);
+        assert_eq!(
+            report.termination,
+            TerminationVerdict::Unknown,
+            "{statements:?}"
+        );
+    }
+}
+
+fn saturating_verdict(statements: Vec<Statement>, saturating: bool) -> TerminationVerdict {
+    let mut optimizer = Optimizer::new(statements).with_saturating(saturating);
+    optimizer.optimize(0).termination
+}
+
+#[test]
+fn test_optimize_single_step_loops() {
+    // code: +++[+], the guard stops at 255 when saturating and never reaches
+    // zero, when wrapping it does after 253 iterations
+    let statements = vec![
+        Statement::Add(3),
+        Statement::new_loop(vec![Statement::Add(1)]),
+    ];
+    assert_eq!(
+        saturating_verdict(statements.clone(), true),
+        TerminationVerdict::GuaranteedInfinite
+    );
+    assert_eq!(
+        saturating_verdict(statements, false),
+        TerminationVerdict::Unknown
+    );
+    assert_eq!(Statement::add_loop_trips(1, 3), Some(253));
+    // code: +++[-] always reaches zero, after 3 iterations
+    let statements = vec![
+        Statement::Add(3),
+        Statement::new_loop(vec![Statement::Add(255)]),
+    ];
+    for saturating in [false, true] {
+        assert_eq!(
+            saturating_verdict(statements.clone(), saturating),
+            TerminationVerdict::Unknown,
+            "saturating {saturating}"
+        );
+    }
+    assert_eq!(Statement::add_loop_trips(255, 3), Some(3));
+}
+
+#[test]
+fn test_optimize_saturating_guard_deltas() {
+    // code: +[>-<+], only the guard's own deltas matter
+    let statements = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![
+            Statement::MoveRight(1),
+            Statement::Add(255),
+            Statement::MoveLeft(1),
+            Statement::Add(1),
+        ]),
+    ];
+    assert_eq!(
+        saturating_verdict(statements, true),
+        TerminationVerdict::GuaranteedInfinite
+    );
+    // code: +[>+<], only other cells change, so the guard stays at one
+    let statements = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![
+            Statement::MoveRight(1),
+            Statement::Add(1),
+            Statement::MoveLeft(1),
+        ]),
+    ];
+    assert_eq!(
+        saturating_verdict(statements, true),
+        TerminationVerdict::GuaranteedInfinite
+    );
+    // code: +[++-], the guard gets decremented at some point, so it may
+    // reach zero
+    let statements = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![Statement::Add(2), Statement::Add(255)]),
+    ];
+    assert_eq!(
+        saturating_verdict(statements, true),
+        TerminationVerdict::Unknown
+    );
+    assert_eq!(
+        Statement::new_loop(vec![
+            Statement::Add(2),
+            Statement::MoveRight(1),
+            Statement::Add(3),
+            Statement::MoveLeft(1),
+            Statement::Add(255),
+        ])
+        .guard_deltas(),
+        Some(vec![2, 255])
+    );
+    assert_eq!(
+        Statement::new_loop(vec![Statement::MoveRight(1), Statement::Add(1)]).guard_deltas(),
+        None
+    );
+}
+
+#[test]
+fn test_optimize_saturating_folds_same_signs_only() {
+    let statements = vec![
+        Statement::Add(2),
+        Statement::Add(3),
+        Statement::Add(255),
+        Statement::Add(1),
+    ];
+    let mut optimizer = Optimizer::new(statements).with_saturating(true);
+    optimizer.optimize_once();
+    assert_eq!(
+        optimizer.yield_back(),
+        [Statement::Add(5), Statement::Add(255), Statement::Add(1)]
+    );
+}
+
+fn offsets(values: &[isize]) -> BTreeSet<isize> {
+    values.iter().copied().collect()
+}
+
+#[test]
+fn test_touched_offsets_moves_and_adds() {
+    // code: +>>-<<<++
+    let block = vec![
+        Statement::Add(1),
+        Statement::MoveRight(2),
+        Statement::Add(255),
+        Statement::MoveLeft(3),
+        Statement::Add(2),
+    ];
+    let (read, written) = Statement::touched_offsets(&block).unwrap();
+    assert_eq!(read, offsets(&[-1, 0, 2]));
+    assert_eq!(written, offsets(&[-1, 0, 2]));
+}
+
+#[test]
+fn test_touched_offsets_clears_are_writes() {
+    let block = vec![
+        Statement::MoveRight(1),
+        Statement::ZeroCells(vec![-1, 0, 2]),
+        Statement::Add(3),
+        Statement::LoadTapeSize,
+        Statement::MoveLeft(1),
+    ];
+    let (read, written) = Statement::touched_offsets(&block).unwrap();
+    assert_eq!(read, offsets(&[1]));
+    assert_eq!(written, offsets(&[0, 1, 3]));
+}
+
+#[test]
+fn test_touched_offsets_empty_and_moves_only() {
+    assert_eq!(
+        Statement::touched_offsets(&[]),
+        Some((BTreeSet::new(), BTreeSet::new()))
+    );
+    let block = vec![Statement::MoveRight(5), Statement::Add(0)];
+    assert_eq!(
+        Statement::touched_offsets(&block),
+        Some((BTreeSet::new(), BTreeSet::new()))
+    );
+}
+
+#[test]
+fn test_touched_offsets_undeterminable() {
+    for statement in [
+        Statement::new_loop(vec![Statement::Add(255)]),
+        Statement::PutChar,
+        Statement::ReadChar,
+    ] {
+        let block = vec![Statement::Add(1), statement.clone()];
+        assert_eq!(Statement::touched_offsets(&block), None, "{statement:?}");
+    }
+}
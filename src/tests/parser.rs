@@ -1,8 +1,8 @@
-use std::io::{Error, ErrorKind};
+use std::io::{BufReader, Error, ErrorKind, Read};
 
-use crate::Statement;
+use crate::{Interpreter, ParseError, Parser, Program, SideEffectKind, Statement, Termination};
 
-use super::utils::{test_parser, test_parser_error};
+use super::utils::{count_allocations, test_parser, test_parser_error, FailingReader};
 
 #[test]
 fn test_parse_empty_string() {
@@ -97,15 +97,203 @@ fn test_parse_loop_invalid_redundant_right_bracket() {
 }
 
 #[test]
-fn test_parse_loop_optimize_remove_empty_loops() {
+fn test_parse_loop_keep_empty_loops() {
     let code = String::from("[][][]");
-    let result: Vec<Statement> = Vec::new();
+    let result: Vec<Statement> = vec![
+        Statement::new_loop(vec![]),
+        Statement::new_loop(vec![]),
+        Statement::new_loop(vec![]),
+    ];
     test_parser(&code, &result);
 }
 
 #[test]
-fn test_parse_loop_optimize_remove_empty_loops_nested() {
+fn test_parse_loop_keep_empty_loops_nested() {
     let code = String::from("[[[]]]");
-    let result: Vec<Statement> = Vec::new();
+    let result: Vec<Statement> = vec![Statement::new_loop(vec![Statement::new_loop(vec![
+        Statement::new_loop(vec![]),
+    ])])];
     test_parser(&code, &result);
 }
+
+#[test]
+fn test_parse_partial_mid_loop() {
+    let code = String::from("+[>[-");
+    let mut parser = Parser::from_reader(code.as_bytes());
+    let (statements, open) = parser.parse_partial().unwrap();
+    let expected = vec![
+        Statement::Add(1),
+        Statement::new_loop(vec![
+            Statement::MoveRight(1),
+            Statement::new_loop(vec![Statement::Add(255)]),
+        ]),
+    ];
+    assert_eq!(statements, expected);
+    assert_eq!(open, 2);
+}
+
+#[test]
+fn test_parse_partial_closed_loops() {
+    let code = String::from("+[>[-]<]");
+    let (program, open) = Program::from_reader_partial(code.as_bytes()).unwrap();
+    assert_eq!(program, code.parse::<Program>().unwrap());
+    assert_eq!(open, 0);
+}
+
+#[test]
+fn test_parse_partial_reopened_loop() {
+    let code = String::from("[[]][");
+    let (program, open) = Program::from_reader_partial(code.as_bytes()).unwrap();
+    assert_eq!(program.max_nesting_depth(), 2);
+    assert_eq!(open, 1);
+}
+
+#[test]
+fn test_parse_partial_unmatched_end() {
+    let code = String::from("+]");
+    let error = Program::from_reader_partial(code.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+fn parse_error(code: &str) -> ParseError {
+    let error = Program::from_reader(code.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    *error
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<ParseError>()
+        .unwrap()
+}
+
+#[test]
+fn test_parse_error_unmatched_end_offset() {
+    let code = "+[-]>]<<";
+    assert_eq!(
+        parse_error(code),
+        ParseError::UnmatchedLoopEnd { offset: 6 }
+    );
+}
+
+#[test]
+fn test_parse_error_unmatched_start_offset() {
+    let code = "+[[-] comment";
+    assert_eq!(
+        parse_error(code),
+        ParseError::UnmatchedLoopStart { offset: 13 }
+    );
+}
+
+#[test]
+fn test_parse_error_leaves_rest_of_reader() {
+    let mut reader = "+]-[".as_bytes();
+    let error = Program::from_reader(&mut reader).unwrap_err();
+    let error = error
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<ParseError>()
+        .unwrap();
+    assert_eq!(error.offset(), 2);
+    assert_eq!(reader, b"-[");
+}
+
+#[test]
+fn test_parse_leaves_input_after_terminator() {
+    let mut reader = "+[->+<]!data".as_bytes();
+    let mut interpreter = Interpreter::from_reader(&mut reader, 10).with_code_terminator(b'!');
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    drop(interpreter);
+    assert_eq!(reader, b"data");
+}
+
+#[test]
+fn test_loop_bodies_allocated_once() {
+    // a loop body is stored in place, so only the non-empty bodies allocate
+    let program = "+[>[-[]]<-]".parse::<Program>().unwrap();
+    let (_, allocations) = count_allocations(|| program.statements.clone());
+    // the top level code and the bodies of the two non-empty loops
+    assert_eq!(allocations, 3);
+}
+
+#[test]
+fn test_parse_read_error_is_returned() {
+    // used to panic instead of returning the error
+    let reader = "+[-"
+        .as_bytes()
+        .chain(FailingReader(ErrorKind::ConnectionReset));
+    let error = Program::from_reader(BufReader::new(reader)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn test_max_statements_rejects_large_program() {
+    // 7 statements: the loop, the 4 in its body and the 2 around it
+    let code = String::from("+[->+<]. comment");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10).with_max_statements(6);
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.get_ref().unwrap().downcast_ref::<ParseError>(),
+        Some(&ParseError::ProgramTooLarge {
+            count: 6,
+            offset: 8
+        })
+    );
+    assert_eq!(
+        error.to_string(),
+        "Error: the code has more than 6 statements."
+    );
+    // nothing was run
+    assert_eq!(interpreter.get_tape(), vec![0; 10]);
+}
+
+#[test]
+fn test_max_statements_accepts_program_within_limit() {
+    let code = String::from("+[->+<].");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10)
+        .with_output(Box::new(std::io::sink()))
+        .with_max_statements(7);
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(interpreter.get_tape()[1], 1);
+}
+
+#[test]
+fn test_display_statements() {
+    let cases = [
+        (Statement::MoveLeft(2), "<2"),
+        (Statement::MoveRight(3), ">3"),
+        (Statement::Add(5), "+5"),
+        (Statement::Add(255), "-1"),
+        (
+            Statement::new_loop(vec![
+                Statement::Add(255),
+                Statement::MoveRight(1),
+                Statement::new_loop(vec![Statement::PutChar]),
+                Statement::MoveLeft(1),
+            ]),
+            "[-1 >1 [.] <1]",
+        ),
+        (Statement::new_loop(Vec::new()), "[]"),
+        (Statement::PutChar, "."),
+        (Statement::ReadChar, ","),
+        (Statement::SideEffect(SideEffectKind::Bell), "bell"),
+        (
+            Statement::SideEffect(SideEffectKind::Custom(35)),
+            "custom 35",
+        ),
+        (Statement::LoadTapeSize, "size"),
+        (Statement::Random, "rand"),
+        (Statement::Comment(b"note\t".to_vec()), r#""note\t""#),
+        (Statement::ZeroCells(vec![-1, 2]), "0[-1, 2]"),
+        (
+            Statement::FillValue {
+                from: 1,
+                to: 4,
+                value: 3,
+            },
+            "3[1..4]",
+        ),
+    ];
+    for (statement, expected) in cases {
+        assert_eq!(statement.to_string(), expected);
+    }
+}
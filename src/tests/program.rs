@@ -0,0 +1,123 @@
+use std::fs;
+
+use crate::testing::run_program;
+use crate::{Program, Termination};
+
+#[test]
+fn test_net_pointer_offset_straight_line() {
+    let program: Program = ">>+<.>>>-<".parse().unwrap();
+    assert_eq!(program.net_pointer_offset(), Some(3));
+}
+
+#[test]
+fn test_net_pointer_offset_negative() {
+    let program: Program = "<<<+>".parse().unwrap();
+    assert_eq!(program.net_pointer_offset(), Some(-2));
+}
+
+#[test]
+fn test_net_pointer_offset_balanced_loops() {
+    let program: Program = ">>[->+<[>>-<<]]<".parse().unwrap();
+    assert_eq!(program.net_pointer_offset(), Some(1));
+}
+
+#[test]
+fn test_net_pointer_offset_unbalanced_loop() {
+    let program: Program = ">[>]<".parse().unwrap();
+    assert_eq!(program.net_pointer_offset(), None);
+}
+
+#[test]
+fn test_net_pointer_offset_nested_unbalanced_loop() {
+    let program: Program = "+[-[<]>>]".parse().unwrap();
+    assert_eq!(program.net_pointer_offset(), None);
+}
+
+#[test]
+fn test_max_nesting_depth() {
+    let program: Program = "[][[]]+[[[-]>]]".parse().unwrap();
+    assert_eq!(program.max_nesting_depth(), 3);
+    let program: Program = "+-.".parse().unwrap();
+    assert_eq!(program.max_nesting_depth(), 0);
+}
+
+#[test]
+fn test_comments_round_trip() {
+    let code = "read a char ,\n[ loop: -> copy >+< ]\n print it >.\n";
+    let program = Program::from_reader_with_comments(code.as_bytes()).unwrap();
+    assert_eq!(program.to_source(), code);
+}
+
+#[test]
+fn test_comments_stripped_by_default() {
+    let code = "read ,[->+<] done";
+    let program: Program = code.parse().unwrap();
+    assert_eq!(program.to_source(), ",[->+<]");
+}
+
+#[test]
+fn test_comments_keep_utf8() {
+    let code = "zażółć +. gęślą";
+    let program = Program::from_reader_with_comments(code.as_bytes()).unwrap();
+    assert_eq!(program.to_source(), code);
+    assert_eq!(program.max_nesting_depth(), 0);
+}
+
+#[test]
+fn test_comments_keep_single_byte_encodings() {
+    // "café" and "€" in Windows-1252, neither of them valid UTF-8
+    let code = b"caf\xe9 +[-] \x80 5.";
+    let program = Program::from_reader_with_comments(code.as_slice()).unwrap();
+    assert_eq!(program.to_source_bytes(), code);
+    assert_eq!(program.to_source(), "caf\u{fffd} +[-] \u{fffd} 5.");
+    let stripped = Program::from_reader(code.as_slice()).unwrap();
+    assert_eq!(stripped.to_source_bytes(), b"+[-].");
+}
+
+#[test]
+fn test_static_output_of_hello_world() {
+    let code = fs::read_to_string("tests/programs/hello.b").unwrap();
+    let program: Program = code.parse().unwrap();
+    let (termination, expected) = run_program(code.as_bytes(), b"").unwrap();
+    assert_eq!(termination, Termination::Completed);
+    assert_eq!(program.static_output(), Some(expected));
+}
+
+#[test]
+fn test_static_output_past_initial_tape() {
+    let program: Program = "++++++++[>++++++++<-]>+.>>>>>>+.".parse().unwrap();
+    assert_eq!(program.static_output(), Some(vec![b'A', 1]));
+    let program: Program = "".parse().unwrap();
+    assert_eq!(program.static_output(), Some(Vec::new()));
+}
+
+#[test]
+fn test_static_output_of_input_dependent_program() {
+    let program: Program = "+.,.".parse().unwrap();
+    assert_eq!(program.static_output(), None);
+    let program: Program = "+[.,]".parse().unwrap();
+    assert_eq!(program.static_output(), None);
+}
+
+#[test]
+fn test_static_output_of_non_terminating_program() {
+    let program: Program = "+[]".parse().unwrap();
+    assert_eq!(program.static_output(), None);
+    let program: Program = "+[>+]".parse().unwrap();
+    assert_eq!(program.static_output(), None);
+    let program: Program = "+.<.".parse().unwrap();
+    assert_eq!(program.static_output(), None);
+}
+
+#[test]
+fn test_fingerprint() {
+    let program: Program = "+[->+<]>.".parse().unwrap();
+    assert_eq!(
+        program.fingerprint(),
+        "+[->+<]>.".parse::<Program>().unwrap().fingerprint()
+    );
+    for other in ["+[->+<]>,", "+[->+<].>", "+[->+<]>.."] {
+        let other: Program = other.parse().unwrap();
+        assert_ne!(program.fingerprint(), other.fingerprint());
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::Cursor;
+
+use crate::{Interpreter, RunOutcome, RunReport, Span, Termination};
+
+fn succeeding_run() -> RunReport {
+    let code = String::from(",[.>,]<");
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_input(Box::new(Cursor::new(b"abc")));
+    interpreter.run_full(Some(0))
+}
+
+fn failing_run() -> RunReport {
+    let code = String::from("+.<");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    interpreter.run_full(None)
+}
+
+#[test]
+fn test_run_full_completed() {
+    let report = succeeding_run();
+    assert_eq!(report.outcome, RunOutcome::Stopped(Termination::Completed));
+    assert_eq!(report.output, b"abc");
+    assert_eq!(&report.tape[..4], b"abc\0");
+    assert_eq!(report.pointer, 2);
+    assert!(report.stats.steps > 0);
+    assert!(report.stats.phases.optimize > std::time::Duration::ZERO);
+    assert!(report.optimization.unwrap().converged);
+}
+
+#[test]
+fn test_run_full_failed() {
+    let report = failing_run();
+    assert_eq!(
+        report.outcome,
+        RunOutcome::Failed {
+            message: "Error: moving the pointer left by 1 from cell 0 leaves the tape (at byte 2)."
+                .to_string(),
+            span: Some(Span { start: 2, end: 3 }),
+        }
+    );
+    // the output written before the error is kept
+    assert_eq!(report.output, [1]);
+    assert_eq!(report.tape[0], 1);
+    assert_eq!(report.optimization, None);
+}
+
+#[test]
+fn test_run_full_parse_error() {
+    let code = String::from("+]");
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 10);
+    let report = interpreter.run_full(None);
+    assert_eq!(
+        report.outcome,
+        RunOutcome::Failed {
+            message: "Error: ']' found with no matching '['.".to_string(),
+            span: None,
+        }
+    );
+    assert_eq!(report.stats, Default::default());
+}
+
+#[test]
+fn test_run_full_keeps_the_output_writer() {
+    let code = String::from("+.");
+    let output = crate::testing::CapturedOutput::default();
+    let mut interpreter =
+        Interpreter::from_reader(code.as_bytes(), 10).with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run_full(None).output, [1]);
+    assert_eq!(output.contents(), b"");
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), [2]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_run_report_json_round_trip() {
+    for report in [succeeding_run(), failing_run()] {
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<RunReport>(&json).unwrap(), report);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for field in [
+            "outcome",
+            "output",
+            "tape",
+            "pointer",
+            "stats",
+            "optimization",
+        ] {
+            assert!(value.get(field).is_some(), "{field} missing from {json}");
+        }
+        assert!(value["stats"].get("phases").is_some());
+    }
+}
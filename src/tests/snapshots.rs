@@ -0,0 +1,49 @@
+use crate::{Optimizer, Program, TerminationVerdict};
+
+use super::utils::assert_snapshot;
+
+// Renders the code before the optimization and after every pass that has
+// changed it, until the code stops changing.
+fn trace_passes(code: &str) -> String {
+    let program: Program = code.parse().unwrap();
+    let mut rendered = format!("code: {code}\n\n{program}");
+    let mut optimizer = Optimizer::new(program.statements);
+    optimizer.trace = Some(Vec::new());
+    let report = optimizer.optimize(0);
+    for (pass, statements) in optimizer.trace.unwrap() {
+        rendered += &format!("\nafter {pass:?}:\n{}", Program { statements });
+    }
+    rendered += &format!("\niterations: {}\n", report.iterations);
+    for warning in report.warnings {
+        rendered += &format!("warning: {warning}\n");
+    }
+    if report.termination != TerminationVerdict::Unknown {
+        rendered += &format!("termination: {:?}\n", report.termination);
+    }
+    rendered
+}
+
+#[test]
+fn test_snapshot_clear_loops() {
+    assert_snapshot("clear_loops", &trace_passes("+>++[-]>[+]<<[-]"));
+}
+
+#[test]
+fn test_snapshot_fused_clears() {
+    assert_snapshot("fused_clears", &trace_passes(",[[-]>[-]>>[-]<<<,]"));
+}
+
+#[test]
+fn test_snapshot_copy_loop() {
+    assert_snapshot("copy_loop", &trace_passes("+++[->+>++<<]>>."));
+}
+
+#[test]
+fn test_snapshot_offset_patterns() {
+    assert_snapshot("offset_patterns", &trace_passes(",>>+<<->+<<>>+-."));
+}
+
+#[test]
+fn test_snapshot_dead_stores() {
+    assert_snapshot("dead_stores", &trace_passes("[.]+++[-]---[++]>[,]<."));
+}
@@ -0,0 +1,157 @@
+// Pathological program shapes, generated at runtime. Each stage runs on a
+// thread of its own with the default stack size and a time limit, so that a
+// hang fails the test instead of blocking it, and the runs are bounded by
+// step limits and timeouts.
+use std::mem::size_of;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    optimize_program, Interpreter, OptimizerConfig, ParseError, Program, Statement, Termination,
+    MAX_NESTING_DEPTH,
+};
+
+use super::utils::peak_memory;
+
+const TIME_LIMIT: Duration = Duration::from_secs(20);
+const STEP_LIMIT: u64 = 10_000_000;
+
+// Runs a function on a new thread, failing if it doesn't return in time.
+fn within_time_limit<R: Send + 'static>(function: impl FnOnce() -> R + Send + 'static) -> R {
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        let _ = sender.send(function());
+    });
+    match receiver.recv_timeout(TIME_LIMIT) {
+        Ok(result) => result,
+        // the worker has panicked, its message is passed on
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            std::panic::resume_unwind(worker.join().unwrap_err())
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("no result after {TIME_LIMIT:?}"),
+    }
+}
+
+// Runs the code unoptimized and fully optimized, returning the termination
+// and the amount of steps of each run.
+fn run_both_ways(code: &[u8]) -> [(Termination, u64); 2] {
+    [false, true].map(|optimize| {
+        let mut interpreter = Interpreter::from_reader(code, 10)
+            .with_input(Box::new(std::io::empty()))
+            .with_output(Box::new(std::io::sink()))
+            .with_step_limit(STEP_LIMIT)
+            .with_timeout(TIME_LIMIT / 2);
+        let termination = match optimize {
+            false => interpreter.run(),
+            true => interpreter.run_with_optimization(0),
+        };
+        (termination.unwrap(), interpreter.stats().steps)
+    })
+}
+
+#[test]
+fn test_deep_nesting_rejected() {
+    let depth = 1_000_000;
+    let code = "[".repeat(depth) + &"]".repeat(depth);
+    let (error, memory) = within_time_limit(move || {
+        peak_memory(|| Program::from_reader(code.as_bytes()).unwrap_err())
+    });
+    let error = error.get_ref().unwrap().downcast_ref::<ParseError>();
+    assert_eq!(
+        error,
+        Some(&ParseError::NestingTooDeep {
+            offset: MAX_NESTING_DEPTH + 1
+        })
+    );
+    // the parser stops at the first loop nested too deep
+    assert!(memory < 1 << 20, "{memory} bytes held");
+}
+
+#[test]
+fn test_nesting_at_limit() {
+    let code =
+        "+".to_string() + &"[".repeat(MAX_NESTING_DEPTH) + "-" + &"]".repeat(MAX_NESTING_DEPTH);
+    let runs = within_time_limit(move || {
+        let program = Program::from_reader(code.as_bytes()).unwrap();
+        assert_eq!(program.max_nesting_depth(), MAX_NESTING_DEPTH);
+        assert!(program.to_string().lines().count() > 2 * MAX_NESTING_DEPTH);
+        run_both_ways(code.as_bytes())
+    });
+    // each loop is entered once and the innermost one clears the cell
+    assert_eq!(
+        runs[0],
+        (Termination::Completed, 2 * MAX_NESTING_DEPTH as u64 + 2)
+    );
+    assert_eq!(runs[1].0, Termination::Completed);
+}
+
+#[test]
+fn test_long_run_of_additions() {
+    let code = "+".repeat(1_000_000);
+    let (program, memory) = within_time_limit(move || {
+        let (program, memory) = peak_memory(|| Program::from_reader(code.as_bytes()).unwrap());
+        let (optimized, _) = optimize_program(program, OptimizerConfig::default());
+        let runs = run_both_ways(code.as_bytes());
+        assert_eq!(
+            runs,
+            [
+                (Termination::Completed, 1_000_000),
+                (Termination::Completed, 1)
+            ]
+        );
+        (optimized, memory)
+    });
+    assert_eq!(program.to_string(), "add +64\n");
+    // the parsed statements and the offsets of their tokens, with room for
+    // the growth of their buffers
+    let size = 1_000_000 * (size_of::<Statement>() + size_of::<usize>());
+    assert!(memory < 2 * size, "{memory} bytes held");
+}
+
+#[test]
+fn test_alternating_additions() {
+    let code = "+-".repeat(500_000);
+    let (program, runs) = within_time_limit(move || {
+        let program = Program::from_reader(code.as_bytes()).unwrap();
+        let (optimized, _) = optimize_program(program, OptimizerConfig::default());
+        (optimized, run_both_ways(code.as_bytes()))
+    });
+    assert_eq!(program.to_string(), "");
+    assert_eq!(
+        runs,
+        [
+            (Termination::Completed, 1_000_000),
+            (Termination::Completed, 0)
+        ]
+    );
+}
+
+#[test]
+fn test_comment_only_file() {
+    let code = b"no commands in here\n".repeat(500_000);
+    assert_eq!(code.len(), 10_000_000);
+    let ((program, runs), memory) = within_time_limit(move || {
+        peak_memory(|| {
+            let program = Program::from_reader(code.as_slice()).unwrap();
+            (program, run_both_ways(&code))
+        })
+    });
+    assert_eq!(program.to_string(), "");
+    assert_eq!(runs, [(Termination::Completed, 0); 2]);
+    // the comments aren't kept
+    assert!(memory < 1 << 16, "{memory} bytes held");
+}
+
+#[test]
+fn test_program_optimized_to_nothing() {
+    // loops that are never entered, as the cell is known to be zero, and
+    // additions cancelling out
+    let code = "[,.]+-[>]-+";
+    let program = Program::from_reader(code.as_bytes()).unwrap();
+    let (optimized, _) = optimize_program(program, OptimizerConfig::default());
+    assert_eq!(optimized.to_string(), "");
+    let runs = within_time_limit(move || run_both_ways(code.as_bytes()));
+    assert_eq!(runs[0].0, Termination::Completed);
+    assert_eq!(runs[1], (Termination::Completed, 0));
+}
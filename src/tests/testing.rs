@@ -0,0 +1,44 @@
+use std::io::Read;
+
+use crate::testing::{run_program, ScriptedInput};
+use crate::Termination;
+
+#[test]
+fn test_scripted_input_ends() {
+    let mut input = ScriptedInput::new("abc");
+    let mut buffer = [0; 2];
+    assert_eq!(input.read(&mut buffer).unwrap(), 2);
+    assert_eq!(&buffer, b"ab");
+    let mut rest = Vec::new();
+    input.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"c");
+    assert_eq!(input.read(&mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn test_run_program_is_limited() {
+    let (termination, output) = run_program(b".+[]", b"").unwrap();
+    assert_eq!(termination, Termination::StepLimit);
+    assert_eq!(output, b"\0");
+}
+
+#[test]
+fn test_assert_bf_output() {
+    crate::assert_bf_output!(",[.,]", "cat", "cat");
+    crate::assert_bf_output!(b",+.", [0xff], [0]);
+    crate::assert_bf_output!(String::from(",,."), vec![1, 2], "\x02");
+}
+
+#[test]
+#[should_panic(expected = "the outputs differ at byte 2\n\
+                           expected: \"cap\" (3 bytes)\n  \
+                           actual: \"cat\" (3 bytes)")]
+fn test_assert_bf_output_shows_difference() {
+    crate::assert_bf_output!(",[.,]", "cat", "cap");
+}
+
+#[test]
+#[should_panic(expected = "the program has stopped early (StepLimit)")]
+fn test_assert_bf_output_stops_infinite_loop() {
+    crate::assert_bf_output!("+[]", "", "");
+}
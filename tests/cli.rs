@@ -0,0 +1,623 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::thread;
+
+use assert_cmd::Command;
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use predicates::prelude::*;
+
+fn write_program(name: &str, code: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, code).unwrap();
+    path
+}
+
+fn hello_program() -> String {
+    // "Hello" without a trailing newline
+    let mut code = String::new();
+    let mut previous = 0u8;
+    for byte in "Hello".bytes() {
+        code.push_str(&"+".repeat((byte - previous) as usize));
+        code.push('.');
+        previous = byte;
+    }
+    code
+}
+
+#[test]
+fn test_output_without_newline_is_flushed() {
+    let path = write_program("flush_hello.b", &hello_program());
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("Hello");
+}
+
+#[test]
+fn test_output_is_flushed_before_failure() {
+    // prints the greeting and then moves out of the tape
+    let code = format!("{}<", hello_program());
+    let path = write_program("flush_hello_fail.b", &code);
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&path)
+        .assert()
+        .failure()
+        .stdout("Hello");
+}
+
+fn programs_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+}
+
+#[test]
+fn test_hello_world_from_file() {
+    let expected = fs::read_to_string(programs_file("hello.out")).unwrap();
+    for flags in [
+        &[][..],
+        &["-O", "0"],
+        &["-O", "1"],
+        &["-O", "0", "--no-optimize-io"],
+        &["--lazy-tape"],
+        &["--lazy-tape", "-O", "0"],
+    ] {
+        Command::cargo_bin("binter")
+            .unwrap()
+            .arg(programs_file("hello.b"))
+            .args(flags)
+            .assert()
+            .success()
+            .stdout(expected.clone())
+            .stderr("");
+    }
+}
+
+#[test]
+fn test_missing_file() {
+    let directory = tempfile::tempdir().unwrap();
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(directory.path().join("missing.b"))
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(
+            predicate::str::contains("Data cannot be read from")
+                .and(predicate::str::contains("missing.b")),
+        );
+}
+
+#[test]
+fn test_parse_error() {
+    let path = write_program("parse_error.b", "+[>+<-]]");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&path)
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("Error: ']' found with no matching '['.\n");
+}
+
+#[test]
+fn test_conflicting_flags() {
+    // rejected before anything is run or written
+    let path = write_program("conflicting_flags.b", ".");
+    let directory = tempfile::tempdir().unwrap();
+    let output = directory.path().join("tape.txt");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&path)
+        .args(["--binary", "--hex", "--output"])
+        .arg(&output)
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "the argument '--binary' cannot be used with '--hex'",
+        ));
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_code_from_stdin_with_inline_input() {
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg("-")
+        .write_stdin(",.,.!hi")
+        .assert()
+        .success()
+        .stdout("hi");
+}
+
+fn tape_output(name: &str, flags: &[&str]) -> Vec<u8> {
+    let program = write_program(&format!("{name}.b"), "+++>++++++++++>>-");
+    let directory = tempfile::tempdir().unwrap();
+    let output = directory.path().join("tape.txt");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--size", "4", "--output"])
+        .arg(&output)
+        .args(flags)
+        .assert()
+        .success();
+    fs::read(output).unwrap()
+}
+
+#[test]
+fn test_tape_output_decimal() {
+    let expected = include_str!("golden/tape_decimal.txt");
+    assert_eq!(tape_output("tape_decimal", &[]), expected.as_bytes());
+}
+
+#[test]
+fn test_tape_output_of_lazy_tape() {
+    let expected = include_str!("golden/tape_decimal.txt");
+    assert_eq!(
+        tape_output("tape_lazy", &["--lazy-tape"]),
+        expected.as_bytes()
+    );
+}
+
+#[test]
+fn test_lazy_tape_size_is_a_cap() {
+    let program = write_program("lazy_cap.b", ">>>>+");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--lazy-tape", "--size", "4"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("from cell 3 leaves the tape"));
+}
+
+#[test]
+fn test_tape_output_hex() {
+    let expected = include_str!("golden/tape_hex.txt");
+    assert_eq!(tape_output("tape_hex", &["--hex"]), expected.as_bytes());
+}
+
+#[test]
+fn test_tape_output_hex_legacy() {
+    let expected = include_str!("golden/tape_hex_legacy.txt");
+    assert_eq!(
+        tape_output("tape_hex_legacy", &["--hex", "--legacy-format"]),
+        expected.as_bytes()
+    );
+}
+
+#[test]
+fn test_tape_output_decimal_legacy() {
+    let expected = include_str!("golden/tape_decimal_legacy.txt");
+    assert_eq!(
+        tape_output("tape_decimal_legacy", &["--legacy-format"]),
+        expected.as_bytes()
+    );
+}
+
+#[test]
+fn test_tape_output_binary() {
+    assert_eq!(tape_output("tape_binary", &["--binary"]), [3, 10, 0, 255]);
+}
+
+fn grid_output(size: usize, width: usize) -> String {
+    // sets the cells 0, 2, 4 and 5
+    let program = write_program("grid.b", "+>>+>>+>+");
+    let directory = tempfile::tempdir().unwrap();
+    let output = directory.path().join("grid.txt");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--size", &size.to_string(), "--grid", &width.to_string()])
+        .arg("--output")
+        .arg(&output)
+        .assert()
+        .success();
+    fs::read_to_string(output).unwrap()
+}
+
+#[test]
+fn test_tape_output_grid() {
+    assert_eq!(grid_output(6, 3), "#.#\n.##\n");
+    assert_eq!(grid_output(8, 3), "#.#\n.##\n..\n");
+    assert_eq!(grid_output(6, 10), "#.#.##\n");
+}
+
+#[test]
+fn test_tape_output_grid_zero_width() {
+    let program = write_program("grid_zero_width.b", "+");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--grid", "0", "--output", "unused.txt"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_type_ahead_survives_on_terminal() {
+    let program = write_program("type_ahead.b", ",+.,+.,+.");
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let mut command = CommandBuilder::new(env!("CARGO_BIN_EXE_binter"));
+    command.arg(&program);
+    let mut child = pair.slave.spawn_command(command).unwrap();
+    drop(pair.slave);
+    let mut reader = pair.master.try_clone_reader().unwrap();
+    let collected = thread::spawn(move || {
+        let mut collected = Vec::new();
+        let mut buffer = [0; 64];
+        // reading fails once the program exits and the terminal is closed
+        while let Ok(amount @ 1..) = reader.read(&mut buffer) {
+            collected.extend_from_slice(&buffer[..amount]);
+        }
+        collected
+    });
+    // all of the keys are typed before the program gets to read any of them
+    let mut writer = pair.master.take_writer().unwrap();
+    writer.write_all(b"abc").unwrap();
+    writer.flush().unwrap();
+    assert!(child.wait().unwrap().success());
+    drop(pair.master);
+    let collected = String::from_utf8(collected.join().unwrap()).unwrap();
+    assert!(collected.ends_with("bcd"), "{collected:?}");
+}
+
+#[test]
+fn test_trace_tape_frames() {
+    let program = write_program("trace_tape.b", "++>+++>+");
+    let trace = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("trace_tape.txt");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--size", "4", "--trace-tape"])
+        .arg(&trace)
+        .args(["--trace-interval", "3", "--trace-window", "3"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(trace).unwrap(),
+        "frame 1: step 3, pointer 0, cells 0..3\n\
+         2,0,0\n\
+         frame 2: step 6, pointer 1, cells 0..3\n\
+         2,2,0\n"
+    );
+}
+
+#[test]
+fn test_trace_tape_keeps_output_exact() {
+    let expected = fs::read_to_string(programs_file("hello.out")).unwrap();
+    let trace = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("trace_hello.txt");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(programs_file("hello.b"))
+        .args(["-O", "0", "--trace-tape"])
+        .arg(&trace)
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("");
+    assert!(fs::read_to_string(trace).unwrap().starts_with("frame 1:"));
+}
+
+#[test]
+fn test_optimization_warnings_kept_off_output() {
+    // the loop never terminates, but the run fails before reaching it
+    let program = write_program("warning.b", ".<+[++]");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["-O", "0"])
+        .assert()
+        .failure()
+        .stdout("\0")
+        .stderr(predicate::str::starts_with(
+            "Warning: The code contains a loop that never terminates.\n",
+        ));
+}
+
+#[test]
+fn test_quiet_keeps_standard_error_empty() {
+    let program = write_program("quiet_warning.b", ".<+[++]");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["-O", "0", "--quiet"])
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("\0")
+        .stderr("");
+}
+
+#[test]
+fn test_quiet_verify_mismatch() {
+    let program = write_program("quiet_verify.b", "+.");
+    let expected = write_program("quiet_verify.out", "\u{2}");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .arg("--verify")
+        .arg(&expected)
+        .arg("-q")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("");
+}
+
+#[test]
+fn test_diagnostics_json() {
+    // an unmatched ']', a loop moving the pointer right, an unexpected word
+    // and an unmatched '['
+    let program = write_program("diagnostics.b", "+]\n[->>+<] x\n[\n");
+    let output = Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--diagnostics-json", "--strict"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "[{\"severity\":\"error\",\"message\":\"']' found with no matching '['.\",\
+         \"line\":1,\"column\":2,\"span\":{\"start\":1,\"end\":2}},\
+         {\"severity\":\"warning\",\"message\":\"The loop moves the pointer by 1 on every iteration.\",\
+         \"line\":2,\"column\":1,\"span\":{\"start\":3,\"end\":10}},\
+         {\"severity\":\"error\",\"message\":\"Unexpected text \\\"x\\\".\",\
+         \"line\":2,\"column\":9,\"span\":{\"start\":11,\"end\":12}},\
+         {\"severity\":\"error\",\"message\":\"'[' found with no matching ']'.\",\
+         \"line\":3,\"column\":1,\"span\":{\"start\":13,\"end\":14}}]\n"
+    );
+}
+
+fn verify(name: &str, expected: &str) -> assert_cmd::assert::Assert {
+    let program = write_program(&format!("{name}.b"), ",[.[-],]");
+    let input = write_program(&format!("{name}.in"), "hello\nworld\n");
+    let expected_path = write_program(&format!("{name}.out"), expected);
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .arg("--input")
+        .arg(&input)
+        .arg("--verify")
+        .arg(&expected_path)
+        .assert()
+}
+
+#[test]
+fn test_verify_matching_output() {
+    verify("verify_match", "hello\nworld\n")
+        .success()
+        .stdout("")
+        .stderr("");
+}
+
+#[test]
+fn test_verify_mismatching_output() {
+    let output = verify("verify_mismatch", "hello\nwordl\n")
+        .failure()
+        .code(1)
+        .stdout("")
+        .get_output()
+        .clone();
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        report.ends_with(
+            "verify_mismatch.out: the outputs differ at byte 9 (line 2), \
+             expected \"ello\\nwordl\\n\", got \"ello\\nworld\\n\" \
+             (12 bytes expected, 12 bytes output).\n"
+        ),
+        "{report}"
+    );
+}
+
+#[test]
+fn test_verify_truncated_output() {
+    let output = verify("verify_truncated", "hello\nworld\n!")
+        .failure()
+        .get_output()
+        .clone();
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.contains("differ at byte 12 (line 3)"), "{report}");
+    assert!(report.contains("(13 bytes expected, 12 bytes output)"));
+}
+
+fn run_random(name: &str, args: &[&str]) -> Vec<u8> {
+    let program = write_program(name, "?.?.?.?.?.?.?.?.");
+    let output = Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    output.stdout
+}
+
+#[test]
+fn test_random_command_seeded() {
+    let first = run_random("random_seeded", &["--enable-rand", "--seed", "5"]);
+    assert_eq!(first.len(), 8);
+    assert_eq!(
+        run_random("random_seeded", &["--enable-rand", "--seed", "5"]),
+        first
+    );
+    assert_ne!(
+        run_random("random_seeded", &["--enable-rand", "--seed", "6"]),
+        first
+    );
+}
+
+#[test]
+fn test_random_command_disabled() {
+    // every "?" is a comment, so only the zeros of the "." are output
+    assert_eq!(run_random("random_disabled", &[]), [0; 8]);
+}
+
+#[test]
+fn test_seed_requires_random_command() {
+    let program = write_program("random_no_flag", "?.");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--seed", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--enable-rand"));
+}
+
+#[test]
+fn test_batch_mode() {
+    let directory = tempfile::tempdir().unwrap();
+    let inputs = directory.path().join("inputs");
+    let outputs = directory.path().join("outputs");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(programs_file("rot13.in"), inputs.join("rot13.txt")).unwrap();
+    fs::write(inputs.join("short.txt"), "Hello").unwrap();
+    fs::write(inputs.join("empty.txt"), "").unwrap();
+    for flags in [&[][..], &["-O", "0"]] {
+        Command::cargo_bin("binter")
+            .unwrap()
+            .arg(programs_file("rot13.b"))
+            .arg("--batch-inputs")
+            .arg(&inputs)
+            .arg("--batch-outputs")
+            .arg(&outputs)
+            .args(flags)
+            .assert()
+            .success()
+            .stdout("")
+            .stderr("");
+        assert_eq!(
+            fs::read(outputs.join("rot13.txt")).unwrap(),
+            fs::read(programs_file("rot13.out")).unwrap()
+        );
+        assert_eq!(fs::read(outputs.join("short.txt")).unwrap(), b"Uryyb");
+        assert_eq!(fs::read(outputs.join("empty.txt")).unwrap(), b"");
+    }
+}
+
+#[test]
+fn test_batch_mode_clears_tape_between_runs() {
+    let program = write_program("batch_counter.b", ">+.<,.");
+    let directory = tempfile::tempdir().unwrap();
+    let inputs = directory.path().join("inputs");
+    fs::create_dir(&inputs).unwrap();
+    fs::write(inputs.join("a"), "a").unwrap();
+    fs::write(inputs.join("b"), "bb").unwrap();
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .arg("--batch-inputs")
+        .arg(&inputs)
+        .arg("--batch-outputs")
+        .arg(directory.path().join("outputs"))
+        .assert()
+        .success();
+    let outputs = directory.path().join("outputs");
+    assert_eq!(fs::read(outputs.join("a")).unwrap(), b"\x01a");
+    assert_eq!(fs::read(outputs.join("b")).unwrap(), b"\x01b");
+}
+
+#[test]
+fn test_two_sided_tape() {
+    let program = write_program("two_sided.b", "<+.<++.>>>+++.");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--two-sided", "--size", "2"])
+        .assert()
+        .success()
+        .stdout("\u{1}\u{2}\u{3}");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--size", "2"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_access_counts_csv() {
+    let program = write_program("access_counts.b", "++[>+<-]");
+    let directory = tempfile::tempdir().unwrap();
+    let csv = directory.path().join("counts.csv");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--size", "3", "--access-counts"])
+        .arg(&csv)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(csv).unwrap(),
+        "cell,accesses\n0,11\n1,4\n2,0\n"
+    );
+}
+
+#[test]
+fn test_emit_c() {
+    let program = write_program("emit_c.b", "++[>+<-]>.");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--emit", "c"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("    while (*p) {\n"))
+        .stdout(predicate::str::contains("    putchar(*p);\n"))
+        .stdout(predicate::str::contains(
+            "static unsigned char tape[30000];",
+        ))
+        .stdout(predicate::str::contains("    *p += 1;\n"))
+        .stdout(predicate::str::contains("*p += 2;").not());
+    // optimized first if "--optimize" is set
+    let directory = tempfile::tempdir().unwrap();
+    let output = directory.path().join("emit.c");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--emit", "c", "-O", "0", "--size", "8", "--output"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout("");
+    let c = fs::read_to_string(output).unwrap();
+    assert!(c.contains("static unsigned char tape[8];"));
+    assert!(c.contains("    *p += 2;\n"));
+}
+
+#[test]
+fn test_emit_optimized_with_count() {
+    // a single iteration of the passes folds the additions and finds the
+    // loop that never terminates
+    let program = write_program("emit_count.b", "+-+[++]");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--emit", "c", "-O", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("    *p += 1;\n"))
+        .stderr("Warning: The code contains a loop that never terminates.\n");
+}
+
+#[test]
+fn test_emit_asm() {
+    let program = write_program("emit_asm.b", "+.");
+    Command::cargo_bin("binter")
+        .unwrap()
+        .arg(&program)
+        .args(["--emit", "asm"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("_start:"));
+}
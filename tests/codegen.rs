@@ -0,0 +1,95 @@
+// Assembles, links and runs the x86-64 code generated for the programs of
+// `tests/programs`, comparing the outputs with the golden files. Needs the
+// GNU assembler and linker, run it with
+//
+//     cargo test --test codegen -- --ignored
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use binter::{codegen::to_asm_x86_64, optimize_program, OptimizerConfig, Program};
+
+fn program_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+}
+
+fn build(asm: &str, executable: &Path) {
+    let source = executable.with_extension("s");
+    let object = executable.with_extension("o");
+    fs::write(&source, asm).unwrap();
+    let assembled = Command::new("as")
+        .arg(&source)
+        .arg("-o")
+        .arg(&object)
+        .status()
+        .unwrap();
+    assert!(assembled.success(), "{}: as has failed", source.display());
+    let linked = Command::new("ld")
+        .arg(&object)
+        .arg("-o")
+        .arg(executable)
+        .status()
+        .unwrap();
+    assert!(linked.success(), "{}: ld has failed", object.display());
+}
+
+fn run(executable: &Path, input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    output.stdout
+}
+
+// Runs the code generated for a program, without and with full
+// optimization, comparing both outputs with the golden file.
+fn test_program(name: &str) {
+    let code = fs::read(program_file(&format!("{name}.b"))).unwrap();
+    let input = fs::read(program_file(&format!("{name}.in"))).unwrap_or_default();
+    let expected = fs::read(program_file(&format!("{name}.out"))).unwrap();
+    let program = Program::from_reader(code.as_slice()).unwrap();
+    let (optimized, _) = optimize_program(program.clone(), OptimizerConfig::default());
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codegen");
+    fs::create_dir_all(&directory).unwrap();
+    for (suffix, program) in [("", program), ("_optimized", optimized)] {
+        let executable = directory.join(format!("{name}{suffix}"));
+        build(&to_asm_x86_64(&program, 30000), &executable);
+        assert_eq!(
+            run(&executable, &input),
+            expected,
+            "{name}{suffix}: output differs from the golden file"
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs the GNU assembler and linker"]
+fn test_native_hello() {
+    test_program("hello");
+}
+
+#[test]
+#[ignore = "needs the GNU assembler and linker"]
+fn test_native_rot13() {
+    test_program("rot13");
+}
+
+#[test]
+#[ignore = "needs the GNU assembler and linker"]
+fn test_native_counter() {
+    test_program("counter");
+}
+
+#[test]
+#[ignore = "needs the GNU assembler and linker"]
+fn test_native_sierpinski() {
+    test_program("sierpinski");
+}
@@ -0,0 +1,87 @@
+// Compiles and runs the C code generated for the programs of
+// `tests/programs`, comparing the outputs with the golden files. Needs a C
+// compiler available as `cc`, run it with
+//
+//     cargo test --test codegen_c -- --ignored
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use binter::{codegen::to_c, optimize_program, OptimizerConfig, Program};
+
+fn program_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+}
+
+fn build(c: &str, executable: &Path) {
+    let source = executable.with_extension("c");
+    fs::write(&source, c).unwrap();
+    let compiled = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Werror", "-O2"])
+        .arg(&source)
+        .arg("-o")
+        .arg(executable)
+        .status()
+        .unwrap();
+    assert!(compiled.success(), "{}: cc has failed", source.display());
+}
+
+fn run(executable: &Path, input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    output.stdout
+}
+
+// Runs the code generated for a program, without and with full
+// optimization, comparing both outputs with the golden file.
+fn test_program(name: &str) {
+    let code = fs::read(program_file(&format!("{name}.b"))).unwrap();
+    let input = fs::read(program_file(&format!("{name}.in"))).unwrap_or_default();
+    let expected = fs::read(program_file(&format!("{name}.out"))).unwrap();
+    let program = Program::from_reader(code.as_slice()).unwrap();
+    let (optimized, _) = optimize_program(program.clone(), OptimizerConfig::default());
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codegen_c");
+    fs::create_dir_all(&directory).unwrap();
+    for (suffix, program) in [("", program), ("_optimized", optimized)] {
+        let executable = directory.join(format!("{name}{suffix}"));
+        build(&to_c(&program, 30000), &executable);
+        assert_eq!(
+            run(&executable, &input),
+            expected,
+            "{name}{suffix}: output differs from the golden file"
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs a C compiler"]
+fn test_c_hello() {
+    test_program("hello");
+}
+
+#[test]
+#[ignore = "needs a C compiler"]
+fn test_c_rot13() {
+    test_program("rot13");
+}
+
+#[test]
+#[ignore = "needs a C compiler"]
+fn test_c_counter() {
+    test_program("counter");
+}
+
+#[test]
+#[ignore = "needs a C compiler"]
+fn test_c_sierpinski() {
+    test_program("sierpinski");
+}
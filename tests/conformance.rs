@@ -0,0 +1,112 @@
+// Runs tiny probe programs under every combination of the semantics
+// settings and compares the results with the table in
+// `corpus/conformance.txt`. A new variant of one of the settings enums
+// fails to compile in `*_name` below, and once named, fails the test until
+// the table has a row for every probe with it.
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use binter::testing::CapturedOutput;
+use binter::{EofPolicy, Interpreter, LoopSemantics, Termination};
+
+const TAPE_SIZE: usize = 4;
+const STEP_LIMIT: u64 = 10_000;
+const OUTPUT_LIMIT: u64 = 8;
+
+// The probes, each with its input.
+const PROBES: [(&str, &str); 10] = [
+    (",.", ""),
+    ("+,.", ""),
+    (",.,.", "a"),
+    ("-.", ""),
+    ("-+.", ""),
+    ("[.]", ""),
+    ("<+.", ""),
+    ("+.[>+]", ""),
+    (",[.,]", "ab"),
+    ("+[]", ""),
+];
+
+const EOF_POLICIES: [EofPolicy; 3] = [EofPolicy::Unchanged, EofPolicy::Zero, EofPolicy::Max];
+const LOOP_SEMANTICS: [LoopSemantics; 2] = [LoopSemantics::While, LoopSemantics::DoWhile];
+
+fn eof_name(policy: EofPolicy) -> &'static str {
+    match policy {
+        EofPolicy::Unchanged => "unchanged",
+        EofPolicy::Zero => "zero",
+        EofPolicy::Max => "max",
+    }
+}
+
+fn loops_name(semantics: LoopSemantics) -> &'static str {
+    match semantics {
+        LoopSemantics::While => "while",
+        LoopSemantics::DoWhile => "do-while",
+    }
+}
+
+// The result of a run as written in the table: the escaped output, followed
+// by the kind of the error or the reason why the run was stopped, if any.
+fn run(code: &str, input: &str, eof_policy: EofPolicy, loop_semantics: LoopSemantics) -> String {
+    let output = CapturedOutput::default();
+    let result = Interpreter::from_reader(code.as_bytes(), TAPE_SIZE)
+        .with_input(Box::new(Cursor::new(input.as_bytes().to_vec())))
+        .with_output(Box::new(output.clone()))
+        .with_eof_policy(eof_policy)
+        .with_loop_semantics(loop_semantics)
+        .with_step_limit(STEP_LIMIT)
+        .with_output_limit(OUTPUT_LIMIT)
+        .run();
+    let output = output.take().escape_ascii().to_string();
+    let output = if output.is_empty() {
+        "-".into()
+    } else {
+        output
+    };
+    match result {
+        Ok(Termination::Completed) => output,
+        Ok(termination) => format!("{output} {termination:?}"),
+        Err(error) => format!("{output} error:{:?}", error.kind()),
+    }
+}
+
+// Reads the table, keyed by the probe code and the names of the settings.
+fn expected_results() -> HashMap<(String, String, String), String> {
+    include_str!("corpus/conformance.txt")
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut columns = line.split_whitespace();
+            let mut column = || columns.next().unwrap().to_string();
+            let key = (column(), column(), column());
+            let result: Vec<&str> = columns.collect();
+            (key, result.join(" "))
+        })
+        .collect()
+}
+
+#[test]
+fn test_conformance_matrix() {
+    let mut expected = expected_results();
+    let mut failures = Vec::new();
+    for (code, input) in PROBES {
+        for eof_policy in EOF_POLICIES {
+            for loop_semantics in LOOP_SEMANTICS {
+                let (eof, loops) = (eof_name(eof_policy), loops_name(loop_semantics));
+                let actual = run(code, input, eof_policy, loop_semantics);
+                let key = (code.to_string(), eof.to_string(), loops.to_string());
+                match expected.remove(&key) {
+                    Some(result) if result == actual => {}
+                    Some(result) => failures.push(format!(
+                        "{code} {eof} {loops}: expected {result}, got {actual}"
+                    )),
+                    None => failures.push(format!("{code} {eof} {loops}: missing, got {actual}")),
+                }
+            }
+        }
+    }
+    for (code, eof, loops) in expected.into_keys() {
+        failures.push(format!("{code} {eof} {loops}: no such probe or setting"));
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
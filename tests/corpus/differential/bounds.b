@@ -0,0 +1,2 @@
+# prints a byte and then moves left of the first cell
++.<
@@ -0,0 +1,2 @@
+# echoes the first four bytes of the input
+,.,.,.,.
@@ -0,0 +1,3 @@
+# reads past the end of the input and prints the cell: 1 if it is left
+# unchanged or 0 or 255 depending on the EOF mode
++,.
@@ -0,0 +1,2 @@
+# prints "hi" followed by a newline
+++++++++++[>++++++++++>+<<-]>++++.+.>.
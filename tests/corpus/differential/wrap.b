@@ -0,0 +1,2 @@
+# wraps the cell around in both directions
+-.+.
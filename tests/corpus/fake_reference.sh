@@ -0,0 +1,12 @@
+#!/bin/sh
+# A reference interpreter that disagrees on purpose, for testing the
+# differential harness: runs the interpreter given as the first argument
+# and uppercases its output, keeping its exit status.
+interpreter=$1
+shift
+output=$(mktemp)
+"$interpreter" "$@" > "$output"
+status=$?
+tr a-z A-Z < "$output"
+rm -f "$output"
+exit $status
@@ -0,0 +1,242 @@
+// Differential testing against an external reference interpreter. Every
+// program of a corpus directory (`NAME.b`, with an optional input in
+// `NAME.in`) is run by this crate and by the reference, and the outputs are
+// compared. Run against a reference with e.g.
+//
+//     BF_REFERENCE="bf --eof=unchanged" BF_EOF=unchanged \
+//         cargo test --test differential -- --ignored
+//
+// The reference is given the path of the program as its last argument and
+// the input on its standard input. Its semantics flags have to match the
+// ones set here through `BF_EOF` (`unchanged`, `zero` or `max`) and
+// `BF_TAPE_SIZE`; the cells of this crate always wrap around at 8 bits.
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use binter::testing::CapturedOutput;
+use binter::{EofPolicy, Interpreter, Termination};
+
+const STEP_LIMIT: u64 = 10_000_000;
+const REFERENCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The semantics that both interpreters are expected to share.
+struct Semantics {
+    eof_policy: EofPolicy,
+    tape_size: usize,
+}
+
+// The reference interpreter, run as `program args... PROGRAM_PATH`.
+struct Reference {
+    program: String,
+    args: Vec<String>,
+}
+
+// What can be compared between the runs: the output and whether the program
+// has ended successfully.
+#[derive(PartialEq, Debug)]
+struct Outcome {
+    output: Vec<u8>,
+    success: bool,
+}
+
+fn run_own(code: &[u8], input: &[u8], semantics: &Semantics) -> Outcome {
+    let output = CapturedOutput::default();
+    let result = Interpreter::from_reader(code, semantics.tape_size)
+        .with_input(Box::new(Cursor::new(input.to_vec())))
+        .with_output(Box::new(output.clone()))
+        .with_eof_policy(semantics.eof_policy)
+        .with_step_limit(STEP_LIMIT)
+        .run();
+    Outcome {
+        output: output.take(),
+        success: matches!(result, Ok(Termination::Completed)),
+    }
+}
+
+fn run_reference(reference: &Reference, program: &Path, input: &[u8]) -> Outcome {
+    let mut child = Command::new(&reference.program)
+        .args(&reference.args)
+        .arg(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|error| panic!("{}: {error}", reference.program));
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // written from another thread, so that a reference that doesn't read
+    // its whole input can't block the harness
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let deadline = Instant::now() + REFERENCE_TIMEOUT;
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    Outcome {
+        output: output.stdout,
+        success: output.status.success(),
+    }
+}
+
+// Keeps only the commands of the code, dropping the comments.
+fn strip_comments(code: &[u8]) -> Vec<u8> {
+    code.iter()
+        .copied()
+        .filter(|byte| b"+-<>[].,".contains(byte))
+        .collect()
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// Writes the smallest repro found for a mismatch, i.e. the code without
+// comments and the shortest prefix of the input that still gives different
+// outcomes, and returns the command line running the reference on it.
+fn minimize(
+    name: &str,
+    code: &[u8],
+    input: &[u8],
+    semantics: &Semantics,
+    reference: &Reference,
+) -> String {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("differential");
+    fs::create_dir_all(&directory).unwrap();
+    let program = directory.join(format!("{name}.b"));
+    let mismatches = |code: &[u8], input: &[u8]| {
+        fs::write(&program, code).unwrap();
+        run_own(code, input, semantics) != run_reference(reference, &program, input)
+    };
+    let stripped = strip_comments(code);
+    let code = match mismatches(&stripped, input) {
+        true => stripped,
+        false => code.to_vec(),
+    };
+    let length = (0..input.len())
+        .find(|&length| mismatches(&code, &input[..length]))
+        .unwrap_or(input.len());
+    fs::write(&program, &code).unwrap();
+    let command: Vec<String> = [&reference.program]
+        .into_iter()
+        .chain(&reference.args)
+        .map(|arg| shell_quote(arg))
+        .chain([shell_quote(&program.to_string_lossy())])
+        .collect();
+    format!(
+        "printf '%s' {} | {}",
+        shell_quote(&String::from_utf8_lossy(&input[..length])),
+        command.join(" ")
+    )
+}
+
+// Runs every program of the corpus through both interpreters and returns
+// a report of each mismatch, with a command line reproducing it.
+fn compare_corpus(corpus: &Path, semantics: &Semantics, reference: &Reference) -> Vec<String> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(corpus)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "b"))
+        .collect();
+    programs.sort();
+    let mut mismatches = Vec::new();
+    for program in programs {
+        let name = program.file_stem().unwrap().to_string_lossy().into_owned();
+        let code = fs::read(&program).unwrap();
+        let input = fs::read(program.with_extension("in")).unwrap_or_default();
+        let own = run_own(&code, &input, semantics);
+        let reference_outcome = run_reference(reference, &program, &input);
+        if own != reference_outcome {
+            let repro = minimize(&name, &code, &input, semantics, reference);
+            mismatches.push(format!(
+                "{name}: expected {:?} (success: {}), the reference gave {:?} (success: {})\n  \
+                 repro: {repro}",
+                own.output.escape_ascii().to_string(),
+                own.success,
+                reference_outcome.output.escape_ascii().to_string(),
+                reference_outcome.success,
+            ));
+        }
+    }
+    mismatches
+}
+
+fn corpus() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/differential")
+}
+
+// The binter executable as a reference, run with the default semantics of
+// the command line interface.
+fn binter_reference(program: &str, args: &[&str]) -> Reference {
+    let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    args.extend(["--size".to_string(), "30000".to_string()]);
+    Reference {
+        program: program.to_string(),
+        args,
+    }
+}
+
+const CLI_SEMANTICS: Semantics = Semantics {
+    eof_policy: EofPolicy::Unchanged,
+    tape_size: 30000,
+};
+
+#[test]
+fn test_harness_accepts_matching_reference() {
+    let reference = binter_reference(env!("CARGO_BIN_EXE_binter"), &[]);
+    let mismatches = compare_corpus(&corpus(), &CLI_SEMANTICS, &reference);
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn test_harness_flags_divergent_reference() {
+    let script = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/fake_reference.sh");
+    let reference = binter_reference(
+        "sh",
+        &[&script.to_string_lossy(), env!("CARGO_BIN_EXE_binter")],
+    );
+    let mismatches = compare_corpus(&corpus(), &CLI_SEMANTICS, &reference);
+    // the script only changes lowercase letters, which are printed by two
+    // of the programs
+    assert_eq!(mismatches.len(), 2, "{}", mismatches.join("\n"));
+    // the input is cut down to the first byte that makes a difference
+    assert!(mismatches[0].starts_with("echo: expected \"echo\""));
+    assert!(mismatches[0].contains("repro: printf '%s' 'e' | 'sh' "));
+    assert!(mismatches[1].starts_with("hello: expected \"hi\\\\n\""));
+    assert!(mismatches[1].contains("the reference gave \"HI\\\\n\""));
+}
+
+#[test]
+#[ignore = "needs a reference interpreter, set with BF_REFERENCE"]
+fn test_against_reference() {
+    let command = std::env::var("BF_REFERENCE").expect("BF_REFERENCE is not set");
+    let mut words = command.split_whitespace().map(String::from);
+    let reference = Reference {
+        program: words.next().expect("BF_REFERENCE is empty"),
+        args: words.collect(),
+    };
+    let eof_policy = match std::env::var("BF_EOF").as_deref() {
+        Ok("zero") => EofPolicy::Zero,
+        Ok("max") => EofPolicy::Max,
+        Ok("unchanged") | Err(_) => EofPolicy::Unchanged,
+        Ok(other) => panic!("unknown EOF mode: {other}"),
+    };
+    let tape_size = std::env::var("BF_TAPE_SIZE").map_or(30000, |size| size.parse().unwrap());
+    let corpus = std::env::var("BF_CORPUS").map_or_else(|_| corpus(), PathBuf::from);
+    let semantics = Semantics {
+        eof_policy,
+        tape_size,
+    };
+    let mismatches = compare_corpus(&corpus, &semantics, &reference);
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
@@ -0,0 +1,108 @@
+use binter::testing::CapturedOutput;
+use binter::{Interpreter, OptimizerConfig, Termination};
+
+// Everything that can be observed after a run: whether it completed, failed
+// or was stopped, the output, the tape and the pointer.
+#[derive(PartialEq, Debug)]
+struct Outcome {
+    result: std::result::Result<Termination, String>,
+    output: Vec<u8>,
+    tape: Vec<u8>,
+    pointer: usize,
+}
+
+fn run(code: &str, input: &[u8], start: usize, level: Option<u32>, optimize_io: bool) -> Outcome {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(code.as_bytes(), 64)
+        .with_input(Box::new(std::io::Cursor::new(input.to_vec())))
+        .with_output(Box::new(output.clone()))
+        .with_step_limit(100_000)
+        .with_optimizer_config(OptimizerConfig {
+            optimize_io,
+            ..Default::default()
+        });
+    interpreter.machine_mut().move_right(start);
+    let result = match level {
+        None => interpreter.run(),
+        Some(max_iterations) => interpreter.run_with_optimization(max_iterations),
+    };
+    let tape = interpreter.get_tape();
+    let (machine, _) = interpreter.into_parts().unwrap();
+    Outcome {
+        result: result.map_err(|error| error.to_string()),
+        output: output.take(),
+        tape,
+        pointer: machine.index(),
+    }
+}
+
+// Compares the unoptimized run with the runs at every optimization level,
+// starting both at the first cell and further on the tape. Runs stopped by
+// the step limit are skipped, as the optimized code takes fewer steps. A
+// failed run has to fail when optimized as well, after producing the same
+// output.
+fn check_equivalence(code: &str, input: &[u8]) -> std::result::Result<(), String> {
+    for start in [0, 8] {
+        check_equivalence_from(code, input, start)?;
+    }
+    Ok(())
+}
+
+fn check_equivalence_from(
+    code: &str,
+    input: &[u8],
+    start: usize,
+) -> std::result::Result<(), String> {
+    let expected = run(code, input, start, None, true);
+    for level in [1, 2, 0] {
+        for optimize_io in [true, false] {
+            let actual = run(code, input, start, Some(level), optimize_io);
+            let equivalent = match (&expected.result, &actual.result) {
+                (Ok(Termination::StepLimit), _) | (_, Ok(Termination::StepLimit)) => true,
+                (Ok(Termination::Completed), Ok(Termination::Completed)) => actual == expected,
+                (Err(_), Err(_)) => actual.output == expected.output,
+                _ => false,
+            };
+            if !equivalent {
+                return Err(format!(
+                    "start {start}, level {level}, optimize_io {optimize_io}:\n\
+                     expected {expected:?}\nactual {actual:?}"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_equivalence_corpus() {
+    let corpus = include_str!("corpus/equivalence.txt");
+    for line in corpus.lines().filter(|line| !line.starts_with('#')) {
+        let (code, input) = line.split_once('!').unwrap();
+        if let Err(message) = check_equivalence(code, input.as_bytes()) {
+            panic!("code {code:?}, input {input:?}: {message}");
+        }
+    }
+}
+
+#[cfg(feature = "proptest")]
+mod properties {
+    use binter::strategies;
+    use proptest::prelude::*;
+
+    use super::check_equivalence;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(500))]
+
+        #[test]
+        fn test_optimization_keeps_behavior(
+            code in strategies::code(40, 3),
+            input in strategies::input(8),
+        ) {
+            if let Err(message) = check_equivalence(&code, &input) {
+                prop_assert!(false, "code {:?}, input {:?}: {}", code, input, message);
+            }
+        }
+    }
+}
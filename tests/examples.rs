@@ -0,0 +1,44 @@
+use std::process::Command;
+
+// Builds and runs an example, comparing its output with the golden file.
+fn check_example(name: &str, expected: &str) {
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--example", name])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{name}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        expected,
+        "{name}"
+    );
+}
+
+#[test]
+fn test_example_run_from_string() {
+    let expected = include_str!("golden/example_run_from_string.txt");
+    check_example("run_from_string", expected);
+}
+
+#[test]
+fn test_example_custom_io() {
+    let expected = include_str!("golden/example_custom_io.txt");
+    check_example("custom_io", expected);
+}
+
+#[test]
+fn test_example_optimize_and_dump_ir() {
+    let expected = include_str!("golden/example_optimize_and_dump_ir.txt");
+    check_example("optimize_and_dump_ir", expected);
+}
+
+#[test]
+fn test_example_step_debugger() {
+    let expected = include_str!("golden/example_step_debugger.txt");
+    check_example("step_debugger", expected);
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::PathBuf;
+
+use binter::testing::CapturedOutput;
+use binter::{EofPolicy, Interpreter, Termination};
+
+fn program_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+}
+
+fn run_program(name: &str, eof_policy: EofPolicy, optimize: bool) -> Vec<u8> {
+    // the input is optional, the programs that don't read get none
+    let input = fs::read(program_file(&format!("{name}.in"))).unwrap_or_default();
+    let output = CapturedOutput::default();
+    let path = program_file(&format!("{name}.b"));
+    let mut interpreter = Interpreter::from_file(path.to_str().unwrap(), 30000)
+        .unwrap()
+        .with_input(Box::new(std::io::Cursor::new(input)))
+        .with_eof_policy(eof_policy)
+        .with_output(Box::new(output.clone()));
+    let termination = match optimize {
+        false => interpreter.run().unwrap(),
+        true => interpreter.run_with_optimization(0).unwrap(),
+    };
+    assert_eq!(termination, Termination::Completed);
+    output.take()
+}
+
+// Runs a program without and with full optimization, comparing both outputs
+// with the golden file.
+fn test_program(name: &str, eof_policy: EofPolicy) {
+    let expected = fs::read(program_file(&format!("{name}.out"))).unwrap();
+    let unoptimized = run_program(name, eof_policy, false);
+    let optimized = run_program(name, eof_policy, true);
+    assert_eq!(
+        unoptimized, optimized,
+        "{name}: optimization changed the output"
+    );
+    assert_eq!(
+        unoptimized, expected,
+        "{name}: output differs from the golden file"
+    );
+}
+
+#[test]
+fn test_program_hello() {
+    test_program("hello", EofPolicy::Unchanged);
+}
+
+#[test]
+fn test_program_rot13() {
+    // stops at the end of the input only if the cell is left unchanged
+    test_program("rot13", EofPolicy::Unchanged);
+}
+
+#[test]
+fn test_program_cat() {
+    test_program("cat", EofPolicy::Zero);
+}
+
+#[test]
+fn test_program_counter() {
+    test_program("counter", EofPolicy::Unchanged);
+}
+
+#[test]
+fn test_program_sierpinski() {
+    test_program("sierpinski", EofPolicy::Unchanged);
+}
@@ -0,0 +1,47 @@
+// Uses nothing but the public testing API, the way a downstream crate would.
+#![cfg(feature = "testing")]
+
+use binter::assert_bf_output;
+use binter::testing::{interpreter, run_program, CapturedOutput, ScriptedInput};
+use binter::{Interpreter, Program, Termination};
+
+// Prints the sum of two digits, given as ASCII, e.g. "34" gives "7".
+const ADD_DIGITS: &str = ",>,[<+>-]<------------------------------------------------.";
+
+#[test]
+fn test_add_digits() {
+    assert_bf_output!(ADD_DIGITS, "34", "7");
+    assert_bf_output!(ADD_DIGITS, "00", "0");
+}
+
+#[test]
+fn test_add_digits_run() {
+    let (termination, output) = run_program(ADD_DIGITS.as_bytes(), b"45").unwrap();
+    assert_eq!(termination, Termination::Completed);
+    assert_eq!(output, b"9");
+}
+
+#[test]
+fn test_add_digits_with_interpreter() {
+    let output = CapturedOutput::default();
+    let mut interpreter = Interpreter::from_reader(ADD_DIGITS.as_bytes(), 2)
+        .with_input(Box::new(ScriptedInput::new("27")))
+        .with_output(Box::new(output.clone()));
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"9");
+}
+
+#[test]
+fn test_add_digits_parsed() {
+    let program: Program = ADD_DIGITS.parse().unwrap();
+    let output = CapturedOutput::default();
+    let mut interpreter = interpreter(&program, 2, ScriptedInput::new("18"), output.clone());
+    assert_eq!(interpreter.run().unwrap(), Termination::Completed);
+    assert_eq!(output.contents(), b"9");
+}
+
+#[test]
+#[should_panic(expected = "the outputs differ at byte 0")]
+fn test_add_digits_wrong_output() {
+    assert_bf_output!(ADD_DIGITS, "34", "8");
+}